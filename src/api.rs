@@ -0,0 +1,274 @@
+// Services other plugins can use. Plugins depend on this crate with
+// default-features = false, which builds only this module, and look up the
+// versioned entry point of the loaded plugin:
+//
+//     let lib = libloading::Library::new(path_to_servercore)?;
+//     let economy = lib.get::<fn() -> Arc<dyn Economy>>(b"servercore_economy_v1")?();
+//
+// The entry point hands out a Rust trait object, whose layout is only the
+// same between binaries built by the same compiler version against the same
+// Pumpkin revision. Both plugins must be built with the same toolchain,
+// as Pumpkin already requires of its plugins.
+//
+// Breaking changes get a new symbol, so a plugin built against an older
+// version fails to look up the service instead of misbehaving.
+
+use std::{any::Any, fmt, sync::Arc};
+
+use async_trait::async_trait;
+#[cfg(feature = "plugin")]
+use pumpkin::PLUGIN_MANAGER;
+use pumpkin::{
+    entity::player::Player,
+    plugin::{Cancellable, Payload},
+};
+
+#[cfg(feature = "plugin")]
+use crate::economy;
+
+pub const ECONOMY_API_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EconomyError {
+    // Amounts must be finite and above zero
+    InvalidAmount,
+    InsufficientFunds,
+    PlayerNotFound,
+    SamePlayer,
+    UnknownCurrency(String),
+    // A plugin cancelled the change
+    Cancelled,
+    Database(String),
+    // The configured tax account has never joined
+    TaxAccountNotFound,
+}
+
+impl fmt::Display for EconomyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EconomyError::InvalidAmount => write!(f, "The amount must be a positive number."),
+            EconomyError::InsufficientFunds => write!(f, "Insufficient funds."),
+            EconomyError::PlayerNotFound => write!(f, "That player has never joined."),
+            EconomyError::SamePlayer => write!(f, "You can not pay yourself."),
+            EconomyError::UnknownCurrency(name) => write!(f, "Unknown currency: {}.", name),
+            EconomyError::Cancelled => write!(f, "The transaction was cancelled."),
+            EconomyError::Database(e) => write!(f, "Database error: {}", e),
+            EconomyError::TaxAccountNotFound => write!(f, "The tax account does not exist."),
+        }
+    }
+}
+
+impl std::error::Error for EconomyError {}
+
+// Players are identified by their hyphenated UUID. All changes follow the
+// same rules as /pay: amounts are positive, balances never go below zero
+// and offline players can be used too. Balances are in the default currency.
+#[async_trait]
+pub trait Economy: Send + Sync {
+    fn version(&self) -> u32 {
+        ECONOMY_API_VERSION
+    }
+
+    // An amount with the currency symbol, as shown to players
+    async fn format(&self, amount: f64) -> String;
+
+    async fn balance(&self, player_uuid: &str) -> Result<f64, EconomyError>;
+
+    async fn has(&self, player_uuid: &str, amount: f64) -> Result<bool, EconomyError> {
+        Ok(self.balance(player_uuid).await? >= amount)
+    }
+
+    // Returns the new balance
+    async fn deposit(&self, player_uuid: &str, amount: f64) -> Result<f64, EconomyError>;
+
+    // Returns the new balance
+    async fn withdraw(&self, player_uuid: &str, amount: f64) -> Result<f64, EconomyError>;
+
+    async fn transfer(
+        &self,
+        from_uuid: &str,
+        to_uuid: &str,
+        amount: f64,
+    ) -> Result<(), EconomyError>;
+}
+
+#[cfg(feature = "plugin")]
+struct ServercoreEconomy;
+
+#[cfg(feature = "plugin")]
+#[async_trait]
+impl Economy for ServercoreEconomy {
+    async fn format(&self, amount: f64) -> String {
+        economy::default_currency().await.format(amount)
+    }
+
+    async fn balance(&self, player_uuid: &str) -> Result<f64, EconomyError> {
+        economy::balance(player_uuid, &economy::default_currency().await.name).await
+    }
+
+    async fn deposit(&self, player_uuid: &str, amount: f64) -> Result<f64, EconomyError> {
+        economy::deposit(player_uuid, &economy::default_currency().await.name, amount).await
+    }
+
+    async fn withdraw(&self, player_uuid: &str, amount: f64) -> Result<f64, EconomyError> {
+        economy::withdraw(player_uuid, &economy::default_currency().await.name, amount).await
+    }
+
+    async fn transfer(
+        &self,
+        from_uuid: &str,
+        to_uuid: &str,
+        amount: f64,
+    ) -> Result<(), EconomyError> {
+        let currency = economy::default_currency().await;
+        economy::transfer(from_uuid, to_uuid, &currency.name, amount).await
+    }
+}
+
+// Entry point looked up by other plugins
+#[cfg(feature = "plugin")]
+#[no_mangle]
+pub fn servercore_economy_v1() -> Arc<dyn Economy> {
+    Arc::new(ServercoreEconomy)
+}
+
+// Pre-events are fired before a change and can be cancelled or adjusted by
+// listeners, post-events are fired after it happened and only observed.
+// Events are fired outside of the economy lock, so listeners may use the
+// Economy service themselves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventPhase {
+    Pre,
+    Post,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BalanceChangeCause {
+    Deposit,
+    Withdraw,
+    Set,
+}
+
+// A deposit, withdrawal or balance set through the economy. Payments fire a
+// PaymentEvent instead.
+#[derive(Clone, Debug)]
+pub struct BalanceChangeEvent {
+    pub phase: EventPhase,
+    pub player_uuid: String,
+    // Name of the currency the balance is in
+    pub currency: String,
+    pub cause: BalanceChangeCause,
+    // Amount deposited or withdrawn, or the balance being set. Listeners of
+    // the pre-event may change it.
+    pub amount: f64,
+    // Balance after the change, only known to the post-event
+    pub balance: Option<f64>,
+    cancelled: bool,
+}
+
+impl BalanceChangeEvent {
+    pub fn new(player_uuid: &str, currency: &str, cause: BalanceChangeCause, amount: f64) -> Self {
+        BalanceChangeEvent {
+            phase: EventPhase::Pre,
+            player_uuid: player_uuid.to_string(),
+            currency: currency.to_string(),
+            cause,
+            amount,
+            balance: None,
+            cancelled: false,
+        }
+    }
+}
+
+// Money moving from one player to another, e.g. through /pay
+#[derive(Clone, Debug)]
+pub struct PaymentEvent {
+    pub phase: EventPhase,
+    pub from_uuid: String,
+    pub to_uuid: String,
+    pub currency: String,
+    // Listeners of the pre-event may change the amount and the tax
+    pub amount: f64,
+    // Part of the amount the recipient does not get
+    pub tax: f64,
+    cancelled: bool,
+}
+
+impl PaymentEvent {
+    pub fn new(from_uuid: &str, to_uuid: &str, currency: &str, amount: f64) -> Self {
+        PaymentEvent {
+            phase: EventPhase::Pre,
+            from_uuid: from_uuid.to_string(),
+            to_uuid: to_uuid.to_string(),
+            currency: currency.to_string(),
+            amount,
+            tax: 0.0,
+            cancelled: false,
+        }
+    }
+}
+
+// A player joining for the first time. Cancelling the pre-event refuses
+// the join before the player is stored.
+#[derive(Clone)]
+pub struct PlayerFirstJoinEvent {
+    pub phase: EventPhase,
+    pub player: Arc<Player>,
+    // Listeners of the pre-event may change the starting balance, other
+    // currencies start with their configured starting balance
+    pub starting_balance: f64,
+    cancelled: bool,
+}
+
+impl PlayerFirstJoinEvent {
+    pub fn new(player: Arc<Player>, starting_balance: f64) -> Self {
+        PlayerFirstJoinEvent {
+            phase: EventPhase::Pre,
+            player,
+            starting_balance,
+            cancelled: false,
+        }
+    }
+}
+
+macro_rules! impl_event {
+    ($event:ty, $name:literal) => {
+        impl Payload for $event {
+            fn get_name_static() -> &'static str {
+                $name
+            }
+
+            fn get_name(&self) -> &'static str {
+                $name
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+
+        impl Cancellable for $event {
+            fn cancelled(&self) -> bool {
+                self.cancelled
+            }
+
+            fn set_cancelled(&mut self, cancelled: bool) {
+                self.cancelled = cancelled;
+            }
+        }
+    };
+}
+
+impl_event!(BalanceChangeEvent, "servercore:balance_change");
+impl_event!(PaymentEvent, "servercore:payment");
+impl_event!(PlayerFirstJoinEvent, "servercore:player_first_join");
+
+// Passes an event through the handlers of all plugins
+#[cfg(feature = "plugin")]
+pub(crate) async fn fire<E: Payload + Send + Sync + 'static>(event: E) -> E {
+    PLUGIN_MANAGER.fire(event).await
+}
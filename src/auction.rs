@@ -0,0 +1,1031 @@
+// Auction house. Listings and the mailbox live in the database, every change
+// of a listing is a compare-and-set on its state, and items and money change
+// hands by moving rows within one transaction together with the balance
+// change. Items only leave or enter an inventory on disk when the player data
+// is saved, so a stack on its way carries the id of its listing or mailbox
+// row and the player is saved before the transaction commits. A crash in
+// between is settled when the player joins again: tagged stacks whose row
+// was committed belong to the auction house, the others stay.
+
+use std::{fmt, io::Cursor, sync::Arc, time::Duration};
+
+use pumpkin::{entity::player::Player, server::Server};
+use pumpkin_nbt::{compound::NbtCompound, Nbt};
+use pumpkin_util::text::TextComponent;
+use pumpkin_world::item::ItemStack;
+
+use crate::{
+    api::BalanceChangeCause,
+    config::get_config,
+    db::get_db,
+    economy::{self, EconomyError},
+    shop::inventory,
+    utils::{current_sec, neutral_colour, parse_duration},
+};
+
+pub const PAGE_SIZE: i64 = 10;
+const DEFAULT_DURATION: i64 = 2 * 86400;
+
+// Custom data keys of stacks on their way, holding the row id
+const LISTING_TAG: &str = "servercore:listing";
+const MAILBOX_TAG: &str = "servercore:mailbox";
+const CUSTOM_DATA: &str = "minecraft:custom_data";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuctionError {
+    Economy(EconomyError),
+    NotFound,
+    NotHolding,
+    // Amount of active listings allowed
+    LimitReached(i64),
+    OwnListing,
+    // Bids on fixed price listings or buying auctions
+    NotAuction,
+    NotFixedPrice,
+    // Lowest bid allowed, formatted
+    BidTooLow(String),
+    AlreadyTopBidder,
+    // Another bid came in first
+    Outbid,
+    HasBids,
+    NotSeller,
+    InventoryFull,
+    MailboxEmpty,
+    // The inventory could not be written to disk, nothing was moved
+    SaveFailed,
+}
+
+impl fmt::Display for AuctionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuctionError::Economy(e) => write!(f, "{}", e),
+            AuctionError::NotFound => write!(f, "That listing does not exist or has ended."),
+            AuctionError::NotHolding => write!(f, "Hold the item you want to list."),
+            AuctionError::LimitReached(limit) => {
+                write!(f, "You can not have more than {} active listings.", limit)
+            }
+            AuctionError::OwnListing => write!(f, "You can not buy or bid on your own listing."),
+            AuctionError::NotAuction => write!(f, "That listing has a fixed price, use buy."),
+            AuctionError::NotFixedPrice => write!(f, "That listing is an auction, use bid."),
+            AuctionError::BidTooLow(min) => write!(f, "Your bid has to be at least {}.", min),
+            AuctionError::AlreadyTopBidder => write!(f, "You already have the highest bid."),
+            AuctionError::Outbid => write!(f, "Someone else bid first, try again."),
+            AuctionError::HasBids => write!(f, "Listings with bids can not be cancelled."),
+            AuctionError::NotSeller => write!(f, "That is not your listing."),
+            AuctionError::InventoryFull => write!(f, "Your inventory is full."),
+            AuctionError::MailboxEmpty => write!(f, "Your mailbox is empty."),
+            AuctionError::SaveFailed => {
+                write!(f, "Your inventory could not be saved, try again later.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AuctionError {}
+
+impl From<EconomyError> for AuctionError {
+    fn from(e: EconomyError) -> Self {
+        AuctionError::Economy(e)
+    }
+}
+
+impl From<sqlx::Error> for AuctionError {
+    fn from(e: sqlx::Error) -> Self {
+        AuctionError::Economy(e.into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListingKind {
+    // Bought at once for the price
+    Fixed(f64),
+    // Sold to the highest bidder when it expires, bids start at the price
+    Auction(f64),
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Listing {
+    pub id: i64,
+    pub seller: String,
+    pub seller_name: Option<String>,
+    pub item_key: String,
+    pub quantity: i64,
+    pub price: Option<f64>,
+    pub start_bid: Option<f64>,
+    pub top_bid: Option<f64>,
+    pub top_bidder: Option<String>,
+    pub expires_at: i64,
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+struct MailboxRow {
+    id: i64,
+    item: Option<String>,
+    amount: f64,
+}
+
+// What became of a mailbox row
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ClaimedRow {
+    Claimed,
+    // Claimed elsewhere in the meantime
+    Gone,
+    // The item did not fit
+    NoRoom,
+    // The item could not be read, the row stays for an administrator
+    Unreadable,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Claimed {
+    pub items: usize,
+    pub amount: f64,
+    // Items were left in the mailbox for lack of room
+    pub full: bool,
+}
+
+// Items are stored with their components as hex encoded NBT
+fn encode(stack: &ItemStack) -> String {
+    let mut compound = NbtCompound::new();
+    stack.write_item_stack(&mut compound);
+    hex::encode(Nbt::new(String::new(), compound).write())
+}
+
+fn decode(data: &str) -> Option<ItemStack> {
+    let bytes = hex::decode(data).ok()?;
+    let nbt = Nbt::read(&mut Cursor::new(bytes)).ok()?;
+    ItemStack::read_item_stack(&nbt.root_tag)
+}
+
+// Custom data of a stack, empty if it has none
+fn custom_data(stack: &ItemStack) -> NbtCompound {
+    let mut compound = NbtCompound::new();
+    stack.write_item_stack(&mut compound);
+    compound
+        .get_compound("components")
+        .and_then(|c| c.get_compound(CUSTOM_DATA))
+        .cloned()
+        .unwrap_or_else(NbtCompound::new)
+}
+
+// Rebuilds a stack with changed custom data, custom data left empty is
+// removed so the stack matches untouched ones again
+fn with_custom_data(stack: &ItemStack, change: impl FnOnce(&mut NbtCompound)) -> Option<ItemStack> {
+    let mut compound = NbtCompound::new();
+    stack.write_item_stack(&mut compound);
+
+    let mut components = compound
+        .get_compound("components")
+        .cloned()
+        .unwrap_or_else(NbtCompound::new);
+    let mut custom = components
+        .get_compound(CUSTOM_DATA)
+        .cloned()
+        .unwrap_or_else(NbtCompound::new);
+    change(&mut custom);
+
+    components
+        .child_tags
+        .retain(|(name, _)| name != CUSTOM_DATA);
+    if !custom.child_tags.is_empty() {
+        components.put_component(CUSTOM_DATA, custom);
+    }
+    compound.child_tags.retain(|(name, _)| name != "components");
+    if !components.child_tags.is_empty() {
+        compound.put_component("components", components);
+    }
+
+    ItemStack::read_item_stack(&compound)
+}
+
+// Row id a stack on its way carries under the key
+fn tag(stack: &ItemStack, key: &str) -> Option<i64> {
+    custom_data(stack).get_long(key)
+}
+
+fn with_tag(stack: &ItemStack, key: &str, id: i64) -> Option<ItemStack> {
+    with_custom_data(stack, |custom| custom.put_long(key, id))
+}
+
+// The stack without the keys of stacks on their way, None if it had none
+fn without_tags(stack: &ItemStack) -> Option<ItemStack> {
+    if tag(stack, LISTING_TAG).is_none() && tag(stack, MAILBOX_TAG).is_none() {
+        return None;
+    }
+
+    with_custom_data(stack, |custom| {
+        custom
+            .child_tags
+            .retain(|(name, _)| name != LISTING_TAG && name != MAILBOX_TAG)
+    })
+}
+
+pub async fn get_listing(id: i64) -> Result<Listing, AuctionError> {
+    let db = get_db().await;
+    sqlx::query_as::<_, Listing>(db.sql(
+        "SELECT l.id, l.seller, p.nickname AS seller_name, l.item_key, l.quantity, l.price,
+                l.start_bid, l.top_bid, l.top_bidder, l.expires_at
+            FROM auction_listings l LEFT JOIN players p ON p.uuid = l.seller
+            WHERE l.id = $1 AND l.state = 'active' AND l.expires_at > $2",
+    ))
+    .bind(id)
+    .bind(current_sec())
+    .fetch_optional(&db.pool)
+    .await?
+    .ok_or(AuctionError::NotFound)
+}
+
+// A page of active listings ending soonest first, and the amount of them
+pub async fn get_listings(page: i64) -> Result<(Vec<Listing>, i64), sqlx::Error> {
+    let db = get_db().await;
+    let now = current_sec();
+
+    let total = sqlx::query_scalar::<_, i64>(
+        db.sql("SELECT COUNT(*) FROM auction_listings WHERE state = 'active' AND expires_at > $1"),
+    )
+    .bind(now)
+    .fetch_one(&db.pool)
+    .await?;
+
+    let listings = sqlx::query_as::<_, Listing>(db.sql(
+        "SELECT l.id, l.seller, p.nickname AS seller_name, l.item_key, l.quantity, l.price,
+                l.start_bid, l.top_bid, l.top_bidder, l.expires_at
+            FROM auction_listings l LEFT JOIN players p ON p.uuid = l.seller
+            WHERE l.state = 'active' AND l.expires_at > $1
+            ORDER BY l.expires_at, l.id LIMIT $2 OFFSET $3",
+    ))
+    .bind(now)
+    .bind(PAGE_SIZE)
+    .bind((page.max(1) - 1) * PAGE_SIZE)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok((listings, total))
+}
+
+// Lowest bid a listing accepts
+pub async fn minimum_bid(listing: &Listing) -> f64 {
+    let currency = economy::default_currency().await;
+    let Some(top) = listing.top_bid else {
+        return listing.start_bid.unwrap_or(0.0);
+    };
+
+    // At least the smallest amount of the currency above the highest bid
+    let step = 1.0 / 10f64.powi(currency.decimals as i32);
+    let increment = get_config().await.value.ah_bid_increment.max(0.0);
+    currency.round((top * (1.0 + increment / 100.0)).max(top + step))
+}
+
+async fn mail_item(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    listing_id: i64,
+    player_uuid: &str,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query(db.sql(
+        "INSERT INTO auction_mailbox (uuid, item, item_key, quantity, amount, reason, created_at)
+            SELECT $1, item, item_key, quantity, 0, $2, $3 FROM auction_listings WHERE id = $4",
+    ))
+    .bind(player_uuid)
+    .bind(reason)
+    .bind(current_sec())
+    .bind(listing_id)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn mail_money(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    player_uuid: &str,
+    amount: f64,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query(db.sql(
+        "INSERT INTO auction_mailbox (uuid, quantity, amount, reason, created_at)
+            VALUES ($1, 0, $2, $3, $4)",
+    ))
+    .bind(player_uuid)
+    .bind(amount)
+    .bind(reason)
+    .bind(current_sec())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Writes the player's data now instead of at the next autosave, so the
+// inventory on disk matches what is committed
+async fn save_player(server: &Server, player: &Player) -> Result<(), AuctionError> {
+    if let Err(e) = server
+        .player_data_storage
+        .extract_data_and_save_player(player)
+        .await
+    {
+        log::error!(
+            "Failed to save the player data of {}: {}",
+            player.gameprofile.id,
+            e
+        );
+        return Err(AuctionError::SaveFailed);
+    }
+
+    Ok(())
+}
+
+// Adds a listing that becomes active once its stack left the inventory
+async fn insert_listing(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    seller_uuid: &str,
+    stack: &ItemStack,
+    kind: ListingKind,
+) -> Result<i64, sqlx::Error> {
+    let db = get_db().await;
+    let now = current_sec();
+    let duration = parse_duration(&get_config().await.value.ah_duration)
+        .filter(|d| *d > 0)
+        .unwrap_or(DEFAULT_DURATION);
+    let (price, start_bid) = match kind {
+        ListingKind::Fixed(price) => (Some(price), None),
+        ListingKind::Auction(start) => (None, Some(start)),
+    };
+
+    sqlx::query(db.sql(
+        "INSERT INTO auction_listings
+            (seller, item, item_key, quantity, price, start_bid, state, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, 'pending', $7, $8)",
+    ))
+    .bind(seller_uuid)
+    .bind(encode(stack))
+    .bind(stack.item.registry_key)
+    .bind(stack.item_count as i64)
+    .bind(price)
+    .bind(start_bid)
+    .bind(now)
+    .bind(now + duration)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query_scalar::<_, i64>(
+        db.sql("SELECT id FROM auction_listings WHERE seller = $1 ORDER BY id DESC LIMIT 1"),
+    )
+    .bind(seller_uuid)
+    .fetch_one(&mut **tx)
+    .await
+}
+
+// Lists the stack in the player's hand, returns the listing id and the fee
+// paid
+pub async fn list(
+    server: &Server,
+    player: &Arc<Player>,
+    kind: ListingKind,
+) -> Result<(i64, f64), AuctionError> {
+    let currency = economy::default_currency().await;
+    let seller_uuid = player.gameprofile.id.to_string();
+    let kind = match kind {
+        ListingKind::Fixed(price) => ListingKind::Fixed(currency.round(price)),
+        ListingKind::Auction(start) => ListingKind::Auction(currency.round(start)),
+    };
+    let (ListingKind::Fixed(price) | ListingKind::Auction(price)) = kind;
+    economy::check_amount(price)?;
+
+    let config = get_config().await;
+    let limit = config.value.ah_listing_limit;
+    if limit > 0 {
+        let db = get_db().await;
+        let active = sqlx::query_scalar::<_, i64>(db.sql(
+            "SELECT COUNT(*) FROM auction_listings
+                WHERE seller = $1 AND state IN ('active', 'pending')",
+        ))
+        .bind(&seller_uuid)
+        .fetch_one(&db.pool)
+        .await?;
+        if active >= limit {
+            return Err(AuctionError::LimitReached(limit));
+        }
+    }
+
+    let fee = currency.round(
+        config.value.ah_listing_fee.max(0.0)
+            + price * config.value.ah_listing_fee_rate.max(0.0) / 100.0,
+    );
+    let fee = if fee > 0.0 {
+        economy::before_change(&seller_uuid, &currency, BalanceChangeCause::Withdraw, fee).await?
+    } else {
+        0.0
+    };
+
+    let (id, balance) = {
+        let _guard = economy::lock().await;
+
+        let Some(stack) = inventory::held_stack(player).await else {
+            return Err(AuctionError::NotHolding);
+        };
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let change = if fee > 0.0 {
+            Some(economy::apply_in(&mut tx, &seller_uuid, &currency.name, -fee).await?)
+        } else {
+            None
+        };
+        let id = insert_listing(&mut tx, &seller_uuid, &stack, kind).await?;
+
+        // The stack is marked and saved before the listing commits, the
+        // held stack may have changed since it was read
+        let listed = encode(&stack);
+        let is_listed = |s: &ItemStack| tag(s, LISTING_TAG) == Some(id);
+        if inventory::replace_held_stack(player, |held| {
+            if encode(held) != listed {
+                return None;
+            }
+            with_tag(held, LISTING_TAG, id)
+        })
+        .await
+        .is_none()
+        {
+            return Err(AuctionError::NotHolding);
+        }
+        let res = match save_player(server, player).await {
+            Ok(()) => tx.commit().await.map_err(AuctionError::from),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            inventory::replace_stacks(player, |s| {
+                if !is_listed(s) {
+                    return None;
+                }
+                without_tags(s)
+            })
+            .await;
+            return Err(e);
+        }
+        let balance = match change {
+            Some(change) => Some(change.committed().await?),
+            None => None,
+        };
+
+        let taken =
+            inventory::replace_stacks(player, |s| is_listed(s).then(|| ItemStack::EMPTY.clone()))
+                .await;
+        if taken == 0 {
+            // Moved out of the inventory in the meantime, e.g. dropped
+            abandon_listing(id, &seller_uuid, &currency.name, fee).await?;
+            return Err(AuctionError::NotHolding);
+        }
+        // A failed save is settled on the next join
+        let _ = save_player(server, player).await;
+        activate_listing(id).await?;
+
+        (id, balance)
+    };
+
+    if let Some(balance) = balance {
+        economy::after_change(
+            &seller_uuid,
+            &currency,
+            BalanceChangeCause::Withdraw,
+            fee,
+            balance,
+        )
+        .await;
+    }
+
+    Ok((id, fee))
+}
+
+// Makes a pending listing active once its stack left the inventory
+async fn activate_listing(id: i64) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query(
+        db.sql("UPDATE auction_listings SET state = 'active' WHERE id = $1 AND state = 'pending'"),
+    )
+    .bind(id)
+    .execute(&db.pool)
+    .await?;
+
+    Ok(())
+}
+
+// Removes a pending listing whose stack never left the inventory and gives
+// the fee back. Callers must hold the lock.
+async fn abandon_listing(
+    id: i64,
+    seller_uuid: &str,
+    currency: &str,
+    fee: f64,
+) -> Result<(), AuctionError> {
+    let db = get_db().await;
+    let mut tx = db.pool.begin().await?;
+    sqlx::query(db.sql("DELETE FROM auction_listings WHERE id = $1 AND state = 'pending'"))
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    let change = if fee > 0.0 {
+        Some(economy::apply_in(&mut tx, seller_uuid, currency, fee).await?)
+    } else {
+        None
+    };
+    tx.commit().await?;
+
+    if let Some(change) = change {
+        change.committed().await?;
+    }
+    Ok(())
+}
+
+// Marks a listing sold, sends the item to the buyer and the money to the
+// seller. False if it was no longer active.
+async fn settle_purchase(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    listing: &Listing,
+    buyer_uuid: &str,
+    price: f64,
+) -> Result<bool, sqlx::Error> {
+    let db = get_db().await;
+    let now = current_sec();
+
+    let res = sqlx::query(db.sql(
+        "UPDATE auction_listings SET state = 'sold', buyer = $1, closed_at = $2
+            WHERE id = $3 AND state = 'active' AND expires_at > $4",
+    ))
+    .bind(buyer_uuid)
+    .bind(now)
+    .bind(listing.id)
+    .bind(now)
+    .execute(&mut **tx)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    mail_item(tx, listing.id, buyer_uuid, "purchase").await?;
+    mail_money(tx, &listing.seller, price, "sale").await?;
+
+    Ok(true)
+}
+
+// Buys a fixed price listing, the item is sent to the buyer's mailbox
+pub async fn buy(player: &Arc<Player>, id: i64) -> Result<Listing, AuctionError> {
+    let currency = economy::default_currency().await;
+    let buyer_uuid = player.gameprofile.id.to_string();
+    let listing = get_listing(id).await?;
+    let price = listing.price.ok_or(AuctionError::NotFixedPrice)?;
+    if listing.seller == buyer_uuid {
+        return Err(AuctionError::OwnListing);
+    }
+
+    let price =
+        economy::before_change(&buyer_uuid, &currency, BalanceChangeCause::Withdraw, price).await?;
+    economy::check_amount(price)?;
+
+    let balance = {
+        let _guard = economy::lock().await;
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let change = economy::apply_in(&mut tx, &buyer_uuid, &currency.name, -price).await?;
+        if !settle_purchase(&mut tx, &listing, &buyer_uuid, price).await? {
+            return Err(AuctionError::NotFound);
+        }
+        tx.commit().await?;
+
+        change.committed().await?
+    };
+
+    economy::after_change(
+        &buyer_uuid,
+        &currency,
+        BalanceChangeCause::Withdraw,
+        price,
+        balance,
+    )
+    .await;
+
+    Ok(listing)
+}
+
+// Replaces the highest bid, the previous bidder gets their bid back through
+// the mailbox. False if another bid came in first.
+async fn place_bid(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    listing: &Listing,
+    bidder_uuid: &str,
+    amount: f64,
+) -> Result<bool, sqlx::Error> {
+    let db = get_db().await;
+
+    let res = sqlx::query(db.sql(
+        "UPDATE auction_listings SET top_bid = $1, top_bidder = $2
+            WHERE id = $3 AND state = 'active' AND expires_at > $4 AND COALESCE(top_bid, -1) = $5",
+    ))
+    .bind(amount)
+    .bind(bidder_uuid)
+    .bind(listing.id)
+    .bind(current_sec())
+    .bind(listing.top_bid.unwrap_or(-1.0))
+    .execute(&mut **tx)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    if let (Some(previous), Some(top)) = (&listing.top_bidder, listing.top_bid) {
+        mail_money(tx, previous, top, "outbid").await?;
+    }
+
+    Ok(true)
+}
+
+// Bids on an auction, the bid is held until the player is outbid or wins
+pub async fn bid(player: &Arc<Player>, id: i64, amount: f64) -> Result<f64, AuctionError> {
+    let currency = economy::default_currency().await;
+    let bidder_uuid = player.gameprofile.id.to_string();
+    let listing = get_listing(id).await?;
+    if listing.start_bid.is_none() {
+        return Err(AuctionError::NotAuction);
+    }
+    if listing.seller == bidder_uuid {
+        return Err(AuctionError::OwnListing);
+    }
+    if listing.top_bidder.as_deref() == Some(&bidder_uuid) {
+        return Err(AuctionError::AlreadyTopBidder);
+    }
+
+    let amount = currency.round(amount);
+    let minimum = minimum_bid(&listing).await;
+    if amount < minimum {
+        return Err(AuctionError::BidTooLow(currency.format(minimum)));
+    }
+
+    let amount = economy::before_change(
+        &bidder_uuid,
+        &currency,
+        BalanceChangeCause::Withdraw,
+        amount,
+    )
+    .await?;
+    if amount < minimum {
+        return Err(AuctionError::BidTooLow(currency.format(minimum)));
+    }
+
+    let balance = {
+        let _guard = economy::lock().await;
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let change = economy::apply_in(&mut tx, &bidder_uuid, &currency.name, -amount).await?;
+        if !place_bid(&mut tx, &listing, &bidder_uuid, amount).await? {
+            return Err(AuctionError::Outbid);
+        }
+        tx.commit().await?;
+
+        change.committed().await?
+    };
+
+    economy::after_change(
+        &bidder_uuid,
+        &currency,
+        BalanceChangeCause::Withdraw,
+        amount,
+        balance,
+    )
+    .await;
+
+    Ok(amount)
+}
+
+// Takes a listing without bids down, the item goes back to the mailbox
+pub async fn cancel(player_uuid: &str, id: i64) -> Result<(), AuctionError> {
+    let listing = get_listing(id).await?;
+    if listing.seller != player_uuid {
+        return Err(AuctionError::NotSeller);
+    }
+
+    let db = get_db().await;
+    let mut tx = db.pool.begin().await?;
+    let res = sqlx::query(db.sql(
+        "UPDATE auction_listings SET state = 'cancelled', closed_at = $1
+            WHERE id = $2 AND state = 'active' AND top_bidder IS NULL",
+    ))
+    .bind(current_sec())
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(AuctionError::HasBids);
+    }
+
+    mail_item(&mut tx, id, player_uuid, "cancelled").await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Closes a listing that ran out, won auctions go to the highest bidder and
+// everything else back to the seller
+async fn close_expired(listing: &Listing) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    let (state, buyer) = match &listing.top_bidder {
+        Some(bidder) => ("sold", Some(bidder.as_str())),
+        None => ("expired", None),
+    };
+
+    let mut tx = db.pool.begin().await?;
+    let res = sqlx::query(db.sql(
+        "UPDATE auction_listings SET state = $1, buyer = $2, closed_at = $3
+            WHERE id = $4 AND state = 'active' AND COALESCE(top_bid, -1) = $5",
+    ))
+    .bind(state)
+    .bind(buyer)
+    .bind(current_sec())
+    .bind(listing.id)
+    .bind(listing.top_bid.unwrap_or(-1.0))
+    .execute(&mut *tx)
+    .await?;
+    // Closed elsewhere, or bid on since it was read
+    if res.rows_affected() == 0 {
+        return Ok(());
+    }
+
+    match (buyer, listing.top_bid) {
+        (Some(buyer), Some(top)) => {
+            mail_item(&mut tx, listing.id, buyer, "won").await?;
+            mail_money(&mut tx, &listing.seller, top, "sale").await?;
+        }
+        _ => mail_item(&mut tx, listing.id, &listing.seller, "expired").await?,
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Closes all expired listings, returns how many were closed
+pub async fn close_all_expired() -> Result<usize, sqlx::Error> {
+    let db = get_db().await;
+    let listings = sqlx::query_as::<_, Listing>(db.sql(
+        "SELECT l.id, l.seller, p.nickname AS seller_name, l.item_key, l.quantity, l.price,
+                l.start_bid, l.top_bid, l.top_bidder, l.expires_at
+            FROM auction_listings l LEFT JOIN players p ON p.uuid = l.seller
+            WHERE l.state = 'active' AND l.expires_at <= $1",
+    ))
+    .bind(current_sec())
+    .fetch_all(&db.pool)
+    .await?;
+
+    for listing in listings.iter() {
+        close_expired(listing).await?;
+    }
+
+    Ok(listings.len())
+}
+
+// Checks for expired listings every minute
+pub fn start_expiry_task() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = close_all_expired().await {
+                log::error!("Failed to close expired auctions: {}", e);
+            }
+        }
+    });
+}
+
+// Items and money waiting in a player's mailbox
+pub async fn mailbox(player_uuid: &str) -> Result<(i64, f64), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query_as::<_, (i64, f64)>(db.sql(
+        "SELECT CAST(COALESCE(SUM(CASE WHEN item IS NULL THEN 0 ELSE 1 END), 0) AS BIGINT),
+                COALESCE(SUM(amount), 0.0)
+            FROM auction_mailbox WHERE uuid = $1",
+    ))
+    .bind(player_uuid)
+    .fetch_one(&db.pool)
+    .await
+}
+
+// Tells a joining player about their mailbox
+pub async fn notify_mailbox(player: &Player) -> Result<(), sqlx::Error> {
+    let (items, amount) = mailbox(&player.gameprofile.id.to_string()).await?;
+    if items == 0 && amount <= 0.0 {
+        return Ok(());
+    }
+
+    let currency = economy::default_currency().await;
+    player
+        .send_system_message(
+            &TextComponent::text(format!(
+                "Your auction house mailbox holds {} item{} and {}, use /ah claim.",
+                items,
+                if items == 1 { "" } else { "s" },
+                currency.format(amount)
+            ))
+            .color_rgb(neutral_colour()),
+        )
+        .await;
+
+    Ok(())
+}
+
+// Hands out one mailbox row. The row is removed and the money credited in a
+// transaction that only commits once the item was given and saved.
+async fn claim_row(
+    server: &Server,
+    player: &Player,
+    player_uuid: &str,
+    currency: &str,
+    row: &MailboxRow,
+) -> Result<ClaimedRow, AuctionError> {
+    let db = get_db().await;
+
+    let mut tx = db.pool.begin().await?;
+    let res = sqlx::query(db.sql("DELETE FROM auction_mailbox WHERE id = $1 AND uuid = $2"))
+        .bind(row.id)
+        .bind(player_uuid)
+        .execute(&mut *tx)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Ok(ClaimedRow::Gone);
+    }
+
+    let change = if row.amount > 0.0 {
+        Some(economy::apply_in(&mut tx, player_uuid, currency, row.amount).await?)
+    } else {
+        None
+    };
+
+    let Some(item) = &row.item else {
+        tx.commit().await?;
+        if let Some(change) = change {
+            change.committed().await?;
+        }
+        return Ok(ClaimedRow::Claimed);
+    };
+
+    let Some(stack) = decode(item).and_then(|stack| with_tag(&stack, MAILBOX_TAG, row.id)) else {
+        log::error!("Failed to decode auction mailbox item {}.", row.id);
+        return Ok(ClaimedRow::Unreadable);
+    };
+    if !inventory::give_stack(player, stack).await {
+        return Ok(ClaimedRow::NoRoom);
+    }
+    let is_row = |s: &ItemStack| tag(s, MAILBOX_TAG) == Some(row.id);
+
+    let res = match save_player(server, player).await {
+        Ok(()) => tx.commit().await.map_err(AuctionError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        log::error!(
+            "Failed to remove auction mailbox row {} after claiming it: {}",
+            row.id,
+            e
+        );
+        inventory::replace_stacks(player, |s| is_row(s).then(|| ItemStack::EMPTY.clone())).await;
+        return Err(e);
+    }
+
+    if let Some(change) = change {
+        change.committed().await?;
+    }
+
+    inventory::replace_stacks(player, |s| {
+        if !is_row(s) {
+            return None;
+        }
+        without_tags(s)
+    })
+    .await;
+    // A failed save is settled on the next join
+    let _ = save_player(server, player).await;
+
+    Ok(ClaimedRow::Claimed)
+}
+
+// Moves everything in the mailbox into the player's inventory and balance,
+// items that do not fit stay in the mailbox
+pub async fn claim(server: &Server, player: &Arc<Player>) -> Result<Claimed, AuctionError> {
+    let currency = economy::default_currency().await;
+    let player_uuid = player.gameprofile.id.to_string();
+
+    let db = get_db().await;
+    let rows = sqlx::query_as::<_, MailboxRow>(
+        db.sql("SELECT id, item, amount FROM auction_mailbox WHERE uuid = $1 ORDER BY id"),
+    )
+    .bind(&player_uuid)
+    .fetch_all(&db.pool)
+    .await?;
+    if rows.is_empty() {
+        return Err(AuctionError::MailboxEmpty);
+    }
+
+    let mut claimed = Claimed::default();
+    let balance = {
+        let _guard = economy::lock().await;
+
+        for row in rows.iter() {
+            match claim_row(server, player, &player_uuid, &currency.name, row).await? {
+                ClaimedRow::Claimed => {
+                    if row.item.is_some() {
+                        claimed.items += 1;
+                    }
+                    claimed.amount += row.amount;
+                }
+                ClaimedRow::NoRoom => claimed.full = true,
+                ClaimedRow::Gone | ClaimedRow::Unreadable => {}
+            }
+        }
+
+        economy::balance(&player_uuid, &currency.name).await?
+    };
+
+    if claimed.amount > 0.0 {
+        economy::after_change(
+            &player_uuid,
+            &currency,
+            BalanceChangeCause::Deposit,
+            claimed.amount,
+            balance,
+        )
+        .await;
+    }
+
+    Ok(claimed)
+}
+
+// Settles moves between the auction house and the inventory that a crash
+// cut short. Tagged stacks whose listing or mailbox row was committed belong
+// to the auction house and are removed, the rest lose the tag. Pending
+// listings of the player are then activated.
+pub async fn reconcile(server: &Server, player: &Player) -> Result<(), AuctionError> {
+    let player_uuid = player.gameprofile.id.to_string();
+    let _guard = economy::lock().await;
+
+    let mut tags = Vec::new();
+    inventory::replace_stacks(player, |s| {
+        if let Some(id) = tag(s, LISTING_TAG) {
+            tags.push((LISTING_TAG, id));
+        }
+        if let Some(id) = tag(s, MAILBOX_TAG) {
+            tags.push((MAILBOX_TAG, id));
+        }
+        None
+    })
+    .await;
+
+    let db = get_db().await;
+    let mut committed = Vec::new();
+    for (key, id) in tags.iter() {
+        let sql = if *key == LISTING_TAG {
+            "SELECT COUNT(*) FROM auction_listings WHERE id = $1 AND seller = $2"
+        } else {
+            "SELECT COUNT(*) FROM auction_mailbox WHERE id = $1 AND uuid = $2"
+        };
+        let count = sqlx::query_scalar::<_, i64>(db.sql(sql))
+            .bind(id)
+            .bind(&player_uuid)
+            .fetch_one(&db.pool)
+            .await?;
+        // A listing row holds the item, a mailbox row still to be claimed
+        // means the claim did not commit
+        if count > 0 {
+            committed.push((*key, *id));
+        }
+    }
+
+    let changed = inventory::replace_stacks(player, |s| {
+        let belongs = [LISTING_TAG, MAILBOX_TAG]
+            .into_iter()
+            .any(|key| tag(s, key).is_some_and(|id| committed.contains(&(key, id))));
+        if belongs {
+            return Some(ItemStack::EMPTY.clone());
+        }
+        without_tags(s)
+    })
+    .await;
+    if changed > 0 {
+        log::info!(
+            "Settled {} interrupted auction house moves of {}.",
+            changed,
+            player_uuid
+        );
+        save_player(server, player).await?;
+    }
+
+    sqlx::query(db.sql(
+        "UPDATE auction_listings SET state = 'active' WHERE seller = $1 AND state = 'pending'",
+    ))
+    .bind(&player_uuid)
+    .execute(&db.pool)
+    .await?;
+
+    Ok(())
+}
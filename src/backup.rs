@@ -0,0 +1,253 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use sqlx::AnyPool;
+
+use crate::{
+    config::{get_config, get_data_folder},
+    db::{get_db, schema_version, sqlite_path, SCHEMA_VERSION},
+    utils::{current_sec, parse_duration},
+};
+
+const BACKUP_DIR: &str = "backups";
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_EXTENSION: &str = ".db";
+// Holds the name of the backup to restore at the next startup
+const RESTORE_MARKER: &str = "restore.pending";
+
+#[derive(Clone, Debug)]
+pub struct Backup {
+    pub name: String,
+    pub created_at: i64,
+    pub size: u64,
+}
+
+fn backup_dir() -> PathBuf {
+    get_data_folder().join(BACKUP_DIR)
+}
+
+// Creation time of a backup from its file name, None for other files
+fn parse_name(name: &str) -> Option<i64> {
+    name.strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_EXTENSION)?
+        .parse()
+        .ok()
+}
+
+// Backs up the database and rotates old backups, returns the backup name
+pub async fn backup_now() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let dir = backup_dir();
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let name = format!("{}{}{}", BACKUP_PREFIX, current_sec(), BACKUP_EXTENSION);
+    let path = dir.join(&name);
+    if path.exists() {
+        return Err("A backup was already made this second.".into());
+    }
+
+    get_db().await.backup(path.to_str().unwrap()).await?;
+    log::info!("Backed up database to {}.", name);
+
+    match rotate().await {
+        Ok(0) => {}
+        Ok(n) => log::info!("Removed {} old backup{}.", n, if n == 1 { "" } else { "s" }),
+        Err(e) => log::error!("Failed to rotate backups: {}", e),
+    }
+
+    Ok(name)
+}
+
+// Backups newest first
+pub async fn list_backups() -> Result<Vec<Backup>, Box<dyn std::error::Error + Send + Sync>> {
+    let dir = backup_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(created_at) = parse_name(&name) else {
+            continue;
+        };
+
+        backups.push(Backup {
+            name,
+            created_at,
+            size: entry.metadata().await?.len(),
+        });
+    }
+
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(backups)
+}
+
+// Removes backups past the configured count and age, returns the amount removed
+pub async fn rotate() -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let config = get_config().await;
+    let keep = config.value.backup_keep;
+    let max_age = parse_duration(&config.value.backup_max_age);
+
+    let mut removed = 0;
+    for (i, backup) in list_backups().await?.iter().enumerate() {
+        let too_many = keep > 0 && i >= keep;
+        let too_old = max_age.is_some_and(|a| current_sec() - backup.created_at > a);
+
+        // The newest backup is always kept
+        if i > 0 && (too_many || too_old) {
+            tokio::fs::remove_file(backup_dir().join(&backup.name)).await?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+// Backs up the database every configured interval
+pub async fn start_backup_task() {
+    if sqlite_path(get_data_folder()).await.is_none() {
+        return;
+    }
+
+    let Some(interval_secs) = parse_duration(&get_config().await.value.backup_interval) else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs as u64));
+        // The first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = backup_now().await {
+                log::error!("Failed to back up database: {}", e);
+            }
+        }
+    });
+}
+
+// Checks that a backup is a database this version of the plugin can use,
+// returns its schema version
+async fn validate(path: &Path) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    // Restores are validated before the database is set up
+    sqlx::any::install_default_drivers();
+    let pool = AnyPool::connect(&format!("sqlite://{}?mode=ro", path.to_str().unwrap())).await?;
+
+    let tables: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type='table'")
+            .fetch_all(&pool)
+            .await?;
+    let version = schema_version(&pool, &tables).await;
+    pool.close().await;
+
+    let Some(version) = version? else {
+        return Err("Backup has no schema version.".into());
+    };
+
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Backup has schema version {}, this version of the plugin supports up to {}.",
+            version, SCHEMA_VERSION
+        )
+        .into());
+    }
+
+    if !tables.contains(&"players".to_string()) {
+        return Err("Backup has no players table.".into());
+    }
+
+    Ok(version)
+}
+
+// Validates a backup and marks it to be restored at the next startup, the
+// database can not be swapped while it is in use
+pub async fn stage_restore(name: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let data_folder = get_data_folder();
+    if sqlite_path(data_folder).await.is_none() {
+        return Err("Only SQLite databases can be restored.".into());
+    }
+
+    // Only plain backup names, nothing outside the backup folder
+    if parse_name(name).is_none() {
+        return Err(format!("{} is not a backup.", name).into());
+    }
+
+    let path = backup_dir().join(name);
+    if !path.exists() {
+        return Err(format!("Backup {} does not exist.", name).into());
+    }
+
+    validate(&path).await?;
+
+    tokio::fs::write(data_folder.join(RESTORE_MARKER), name).await?;
+    log::info!("Backup {} will be restored at the next startup.", name);
+    Ok(())
+}
+
+// Writes everything in the write-ahead log into the database file, so the
+// replaced database keeps its latest changes once it is moved
+async fn checkpoint(db_path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    sqlx::any::install_default_drivers();
+    let pool = AnyPool::connect(&format!("sqlite://{}", db_path)).await?;
+
+    // Busy is returned as the first column when the log could not be emptied
+    let res = sqlx::query_as::<_, (i64, i64, i64)>("PRAGMA wal_checkpoint(TRUNCATE)")
+        .fetch_one(&pool)
+        .await;
+    pool.close().await;
+
+    if res?.0 != 0 {
+        return Err("The database is in use, its write-ahead log could not be emptied.".into());
+    }
+
+    Ok(())
+}
+
+// Swaps in a staged backup, must run before the database is opened. The
+// replaced database is kept next to it.
+pub async fn apply_pending_restore(
+    data_folder: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let marker = data_folder.join(RESTORE_MARKER);
+    if !marker.exists() {
+        return Ok(());
+    }
+
+    let name = tokio::fs::read_to_string(&marker).await?.trim().to_string();
+    // A failed restore should not be retried on every startup
+    tokio::fs::remove_file(&marker).await?;
+
+    let Some(db_path) = sqlite_path(data_folder).await else {
+        return Err("Only SQLite databases can be restored.".into());
+    };
+    if parse_name(&name).is_none() {
+        return Err(format!("{} is not a backup.", name).into());
+    }
+
+    let backup = data_folder.join(BACKUP_DIR).join(&name);
+    let version = validate(&backup).await?;
+
+    if Path::new(&db_path).exists() {
+        checkpoint(&db_path).await?;
+
+        let replaced = format!("{}.pre-restore-{}", db_path, current_sec());
+        tokio::fs::rename(&db_path, &replaced).await?;
+        log::info!("Moved current database to {}.", replaced);
+    }
+
+    // Journal files belong to the replaced database and were emptied into it
+    for suffix in ["-wal", "-shm"] {
+        let journal = format!("{}{}", db_path, suffix);
+        if Path::new(&journal).exists() {
+            tokio::fs::remove_file(&journal).await?;
+        }
+    }
+
+    tokio::fs::copy(&backup, &db_path).await?;
+    log::info!("Restored backup {} (schema version {}).", name, version);
+    Ok(())
+}
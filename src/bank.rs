@@ -0,0 +1,468 @@
+use std::{fmt, time::Duration};
+
+use crate::{
+    api::BalanceChangeCause,
+    config::get_config,
+    db::get_db,
+    economy::{self, EconomyError},
+    payouts,
+    utils::{current_sec, parse_duration},
+};
+
+const MAX_NAME_LENGTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BankError {
+    Economy(EconomyError),
+    AccountNotFound,
+    AccountExists,
+    // Names are 1-32 letters, digits, dashes or underscores
+    InvalidName,
+    // Only members may use an account, only owners may manage members
+    NotMember,
+    NotOwner,
+    AlreadyMember,
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BankError::Economy(e) => write!(f, "{}", e),
+            BankError::AccountNotFound => write!(f, "That bank account does not exist."),
+            BankError::AccountExists => write!(f, "A bank account with that name already exists."),
+            BankError::InvalidName => write!(
+                f,
+                "Bank account names are up to {} letters, digits, dashes or underscores.",
+                MAX_NAME_LENGTH
+            ),
+            BankError::NotMember => write!(f, "You are not a member of that bank account."),
+            BankError::NotOwner => write!(f, "Only the owner can manage that bank account."),
+            BankError::AlreadyMember => write!(f, "That player is already a member."),
+        }
+    }
+}
+
+impl std::error::Error for BankError {}
+
+impl From<EconomyError> for BankError {
+    fn from(e: EconomyError) -> Self {
+        BankError::Economy(e)
+    }
+}
+
+impl From<sqlx::Error> for BankError {
+    fn from(e: sqlx::Error) -> Self {
+        BankError::Economy(e.into())
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct BankAccount {
+    pub name: String,
+    pub owner: String,
+    pub balance: f64,
+    pub created_at: i64,
+    pub last_interest: i64,
+}
+
+fn check_name(name: &str) -> Result<(), BankError> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_LENGTH
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(BankError::InvalidName);
+    }
+
+    Ok(())
+}
+
+// Account by name, names are matched case-insensitively
+pub async fn get_account(name: &str) -> Result<BankAccount, BankError> {
+    let db = get_db().await;
+    sqlx::query_as::<_, BankAccount>(db.sql(
+        "SELECT name, owner, balance, created_at, last_interest
+            FROM bank_accounts WHERE name_key = $1",
+    ))
+    .bind(name.to_lowercase())
+    .fetch_optional(&db.pool)
+    .await?
+    .ok_or(BankError::AccountNotFound)
+}
+
+pub async fn is_member(account: &BankAccount, player_uuid: &str) -> Result<bool, BankError> {
+    if account.owner == player_uuid {
+        return Ok(true);
+    }
+
+    let db = get_db().await;
+    let count = sqlx::query_scalar::<_, i64>(
+        db.sql("SELECT COUNT(*) FROM bank_members WHERE account = $1 AND uuid = $2"),
+    )
+    .bind(&account.name)
+    .bind(player_uuid)
+    .fetch_one(&db.pool)
+    .await?;
+
+    Ok(count > 0)
+}
+
+// Account the player may use, the owner counts as a member
+async fn member_account(name: &str, player_uuid: &str) -> Result<BankAccount, BankError> {
+    let account = get_account(name).await?;
+    if !is_member(&account, player_uuid).await? {
+        return Err(BankError::NotMember);
+    }
+
+    Ok(account)
+}
+
+// UUIDs of the members of an account, without the owner
+pub async fn get_members(account: &BankAccount) -> Result<Vec<String>, BankError> {
+    let db = get_db().await;
+    let members = sqlx::query_scalar::<_, String>(
+        db.sql("SELECT uuid FROM bank_members WHERE account = $1 ORDER BY added_at"),
+    )
+    .bind(&account.name)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(members)
+}
+
+// Opens an account owned by the player, the creation fee is taken from
+// their balance. Returns the fee paid.
+pub async fn create_account(name: &str, owner_uuid: &str) -> Result<f64, BankError> {
+    check_name(name)?;
+    let currency = economy::default_currency().await;
+    let fee = currency.round(get_config().await.value.bank_creation_fee.max(0.0));
+
+    let fee = if fee > 0.0 {
+        economy::before_change(owner_uuid, &currency, BalanceChangeCause::Withdraw, fee).await?
+    } else {
+        0.0
+    };
+
+    let balance = {
+        let _guard = economy::lock().await;
+        match get_account(name).await {
+            Ok(_) => return Err(BankError::AccountExists),
+            Err(BankError::AccountNotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let change = if fee > 0.0 {
+            Some(economy::apply_in(&mut tx, owner_uuid, &currency.name, -fee).await?)
+        } else {
+            None
+        };
+        insert_account(&mut tx, name, owner_uuid, fee).await?;
+        tx.commit().await?;
+
+        match change {
+            Some(change) => Some(change.committed().await?),
+            None => None,
+        }
+    };
+
+    if let Some(balance) = balance {
+        economy::after_change(
+            owner_uuid,
+            &currency,
+            BalanceChangeCause::Withdraw,
+            fee,
+            balance,
+        )
+        .await;
+    }
+
+    Ok(fee)
+}
+
+// Moves money from the player's balance into an account, returns the new
+// account balance
+pub async fn deposit(name: &str, player_uuid: &str, amount: f64) -> Result<f64, BankError> {
+    let currency = economy::default_currency().await;
+    let amount = currency.round(amount);
+    economy::check_amount(amount)?;
+    let account = member_account(name, player_uuid).await?;
+    let amount =
+        economy::before_change(player_uuid, &currency, BalanceChangeCause::Withdraw, amount)
+            .await?;
+    economy::check_amount(amount)?;
+
+    let (balance, account_balance) = {
+        let _guard = economy::lock().await;
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let change = economy::apply_in(&mut tx, player_uuid, &currency.name, -amount).await?;
+        // The account may have been removed in the meantime
+        let account_balance =
+            change_balance(&mut tx, &account.name, player_uuid, "deposit", amount)
+                .await?
+                .ok_or(BankError::AccountNotFound)?;
+        tx.commit().await?;
+
+        (change.committed().await?, account_balance)
+    };
+
+    economy::after_change(
+        player_uuid,
+        &currency,
+        BalanceChangeCause::Withdraw,
+        amount,
+        balance,
+    )
+    .await;
+    Ok(account_balance)
+}
+
+// Moves money from an account onto the player's balance, returns the new
+// account balance
+pub async fn withdraw(name: &str, player_uuid: &str, amount: f64) -> Result<f64, BankError> {
+    let currency = economy::default_currency().await;
+    let amount = currency.round(amount);
+    economy::check_amount(amount)?;
+    let account = member_account(name, player_uuid).await?;
+    let amount =
+        economy::before_change(player_uuid, &currency, BalanceChangeCause::Deposit, amount).await?;
+    economy::check_amount(amount)?;
+
+    let (balance, account_balance) = {
+        let _guard = economy::lock().await;
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let account_balance =
+            change_balance(&mut tx, &account.name, player_uuid, "withdraw", -amount)
+                .await?
+                .ok_or(EconomyError::InsufficientFunds)?;
+        let change = economy::apply_in(&mut tx, player_uuid, &currency.name, amount).await?;
+        tx.commit().await?;
+
+        (change.committed().await?, account_balance)
+    };
+
+    economy::after_change(
+        player_uuid,
+        &currency,
+        BalanceChangeCause::Deposit,
+        amount,
+        balance,
+    )
+    .await;
+    Ok(account_balance)
+}
+
+pub async fn add_member(name: &str, owner_uuid: &str, member_uuid: &str) -> Result<(), BankError> {
+    let account = get_account(name).await?;
+    if account.owner != owner_uuid {
+        return Err(BankError::NotOwner);
+    }
+    if is_member(&account, member_uuid).await? {
+        return Err(BankError::AlreadyMember);
+    }
+
+    // The member may have been added by another server in the meantime
+    let db = get_db().await;
+    let res = sqlx::query(db.sql(
+        "INSERT INTO bank_members (account, uuid, added_at) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING",
+    ))
+    .bind(&account.name)
+    .bind(member_uuid)
+    .bind(current_sec())
+    .execute(&db.pool)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(BankError::AlreadyMember);
+    }
+
+    Ok(())
+}
+
+pub async fn remove_member(
+    name: &str,
+    owner_uuid: &str,
+    member_uuid: &str,
+) -> Result<(), BankError> {
+    let account = get_account(name).await?;
+    if account.owner != owner_uuid {
+        return Err(BankError::NotOwner);
+    }
+
+    let db = get_db().await;
+    let res = sqlx::query(db.sql("DELETE FROM bank_members WHERE account = $1 AND uuid = $2"))
+        .bind(&account.name)
+        .bind(member_uuid)
+        .execute(&db.pool)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(BankError::NotMember);
+    }
+
+    Ok(())
+}
+
+// Records a change of an account, in the transaction making the change
+async fn record(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    account: &str,
+    player_uuid: &str,
+    kind: &str,
+    amount: f64,
+) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query(db.sql(
+        "INSERT INTO bank_transactions (account, uuid, kind, amount, created_at)
+            VALUES ($1, $2, $3, $4, $5)",
+    ))
+    .bind(account)
+    .bind(player_uuid)
+    .bind(kind)
+    .bind(amount)
+    .bind(current_sec())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Adds an account, names are unique regardless of case and another server
+// may have taken the name since it was checked
+async fn insert_account(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    name: &str,
+    owner_uuid: &str,
+    fee: f64,
+) -> Result<(), BankError> {
+    let db = get_db().await;
+    let now = current_sec();
+
+    let res = sqlx::query(db.sql(
+        "INSERT INTO bank_accounts (name, name_key, owner, balance, created_at, last_interest)
+            VALUES ($1, $2, $3, 0, $4, $5) ON CONFLICT DO NOTHING",
+    ))
+    .bind(name)
+    .bind(name.to_lowercase())
+    .bind(owner_uuid)
+    .bind(now)
+    .bind(now)
+    .execute(&mut **tx)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(BankError::AccountExists);
+    }
+    record(tx, name, owner_uuid, "create", fee).await?;
+
+    Ok(())
+}
+
+// Changes the balance of an account and records it, returns the new balance
+// or None if the account would be overdrawn. The check is part of the
+// update, other servers may use the account too.
+async fn change_balance(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    account: &str,
+    player_uuid: &str,
+    kind: &str,
+    delta: f64,
+) -> Result<Option<f64>, sqlx::Error> {
+    let db = get_db().await;
+
+    let res = sqlx::query(db.sql(
+        "UPDATE bank_accounts SET balance = balance + $1 WHERE name = $2 AND balance + $3 >= 0",
+    ))
+    .bind(delta)
+    .bind(account)
+    .bind(delta)
+    .execute(&mut **tx)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Ok(None);
+    }
+    record(tx, account, player_uuid, kind, delta.abs()).await?;
+
+    let balance =
+        sqlx::query_scalar::<_, f64>(db.sql("SELECT balance FROM bank_accounts WHERE name = $1"))
+            .bind(account)
+            .fetch_one(&mut **tx)
+            .await?;
+
+    Ok(Some(balance))
+}
+
+// Pays interest to every account that has not had any for a full interval.
+// The time of the last payout is stored per account, so restarts and other
+// servers sharing the database do not pay twice.
+pub async fn pay_interest() -> Result<usize, BankError> {
+    let config = get_config().await;
+    let rate = config.value.bank_interest_rate;
+    let Some(interval) = parse_duration(&config.value.bank_interest_interval) else {
+        return Ok(0);
+    };
+    if rate <= 0.0 {
+        return Ok(0);
+    }
+
+    let currency = economy::default_currency().await;
+    let db = get_db().await;
+    let now = current_sec();
+    let due = sqlx::query_as::<_, BankAccount>(db.sql(
+        "SELECT name, owner, balance, created_at, last_interest
+            FROM bank_accounts WHERE last_interest <= $1",
+    ))
+    .bind(now - interval)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let mut paid = 0;
+    for account in due {
+        // Tiny balances earn nothing
+        let interest = payouts::interest(&currency, account.balance, rate, 0.0);
+
+        let mut tx = db.pool.begin().await?;
+        let res = sqlx::query(db.sql(
+            "UPDATE bank_accounts SET balance = balance + $1, last_interest = $2
+                WHERE name = $3 AND last_interest = $4",
+        ))
+        .bind(interest.max(0.0))
+        .bind(now)
+        .bind(&account.name)
+        .bind(account.last_interest)
+        .execute(&mut *tx)
+        .await?;
+
+        // Another server got to it first
+        if res.rows_affected() == 0 {
+            continue;
+        }
+
+        if interest > 0.0 {
+            record(&mut tx, &account.name, &account.owner, "interest", interest).await?;
+            paid += 1;
+        }
+        tx.commit().await?;
+    }
+
+    Ok(paid)
+}
+
+// Checks for accounts due interest every minute
+pub fn start_interest_task() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = pay_interest().await {
+                log::error!("Failed to pay bank interest: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,539 @@
+use std::{sync::Arc, time::Duration};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use pumpkin::server::Server;
+use serde::Deserialize;
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+use crate::{config::get_config, staffchat, utils::current_sec};
+
+const SIGNATURE_HEADER: &str = "x-servercore-signature";
+const TIMESTAMP_HEADER: &str = "x-servercore-timestamp";
+
+// Signed requests older than this are rejected to prevent replays
+const MAX_REQUEST_AGE: i64 = 300;
+const MAX_HEADER_LINES: usize = 64;
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+const MAX_BODY_SIZE: usize = 16 * 1024;
+// Clients have this long to send the whole request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// The webhook has this long to answer a post
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+// Posts waiting for the webhook, more are dropped while it is slow
+const MAX_QUEUED_POSTS: usize = 64;
+
+lazy_static! {
+    static ref WEBHOOK_QUEUE: mpsc::Sender<WebhookPost> = start_webhook_worker();
+    // Signature -> Timestamp of inbound requests within MAX_REQUEST_AGE
+    static ref SEEN_SIGNATURES: DashMap<String, i64> = DashMap::new();
+}
+
+struct WebhookPost {
+    url: String,
+    username: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InboundMessage {
+    sender: String,
+    message: String,
+    channel: Option<String>,
+}
+
+// Posts a staffchat message to the webhook
+pub async fn post_staff(prefix: &str, sender: &str, message: &str) {
+    let config = get_config().await;
+    if !config.value.bridge_enabled {
+        return;
+    }
+
+    post(format!("{} {}", prefix, sender), message.to_string()).await;
+}
+
+// Posts a public chat message to the webhook
+pub async fn post_chat(sender: &str, message: &str) {
+    let config = get_config().await;
+    if !config.value.bridge_enabled || !config.value.bridge_public_chat {
+        return;
+    }
+
+    post(sender.to_string(), message.to_string()).await;
+}
+
+// Posts a join or leave message to the webhook
+pub async fn post_join_leave(message: &str) {
+    let config = get_config().await;
+    if !config.value.bridge_enabled || !config.value.bridge_join_leave {
+        return;
+    }
+
+    post("Server".to_string(), message.to_string()).await;
+}
+
+async fn post(username: String, content: String) {
+    let url = get_config().await.value.bridge_webhook_url.clone();
+    if url.is_empty() {
+        return;
+    }
+
+    // Do not hold up chat while the webhook responds
+    let post = WebhookPost {
+        url,
+        username,
+        content,
+    };
+    if WEBHOOK_QUEUE.try_send(post).is_err() {
+        log::warn!("Chat bridge webhook is not keeping up, dropping a message.");
+    }
+}
+
+// Posts queued messages one at a time on its own thread
+fn start_webhook_worker() -> mpsc::Sender<WebhookPost> {
+    let (tx, mut rx) = mpsc::channel::<WebhookPost>(MAX_QUEUED_POSTS);
+
+    std::thread::spawn(move || {
+        let agent = webhook_agent();
+        while let Some(post) = rx.blocking_recv() {
+            if let Err(e) = send_webhook(&agent, &post.url, &post.username, &post.content) {
+                log::warn!("Failed to post to chat bridge webhook: {}", e);
+            }
+        }
+    });
+
+    tx
+}
+
+fn webhook_agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .into()
+}
+
+// Blocks until the webhook responded
+fn send_webhook(
+    agent: &ureq::Agent,
+    url: &str,
+    username: &str,
+    content: &str,
+) -> Result<(), ureq::Error> {
+    // Discord compatible payload, players must not be able to ping anyone
+    let body = serde_json::json!({
+        "username": username,
+        "content": content,
+        "allowed_mentions": { "parse": [] },
+    })
+    .to_string();
+
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(&body)?;
+
+    Ok(())
+}
+
+// Starts the inbound endpoint if an address has been configured
+pub async fn start(server: Arc<Server>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = get_config().await;
+    if !config.value.bridge_enabled || config.value.bridge_listen_address.is_empty() {
+        return Ok(());
+    }
+
+    if config.value.bridge_secret.is_empty() {
+        return Err("bridge_secret must be set to accept inbound messages".into());
+    }
+
+    let listener = TcpListener::bind(&config.value.bridge_listen_address).await?;
+    log::info!(
+        "Chat bridge listening on: {}",
+        config.value.bridge_listen_address
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, addr) = match listener.accept().await {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("Chat bridge failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, &server).await {
+                    log::warn!("Chat bridge request from {} failed: {}", addr, e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    server: &Server,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let secret = get_config().await.value.bridge_secret.clone();
+    let mut reader = BufReader::new(stream);
+
+    // Slow clients would otherwise hold the task forever
+    let inbound = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut reader, &secret)).await
+    {
+        Ok(Ok(inbound)) => inbound,
+        Ok(Err(status)) => return respond(reader.get_mut(), status).await,
+        Err(_) => return respond(reader.get_mut(), "408 Request Timeout").await,
+    };
+
+    let channel_name = inbound
+        .channel
+        .unwrap_or(staffchat::DEFAULT_CHANNEL.to_string());
+    let Some(channel) = staffchat::get_channel(&channel_name).await else {
+        return respond(reader.get_mut(), "404 Not Found").await;
+    };
+
+    // Relay without posting back to the webhook
+    staffchat::relay(server, &channel, &inbound.sender, &inbound.message).await;
+
+    respond(reader.get_mut(), "204 No Content").await
+}
+
+// Reads one line into the buffer, false if it was longer than allowed or
+// the connection closed first
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut String,
+) -> std::io::Result<bool> {
+    line.clear();
+    (&mut *reader).take(MAX_LINE_LENGTH).read_line(line).await?;
+
+    Ok(line.ends_with('\n'))
+}
+
+// Reads and verifies a request, returns the status to answer with if it is
+// refused
+async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    secret: &str,
+) -> Result<InboundMessage, &'static str> {
+    const BAD_REQUEST: &str = "400 Bad Request";
+
+    let mut line = String::new();
+    if !read_line(reader, &mut line)
+        .await
+        .map_err(|_| BAD_REQUEST)?
+    {
+        return Err(BAD_REQUEST);
+    }
+    if !line.starts_with("POST ") {
+        return Err("405 Method Not Allowed");
+    }
+
+    let mut content_length = 0;
+    let mut signature = String::new();
+    let mut timestamp = String::new();
+
+    for _ in 0..MAX_HEADER_LINES {
+        if !read_line(reader, &mut line)
+            .await
+            .map_err(|_| BAD_REQUEST)?
+        {
+            return Err("431 Request Header Fields Too Large");
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+
+        match name.trim().to_lowercase().as_str() {
+            "content-length" => {
+                content_length = value.trim().parse::<usize>().map_err(|_| BAD_REQUEST)?
+            }
+            SIGNATURE_HEADER => signature = value.trim().to_string(),
+            TIMESTAMP_HEADER => timestamp = value.trim().to_string(),
+            _ => {}
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err("413 Payload Too Large");
+    }
+
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|_| BAD_REQUEST)?;
+
+    let now = current_sec();
+    if !verify(secret, &timestamp, &body, &signature, now)
+        || !first_use(&signature, timestamp.parse().unwrap_or(0), now)
+    {
+        return Err("401 Unauthorized");
+    }
+
+    serde_json::from_slice::<InboundMessage>(&body).map_err(|_| BAD_REQUEST)
+}
+
+fn verify(secret: &str, timestamp: &str, body: &[u8], signature: &str, now: i64) -> bool {
+    let Ok(ts) = timestamp.parse::<i64>() else {
+        return false;
+    };
+
+    if (now - ts).abs() > MAX_REQUEST_AGE {
+        return false;
+    }
+
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    // Constant time comparison
+    mac.verify_slice(&signature).is_ok()
+}
+
+// Remembers the signature of a verified request, false if it was seen
+// before. Signatures are forgotten once their request is too old anyway.
+fn first_use(signature: &str, timestamp: i64, now: i64) -> bool {
+    SEEN_SIGNATURES.retain(|_, ts| (now - *ts).abs() <= MAX_REQUEST_AGE);
+
+    match SEEN_SIGNATURES.entry(signature.to_lowercase()) {
+        Entry::Occupied(_) => false,
+        Entry::Vacant(entry) => {
+            entry.insert(timestamp);
+            true
+        }
+    }
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Read, Write};
+
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn sign(timestamp: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn request(body: &str, timestamp: &str, signature: &str) -> String {
+        format!(
+            "POST /relay HTTP/1.1\r\nContent-Length: {}\r\n{}: {}\r\n{}: {}\r\n\r\n{}",
+            body.len(),
+            SIGNATURE_HEADER,
+            signature,
+            TIMESTAMP_HEADER,
+            timestamp,
+            body
+        )
+    }
+
+    // Sends raw bytes through a local connection and reads them as the
+    // bridge would
+    async fn read_over_tcp(raw: Vec<u8>) -> Result<InboundMessage, &'static str> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            // The bridge may stop reading early
+            let _ = stream.write_all(&raw).await;
+            stream
+        });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let res = read_request(&mut reader, SECRET).await;
+        drop(client.await.unwrap());
+        res
+    }
+
+    #[test]
+    fn verify_accepts_valid_signature() {
+        let now = current_sec();
+        let ts = now.to_string();
+        let body = br#"{"sender":"a","message":"b"}"#;
+        assert!(verify(SECRET, &ts, body, &sign(&ts, body), now));
+    }
+
+    #[test]
+    fn verify_rejects_bad_signatures() {
+        let now = current_sec();
+        let ts = now.to_string();
+        let body = br#"{"sender":"a","message":"b"}"#;
+        let signature = sign(&ts, body);
+
+        assert!(!verify("other-secret", &ts, body, &signature, now));
+        assert!(!verify(SECRET, &ts, b"{}", &signature, now));
+        assert!(!verify(SECRET, &ts, body, "not hex", now));
+        assert!(!verify(SECRET, "soon", body, &signature, now));
+    }
+
+    #[test]
+    fn verify_rejects_old_requests() {
+        let then = current_sec() - MAX_REQUEST_AGE - 1;
+        let ts = then.to_string();
+        let body = b"{}";
+        assert!(!verify(SECRET, &ts, body, &sign(&ts, body), current_sec()));
+    }
+
+    #[tokio::test]
+    async fn relays_signed_request() {
+        let ts = current_sec().to_string();
+        let body = r#"{"sender":"Discord","message":"hello","channel":"admin"}"#;
+        let raw = request(body, &ts, &sign(&ts, body.as_bytes()));
+
+        let inbound = read_over_tcp(raw.into_bytes()).await.unwrap();
+        assert_eq!(inbound.sender, "Discord");
+        assert_eq!(inbound.message, "hello");
+        assert_eq!(inbound.channel.as_deref(), Some("admin"));
+    }
+
+    #[tokio::test]
+    async fn refuses_replayed_request() {
+        let ts = current_sec().to_string();
+        let body = r#"{"sender":"Discord","message":"once"}"#;
+        let raw = request(body, &ts, &sign(&ts, body.as_bytes()));
+
+        assert!(read_over_tcp(raw.clone().into_bytes()).await.is_ok());
+        assert_eq!(
+            read_over_tcp(raw.into_bytes()).await.unwrap_err(),
+            "401 Unauthorized"
+        );
+    }
+
+    #[test]
+    fn forgets_old_signatures() {
+        let now = current_sec();
+        assert!(first_use("abc", now - MAX_REQUEST_AGE, now));
+        assert!(!first_use("ABC", now, now));
+        // Once the request is too old it is refused by verify instead
+        assert!(first_use("abc", now, now + 1));
+    }
+
+    #[tokio::test]
+    async fn refuses_unsigned_request() {
+        let ts = current_sec().to_string();
+        let body = r#"{"sender":"a","message":"b"}"#;
+        let raw = request(body, &ts, &sign(&ts, b"other"));
+
+        assert_eq!(
+            read_over_tcp(raw.into_bytes()).await.unwrap_err(),
+            "401 Unauthorized"
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_bad_content_length() {
+        let raw = "POST / HTTP/1.1\r\nContent-Length: lots\r\n\r\n";
+        assert_eq!(
+            read_over_tcp(raw.as_bytes().to_vec()).await.unwrap_err(),
+            "400 Bad Request"
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_endless_header_line() {
+        let mut raw = b"POST / HTTP/1.1\r\nX-Long: ".to_vec();
+        raw.extend(std::iter::repeat(b'a').take(MAX_LINE_LENGTH as usize * 2));
+        assert_eq!(
+            read_over_tcp(raw).await.unwrap_err(),
+            "431 Request Header Fields Too Large"
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_other_methods() {
+        let raw = "GET / HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            read_over_tcp(raw.as_bytes().to_vec()).await.unwrap_err(),
+            "405 Method Not Allowed"
+        );
+    }
+
+    #[test]
+    fn posts_to_webhook() {
+        // Stand-in for the webhook that answers one request
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/webhook", listener.local_addr().unwrap());
+
+        let webhook = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream);
+
+            let mut content_length = 0;
+            let mut line = String::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if line.trim_end().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        });
+
+        send_webhook(&webhook_agent(), &url, "Steve", "hello @everyone").unwrap();
+
+        let body = webhook.join().unwrap();
+        assert_eq!(body["username"], "Steve");
+        assert_eq!(body["content"], "hello @everyone");
+        assert_eq!(body["allowed_mentions"]["parse"], serde_json::json!([]));
+    }
+}
@@ -1,26 +1,59 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use dashmap::DashMap;
 use lazy_static::lazy_static;
-use pumpkin::entity::player::Player;
+use pumpkin::{entity::player::Player, plugin::Cancellable};
 
-use crate::{config::get_config, db::get_db, utils::current_sec};
+use crate::{
+    api::{self, EventPhase, PlayerFirstJoinEvent},
+    db::get_db,
+    economy,
+    ip::{player_ip, record_session},
+    nick::record_name,
+    utils::current_sec,
+};
 
 #[derive(Clone, Debug, sqlx::FromRow)]
 struct DBPlayer {
+    id: i64,
     nickname: String,
     playtime: i64,
-    balance: f64,
+    display_name: Option<String>,
+    version: i64,
 }
 
 #[derive(Clone, Debug)]
 struct CachePlayer {
+    id: i64,
     nickname: String,
+    display_name: Option<String>,
     playtime: i64,
     join_time: i64,
-    balance: f64,
+    // Currency name -> Balance
+    balances: HashMap<String, f64>,
+    // Balances, playtime and row version as of the last write to the
+    // database, only the difference is written back so servers sharing
+    // the database do not overwrite each other
+    synced_balances: HashMap<String, f64>,
+    synced_playtime: i64,
+    synced_at: i64,
+    version: i64,
+    // Last time the player moved, chatted or ran a command
+    last_active: i64,
 }
 
+// A plugin cancelled the first join of a player
+#[derive(Debug)]
+pub struct JoinRefused;
+
+impl std::fmt::Display for JoinRefused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Joining was refused by a plugin.")
+    }
+}
+
+impl std::error::Error for JoinRefused {}
+
 lazy_static! {
     // User UUID -> Playtime at join
     static ref PLAYER_CACHE: DashMap<String, CachePlayer> = DashMap::new();
@@ -31,7 +64,7 @@ pub fn get_playtime_cache(player_uuid: &str) -> i64 {
     let old_pt = PLAYER_CACHE.get(player_uuid).map_or(0, |v| v.playtime);
 
     // Now we need to calculate how much additional time the user spent on the server
-    let jt = PLAYER_CACHE.get(player_uuid).map_or(0, |v| v.join_time);
+    let jt = PLAYER_CACHE.get(player_uuid).map_or(0, |v| v.synced_at);
     let ct = current_sec();
 
     let diff = ct - jt;
@@ -48,17 +81,19 @@ pub async fn get_playtime(player_uuid: &str) -> i64 {
         Some(v) => v.playtime,
         None => {
             // Player isnt online, we may get from
-            sqlx::query_as::<_, DBPlayer>("SELECT playtime FROM players WHERE uuid = $1")
+            let db = get_db().await;
+            sqlx::query_scalar::<_, i64>(db.sql("SELECT playtime FROM players WHERE uuid = $1"))
                 .bind(player_uuid)
-                .fetch_one(&get_db().await.pool)
+                .fetch_one(&db.pool)
                 .await
-                .map_or(0, |v| v.playtime)
+                .unwrap_or(0)
         }
     };
 
-    // Now we need to calculate how much additional time the user spent on the server
-    let jt = PLAYER_CACHE.get(player_uuid).map_or(0, |v| v.join_time);
+    // Now we need to calculate how much additional time the user spent on the server,
+    // which is nothing if they are offline
     let ct = current_sec();
+    let jt = PLAYER_CACHE.get(player_uuid).map_or(ct, |v| v.synced_at);
 
     let diff = ct - jt;
 
@@ -88,13 +123,171 @@ pub fn update_nickname(player_uuid: &str, nickname: &str) {
     old_player.nickname = nickname.to_string();
 }
 
-pub fn get_balance(player_uuid: &str) -> f64 {
-    PLAYER_CACHE.get(player_uuid).map_or(0.0, |v| v.balance)
+// The custom nickname including & codes if set, otherwise the account name
+pub fn get_display_name(player_uuid: &str) -> String {
+    PLAYER_CACHE
+        .get(player_uuid)
+        .map_or("Unknown".to_string(), |v| {
+            v.display_name.clone().unwrap_or(v.nickname.clone())
+        })
 }
 
-pub fn update_balance(player_uuid: &str, balance: f64) {
+pub fn update_display_name(player_uuid: &str, display_name: Option<String>) {
     let mut old_player = PLAYER_CACHE.get_mut(player_uuid).unwrap();
-    old_player.balance = balance;
+    old_player.display_name = display_name;
+}
+
+// Time the player joined at, None if they are offline
+pub fn get_join_time(player_uuid: &str) -> Option<i64> {
+    PLAYER_CACHE.get(player_uuid).map(|v| v.join_time)
+}
+
+pub fn mark_active(player_uuid: &str) {
+    if let Some(mut player) = PLAYER_CACHE.get_mut(player_uuid) {
+        player.last_active = current_sec();
+    }
+}
+
+// Whether an online player has not done anything for the timeout
+pub fn is_afk(player_uuid: &str, timeout_secs: i64) -> bool {
+    PLAYER_CACHE
+        .get(player_uuid)
+        .is_some_and(|v| current_sec() - v.last_active >= timeout_secs)
+}
+
+pub fn is_loaded(player_uuid: &str) -> bool {
+    PLAYER_CACHE.contains_key(player_uuid)
+}
+
+// Looks up a player that has joined before by name, returns (uuid, nickname)
+pub async fn find_player(
+    name: &str,
+) -> Result<Option<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let row = sqlx::query_as::<_, (String, String)>(
+        db.sql("SELECT uuid, nickname FROM players WHERE LOWER(nickname) = LOWER($1)"),
+    )
+    .bind(name)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    Ok(row)
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct PlayerInfo {
+    pub uuid: String,
+    pub nickname: String,
+    pub display_name: Option<String>,
+    pub playtime: i64,
+    pub first_join: Option<i64>,
+    pub last_join: Option<i64>,
+    pub last_seen: Option<i64>,
+}
+
+// Stored information of a player that joined before, playtime is taken from
+// the cache for online players
+pub async fn get_player_info(
+    player_uuid: &str,
+) -> Result<Option<PlayerInfo>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let info = sqlx::query_as::<_, PlayerInfo>(db.sql(
+        "SELECT uuid, nickname, display_name, playtime, first_join, last_join, last_seen
+            FROM players WHERE uuid = $1",
+    ))
+    .bind(player_uuid)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    let Some(mut info) = info else {
+        return Ok(None);
+    };
+
+    if is_loaded(player_uuid) {
+        info.playtime = get_playtime_cache(player_uuid);
+    }
+
+    Ok(Some(info))
+}
+
+pub fn get_balance(player_uuid: &str, currency: &str) -> f64 {
+    PLAYER_CACHE
+        .get(player_uuid)
+        .and_then(|v| v.balances.get(currency).copied())
+        .unwrap_or(0.0)
+}
+
+// Adds to the balance of an online player, returns the new balance or None
+// if the player is not loaded. Balances are changed through the economy module.
+pub fn add_balance(player_uuid: &str, currency: &str, delta: f64) -> Option<f64> {
+    let mut player = PLAYER_CACHE.get_mut(player_uuid)?;
+    let balance = player.balances.entry(currency.to_string()).or_insert(0.0);
+    *balance += delta;
+    Some(*balance)
+}
+
+// Adds a change that is already in the database to the balance of an
+// online player, so the next sync does not write it again. Returns the new
+// balance or None if the player is not loaded.
+pub fn rebase_balance(player_uuid: &str, currency: &str, delta: f64) -> Option<f64> {
+    let mut player = PLAYER_CACHE.get_mut(player_uuid)?;
+    *player
+        .synced_balances
+        .entry(currency.to_string())
+        .or_insert(0.0) += delta;
+    let balance = player.balances.entry(currency.to_string()).or_insert(0.0);
+    *balance += delta;
+    Some(*balance)
+}
+
+// Balances of a player in every configured currency, currencies added since
+// the player last joined start at zero
+async fn load_balances(
+    player_id: i64,
+) -> Result<HashMap<String, f64>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+    let query = db.sql("SELECT currency, balance FROM balances WHERE player_id = $1");
+
+    let balances: HashMap<String, f64> = sqlx::query_as::<_, (String, f64)>(query)
+        .bind(player_id)
+        .fetch_all(&db.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    let missing: Vec<String> = economy::currencies()
+        .await
+        .into_iter()
+        .map(|c| c.name)
+        .filter(|name| !balances.contains_key(name))
+        .collect();
+    if missing.is_empty() {
+        return Ok(balances);
+    }
+
+    for currency in missing {
+        sqlx::query(db.sql(
+            "INSERT INTO balances (player_id, currency, balance) VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING",
+        ))
+        .bind(player_id)
+        .bind(&currency)
+        .bind(0.0)
+        .execute(&db.pool)
+        .await?;
+    }
+
+    // Another server may have created some of the rows in the meantime
+    let balances = sqlx::query_as::<_, (String, f64)>(query)
+        .bind(player_id)
+        .fetch_all(&db.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    Ok(balances)
 }
 
 pub async fn load_player(
@@ -108,7 +301,7 @@ pub async fn load_player(
     let mut new_player = false;
 
     let db_player = match sqlx::query_as::<_, DBPlayer>(
-        "SELECT nickname, playtime, balance FROM players WHERE uuid = $1",
+        db.sql("SELECT id, nickname, playtime, display_name, version FROM players WHERE uuid = $1"),
     )
     .bind(player.gameprofile.id.to_string())
     .fetch_one(&db.pool)
@@ -118,20 +311,74 @@ pub async fn load_player(
         Err(e) => {
             // Player does not yet exist, create them
             if let sqlx::Error::RowNotFound = e {
-                sqlx::query("INSERT INTO players (uuid, nickname) VALUES ($1, $2)")
-                    .bind(&uuid_s)
-                    .bind(&nickname)
-                    .execute(&db.pool)
-                    .await
-                    .unwrap();
+                // Plugins may refuse new players or change what they start with
+                let currencies = economy::currencies().await;
+                let event = api::fire(PlayerFirstJoinEvent::new(
+                    player.clone(),
+                    currencies[0].starting_balance,
+                ))
+                .await;
+                if event.cancelled() {
+                    return Err(Box::new(JoinRefused));
+                }
+                // Listeners may set anything, balances are kept to the
+                // currency's decimals and never go below zero
+                let starting_balance = currencies[0].round(event.starting_balance);
+                let starting_balance = if starting_balance.is_finite() && starting_balance >= 0.0 {
+                    starting_balance
+                } else {
+                    log::warn!(
+                        "Ignoring invalid starting balance {} for {}.",
+                        event.starting_balance,
+                        uuid_s
+                    );
+                    currencies[0].starting_balance
+                };
+
+                // The player and their balances are created together, a
+                // failed join leaves nothing behind
+                let mut tx = db.pool.begin().await?;
+                sqlx::query(
+                    db.sql("INSERT INTO players (uuid, nickname, first_join) VALUES ($1, $2, $3)"),
+                )
+                .bind(&uuid_s)
+                .bind(&nickname)
+                .bind(current_sec())
+                .execute(&mut *tx)
+                .await?;
+
+                let id =
+                    sqlx::query_scalar::<_, i64>(db.sql("SELECT id FROM players WHERE uuid = $1"))
+                        .bind(&uuid_s)
+                        .fetch_one(&mut *tx)
+                        .await?;
+
+                for (i, currency) in currencies.iter().enumerate() {
+                    let balance = if i == 0 {
+                        starting_balance
+                    } else {
+                        currency.starting_balance
+                    };
+                    sqlx::query(db.sql(
+                        "INSERT INTO balances (player_id, currency, balance) VALUES ($1, $2, $3)",
+                    ))
+                    .bind(id)
+                    .bind(&currency.name)
+                    .bind(balance)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                tx.commit().await?;
 
                 log::info!("Created new user: {}", uuid_s);
                 new_player = true;
 
                 DBPlayer {
-                    nickname,
+                    id,
+                    nickname: nickname.clone(),
                     playtime: 0,
-                    balance: get_config().await.value.eco_starting_balance,
+                    display_name: None,
+                    version: 0,
                 }
             } else {
                 return Err(e.into());
@@ -139,30 +386,228 @@ pub async fn load_player(
         }
     };
 
+    // Players from before join times were tracked get their first join now.
+    // The account name is stored right away so renamed players can be
+    // looked up by their new name.
+    sqlx::query(db.sql(
+        "UPDATE players SET nickname = $1, last_join = $2, first_join = COALESCE(first_join, $3)
+            WHERE uuid = $4",
+    ))
+    .bind(&nickname)
+    .bind(current_sec())
+    .bind(current_sec())
+    .bind(&uuid_s)
+    .execute(&db.pool)
+    .await?;
+
+    let ip = player_ip(player).await;
+    record_session(&uuid_s, &ip).await?;
+
+    let balances = load_balances(db_player.id).await?;
+    let default_balance = balances
+        .get(&economy::default_currency().await.name)
+        .copied()
+        .unwrap_or(0.0);
+
+    let join_time = current_sec();
     let cache_player = CachePlayer {
+        id: db_player.id,
         nickname: db_player.nickname.clone(),
+        display_name: db_player.display_name.clone(),
         playtime: db_player.playtime,
-        join_time: current_sec(),
-        balance: db_player.balance,
+        join_time,
+        balances: balances.clone(),
+        synced_balances: balances,
+        synced_playtime: db_player.playtime,
+        synced_at: join_time,
+        version: db_player.version,
+        last_active: join_time,
     };
 
     PLAYER_CACHE.insert(uuid_s.to_string(), cache_player);
+
+    // Account names can be changed at Mojang
+    if let Some(old_name) = record_name(&uuid_s, &nickname).await? {
+        log::info!("{} changed their name to {}", old_name, nickname);
+    }
+    if db_player.nickname != nickname {
+        update_nickname(&uuid_s, &nickname);
+    }
+
+    if new_player {
+        let mut event = PlayerFirstJoinEvent::new(player.clone(), default_balance);
+        event.phase = EventPhase::Post;
+        api::fire(event).await;
+    }
+
     Ok(new_player)
 }
 
+// Writes the balance and playtime gained since the last sync to the database
+// as increments and rebases the cache on the stored row. A version mismatch
+// means another server wrote the row in the meantime, the increments are
+// still applied on top so no money is lost or duplicated.
+pub async fn sync_player(
+    player_uuid: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Two syncs of the same player would both write the same increments
+    let _guard = economy::lock().await;
+    sync_locked(player_uuid).await
+}
+
+// Syncs a player, callers must hold the economy lock
+async fn sync_locked(player_uuid: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some((id, nickname, balances, synced_balances, synced_playtime, version)) =
+        PLAYER_CACHE.get(player_uuid).map(|v| {
+            (
+                v.id,
+                v.nickname.clone(),
+                v.balances.clone(),
+                v.synced_balances.clone(),
+                v.synced_playtime,
+                v.version,
+            )
+        })
+    else {
+        return Ok(());
+    };
+
+    let ct = current_sec();
+    let balance_deltas: Vec<(&String, f64)> = balances
+        .iter()
+        .map(|(currency, balance)| {
+            let synced = synced_balances.get(currency).copied().unwrap_or(0.0);
+            (currency, balance - synced)
+        })
+        .filter(|(_, delta)| *delta != 0.0)
+        .collect();
+    let playtime_delta = get_playtime_cache(player_uuid) - synced_playtime;
+
+    let db = get_db().await;
+    let mut tx = db.pool.begin().await?;
+
+    let res = sqlx::query(db.sql(
+        "UPDATE players SET nickname = $1, playtime = playtime + $2,
+            version = version + 1 WHERE uuid = $3 AND version = $4",
+    ))
+    .bind(&nickname)
+    .bind(playtime_delta)
+    .bind(player_uuid)
+    .bind(version)
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        log::warn!(
+            "Player {} ({}) was changed by another server since version {}, applying balance changes {:?} and {}s playtime on top.",
+            nickname,
+            player_uuid,
+            version,
+            balance_deltas,
+            playtime_delta
+        );
+
+        sqlx::query(db.sql(
+            "UPDATE players SET nickname = $1, playtime = playtime + $2,
+                version = version + 1 WHERE uuid = $3",
+        ))
+        .bind(&nickname)
+        .bind(playtime_delta)
+        .bind(player_uuid)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Every balance row was created when the player was loaded
+    for (currency, delta) in balance_deltas.iter() {
+        sqlx::query(db.sql(
+            "UPDATE balances SET balance = balance + $1 WHERE player_id = $2 AND currency = $3",
+        ))
+        .bind(delta)
+        .bind(id)
+        .bind(currency.as_str())
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let (stored_playtime, stored_version) = sqlx::query_as::<_, (i64, i64)>(
+        db.sql("SELECT playtime, version FROM players WHERE uuid = $1"),
+    )
+    .bind(player_uuid)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let stored_balances = sqlx::query_as::<_, (String, f64)>(
+        db.sql("SELECT currency, balance FROM balances WHERE player_id = $1"),
+    )
+    .bind(id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    // Balance changes made while the write was in flight are kept
+    if let Some(mut v) = PLAYER_CACHE.get_mut(player_uuid) {
+        for (currency, stored) in stored_balances {
+            let written = balances.get(&currency).copied().unwrap_or(0.0);
+            let current = v.balances.get(&currency).copied().unwrap_or(0.0);
+            v.balances
+                .insert(currency.clone(), stored + (current - written));
+            v.synced_balances.insert(currency, stored);
+        }
+        v.playtime = stored_playtime;
+        v.synced_playtime = stored_playtime;
+        v.synced_at = ct;
+        v.version = stored_version;
+    }
+
+    Ok(())
+}
+
+// Syncs all online players every interval, so balances changed on other
+// servers of a network show up while the player stays online
+pub fn start_sync_task(interval_secs: u64) {
+    if interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        // The first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            sync_all().await;
+        }
+    });
+}
+
+// Syncs every online player, failures are logged
+pub async fn sync_all() {
+    let uuids: Vec<String> = PLAYER_CACHE.iter().map(|v| v.key().clone()).collect();
+    for uuid in uuids {
+        if let Err(e) = sync_player(&uuid).await {
+            log::error!("Failed to sync player {}: {}", uuid, e);
+        }
+    }
+}
+
 pub async fn resolve_player(
     player_uuid: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Playtime is not updated by some function so we,
-    // will get the "updated" playtime here, which is the playtime at join
-    // plus the passed time since join
-    let pt = get_playtime(player_uuid).await;
+    // Players that were refused at join never got loaded, writing them
+    // back would wipe their stored data
+    if !is_loaded(player_uuid) {
+        return Ok(());
+    }
+
+    // Balance changes in flight would be lost once the player is removed
+    let _guard = economy::lock().await;
+    sync_locked(player_uuid).await?;
 
     let db = get_db().await;
-    sqlx::query("UPDATE players SET nickname = $1, playtime = $2, balance = $3 WHERE uuid = $4")
-        .bind(&get_nickname(player_uuid))
-        .bind(pt)
-        .bind(&get_balance(player_uuid))
+    sqlx::query(db.sql("UPDATE players SET last_seen = $1 WHERE uuid = $2"))
+        .bind(current_sec())
         .bind(player_uuid)
         .execute(&db.pool)
         .await?;
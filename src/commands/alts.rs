@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    ip,
+    moderation::{self, PunishmentKind},
+    utils::{failed_message, mark_colour, neutral_colour, player_not_found_message},
+};
+
+const NAMES: [&str; 1] = ["alts"];
+const DESCRIPTION: &str = "List accounts sharing an IP address with a player.";
+
+const ARG_PLAYER: &str = "player";
+
+struct AltsExecutor;
+
+#[async_trait]
+impl CommandExecutor for AltsExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let alts = match ip::get_alts(&uuid).await {
+            Ok(alts) => alts,
+            Err(e) => {
+                log::error!("Failed to fetch alts: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        if alts.is_empty() {
+            sender
+                .send_message(
+                    TextComponent::text(format!("No known alts of {}.", nickname))
+                        .color_rgb(neutral_colour()),
+                )
+                .await;
+            return Ok(());
+        }
+
+        let mut names = Vec::new();
+        for (alt_uuid, alt_nickname) in alts.iter() {
+            match moderation::get_active(alt_uuid, PunishmentKind::Ban).await {
+                Ok(Some(_)) => names.push(format!("{} (banned)", alt_nickname)),
+                _ => names.push(alt_nickname.clone()),
+            }
+        }
+
+        sender
+            .send_message(
+                TextComponent::text(format!("Alts of {}: {}", nickname, names.join(", ")))
+                    .color_rgb(mark_colour()),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_PLAYER, SimpleArgConsumer).execute(AltsExecutor))
+}
@@ -0,0 +1,415 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, literal, require},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::{click::ClickEvent, TextComponent};
+
+use crate::{
+    auction::{self, AuctionError, Listing, ListingKind},
+    economy::{self, Currency, EconomyError},
+    utils::{
+        current_sec, error_colour, failed_message, format_duration, mark_colour, neutral_colour,
+        success_colour,
+    },
+};
+
+const NAMES: [&str; 2] = ["auctionhouse", "ah"];
+const DESCRIPTION: &str = "Buy and sell items with other players.";
+
+const ARG_PAGE: &str = "page";
+const ARG_PRICE: &str = "price";
+const ARG_ID: &str = "id";
+const ARG_AMOUNT: &str = "amount";
+
+fn get_number_arg<T: std::str::FromStr>(
+    args: &ConsumedArgs,
+    name: &'static str,
+) -> Result<T, CommandError> {
+    let Some(Arg::Simple(value)) = args.get(&name) else {
+        return Err(CommandError::InvalidConsumption(Some(name.into())));
+    };
+
+    value
+        .parse::<T>()
+        .map_err(|_| CommandError::InvalidConsumption(Some(name.into())))
+}
+
+async fn report_error(sender: &mut CommandSender, e: AuctionError) {
+    if let AuctionError::Economy(EconomyError::Database(_)) = e {
+        log::error!("Auction house command failed: {}", e);
+        sender.send_message(failed_message()).await;
+        return;
+    }
+
+    sender
+        .send_message(TextComponent::text(e.to_string()).color_rgb(error_colour()))
+        .await;
+}
+
+async fn send_success(sender: &mut CommandSender, msg: String) {
+    sender
+        .send_message(TextComponent::text(msg).color_rgb(success_colour()))
+        .await;
+}
+
+// One line of the listing overview with a button to buy or bid
+async fn listing_line(listing: &Listing, currency: &Currency) -> TextComponent {
+    let seller = listing.seller_name.as_deref().unwrap_or(&listing.seller);
+    let ends = format_duration(listing.expires_at - current_sec());
+
+    let (offer, button, click) = match listing.price {
+        Some(price) => (
+            format!("buy for {}", currency.format(price)),
+            "[Buy]",
+            ClickEvent::RunCommand(format!("/ah buy {}", listing.id).into()),
+        ),
+        None => (
+            match listing.top_bid {
+                Some(top) => format!("highest bid {}", currency.format(top)),
+                None => format!(
+                    "bids from {}",
+                    currency.format(listing.start_bid.unwrap_or(0.0))
+                ),
+            },
+            "[Bid]",
+            ClickEvent::SuggestCommand(
+                format!(
+                    "/ah bid {} {}",
+                    listing.id,
+                    auction::minimum_bid(listing).await
+                )
+                .into(),
+            ),
+        ),
+    };
+
+    TextComponent::text(format!(
+        "#{} {}x {} from {}, {}, ends in {} ",
+        listing.id,
+        listing.quantity,
+        listing.item_key.replace('_', " "),
+        seller,
+        offer,
+        ends
+    ))
+    .color_rgb(neutral_colour())
+    .add_child(
+        TextComponent::text(button)
+            .color_rgb(mark_colour())
+            .bold()
+            .click_event(click),
+    )
+}
+
+struct AuctionListExecutor;
+
+#[async_trait]
+impl CommandExecutor for AuctionListExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let page = match args.get(&ARG_PAGE) {
+            Some(_) => get_number_arg::<i64>(args, ARG_PAGE)?.max(1),
+            None => 1,
+        };
+
+        let (listings, total) = match auction::get_listings(page).await {
+            Ok(res) => res,
+            Err(e) => {
+                log::error!("Failed to fetch auction listings: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        if listings.is_empty() {
+            sender
+                .send_message(
+                    TextComponent::text("There are no listings on this page.")
+                        .color_rgb(neutral_colour()),
+                )
+                .await;
+            return Ok(());
+        }
+
+        let pages = (total + auction::PAGE_SIZE - 1) / auction::PAGE_SIZE;
+        let currency = economy::default_currency().await;
+        let mut msg = TextComponent::text(format!("Auction house, page {} of {}:", page, pages))
+            .color_rgb(neutral_colour());
+        for listing in listings.iter() {
+            msg = msg
+                .add_child(TextComponent::text("\n"))
+                .add_child(listing_line(listing, &currency).await);
+        }
+        sender.send_message(msg).await;
+
+        Ok(())
+    }
+}
+
+struct AuctionSellExecutor {
+    auction: bool,
+}
+
+#[async_trait]
+impl CommandExecutor for AuctionSellExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        server: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let price = get_number_arg::<f64>(args, ARG_PRICE)?;
+        let player = sender.as_player().unwrap();
+        let kind = if self.auction {
+            ListingKind::Auction(price)
+        } else {
+            ListingKind::Fixed(price)
+        };
+
+        let (id, fee) = match auction::list(server, &player, kind).await {
+            Ok(res) => res,
+            Err(e) => {
+                report_error(sender, e).await;
+                return Ok(());
+            }
+        };
+
+        let currency = economy::default_currency().await;
+        let msg = if fee > 0.0 {
+            format!("Listed as #{} for a fee of {}.", id, currency.format(fee))
+        } else {
+            format!("Listed as #{}.", id)
+        };
+        send_success(sender, msg).await;
+
+        Ok(())
+    }
+}
+
+struct AuctionBuyExecutor;
+
+#[async_trait]
+impl CommandExecutor for AuctionBuyExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let id = get_number_arg::<i64>(args, ARG_ID)?;
+        let player = sender.as_player().unwrap();
+
+        let listing = match auction::buy(&player, id).await {
+            Ok(listing) => listing,
+            Err(e) => {
+                report_error(sender, e).await;
+                return Ok(());
+            }
+        };
+
+        let currency = economy::default_currency().await;
+        let msg = format!(
+            "Bought {}x {} for {}, claim it with /ah claim.",
+            listing.quantity,
+            listing.item_key.replace('_', " "),
+            currency.format(listing.price.unwrap_or(0.0))
+        );
+        send_success(sender, msg).await;
+
+        Ok(())
+    }
+}
+
+struct AuctionBidExecutor;
+
+#[async_trait]
+impl CommandExecutor for AuctionBidExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let id = get_number_arg::<i64>(args, ARG_ID)?;
+        let amount = get_number_arg::<f64>(args, ARG_AMOUNT)?;
+        let player = sender.as_player().unwrap();
+
+        let amount = match auction::bid(&player, id, amount).await {
+            Ok(amount) => amount,
+            Err(e) => {
+                report_error(sender, e).await;
+                return Ok(());
+            }
+        };
+
+        let currency = economy::default_currency().await;
+        let msg = format!(
+            "You bid {} on #{}, it is returned to your mailbox if you are outbid.",
+            currency.format(amount),
+            id
+        );
+        send_success(sender, msg).await;
+
+        Ok(())
+    }
+}
+
+struct AuctionCancelExecutor;
+
+#[async_trait]
+impl CommandExecutor for AuctionCancelExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let id = get_number_arg::<i64>(args, ARG_ID)?;
+        let player = sender.as_player().unwrap();
+
+        if let Err(e) = auction::cancel(&player.gameprofile.id.to_string(), id).await {
+            report_error(sender, e).await;
+            return Ok(());
+        }
+
+        send_success(
+            sender,
+            format!("Cancelled #{}, the item is in your mailbox.", id),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+struct AuctionMailboxExecutor;
+
+#[async_trait]
+impl CommandExecutor for AuctionMailboxExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let player = sender.as_player().unwrap();
+
+        let (items, amount) = match auction::mailbox(&player.gameprofile.id.to_string()).await {
+            Ok(res) => res,
+            Err(e) => {
+                log::error!("Failed to fetch auction mailbox: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let currency = economy::default_currency().await;
+        sender
+            .send_message(
+                TextComponent::text(format!(
+                    "Your mailbox holds {} item{} and {}.",
+                    items,
+                    if items == 1 { "" } else { "s" },
+                    currency.format(amount)
+                ))
+                .color_rgb(neutral_colour()),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+struct AuctionClaimExecutor;
+
+#[async_trait]
+impl CommandExecutor for AuctionClaimExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        server: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let player = sender.as_player().unwrap();
+
+        let claimed = match auction::claim(server, &player).await {
+            Ok(claimed) => claimed,
+            Err(e) => {
+                report_error(sender, e).await;
+                return Ok(());
+            }
+        };
+
+        let currency = economy::default_currency().await;
+        send_success(
+            sender,
+            format!(
+                "Claimed {} item{} and {}.",
+                claimed.items,
+                if claimed.items == 1 { "" } else { "s" },
+                currency.format(claimed.amount)
+            ),
+        )
+        .await;
+
+        if claimed.full {
+            report_error(sender, AuctionError::InventoryFull).await;
+        }
+
+        Ok(())
+    }
+}
+
+// TODO: Move to a proper consumer instead of SimpleArgConsumer for numbers
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        require(|sender| sender.is_player())
+            .execute(AuctionListExecutor)
+            .then(
+                literal("list")
+                    .execute(AuctionListExecutor)
+                    .then(argument(ARG_PAGE, SimpleArgConsumer).execute(AuctionListExecutor)),
+            )
+            .then(
+                literal("sell").then(
+                    argument(ARG_PRICE, SimpleArgConsumer)
+                        .execute(AuctionSellExecutor { auction: false }),
+                ),
+            )
+            .then(
+                literal("auction").then(
+                    argument(ARG_PRICE, SimpleArgConsumer)
+                        .execute(AuctionSellExecutor { auction: true }),
+                ),
+            )
+            .then(
+                literal("buy")
+                    .then(argument(ARG_ID, SimpleArgConsumer).execute(AuctionBuyExecutor)),
+            )
+            .then(
+                literal("bid").then(
+                    argument(ARG_ID, SimpleArgConsumer)
+                        .then(argument(ARG_AMOUNT, SimpleArgConsumer).execute(AuctionBidExecutor)),
+                ),
+            )
+            .then(
+                literal("cancel")
+                    .then(argument(ARG_ID, SimpleArgConsumer).execute(AuctionCancelExecutor)),
+            )
+            .then(literal("mailbox").execute(AuctionMailboxExecutor))
+            .then(literal("claim").execute(AuctionClaimExecutor)),
+    )
+}
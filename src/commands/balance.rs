@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use pumpkin::{
     command::{
-        args::{players::PlayersArgumentConsumer, Arg, ConsumedArgs},
+        args::{players::PlayersArgumentConsumer, simple::SimpleArgConsumer, Arg, ConsumedArgs},
         dispatcher::CommandError,
         tree::{
             builder::{argument, require},
@@ -13,15 +13,40 @@ use pumpkin::{
 };
 use pumpkin_util::text::TextComponent;
 
-use crate::{cache::get_balance, config::get_config, utils::neutral_colour};
+use crate::{
+    cache::get_balance,
+    economy::{self, Currency},
+    utils::{error_colour, neutral_colour},
+};
 
 const NAMES: [&str; 2] = ["balance", "bal"];
 const DESCRIPTION: &str = "Inspect player balance.";
 
 const ARG_PLAYER: &str = "player";
+const ARG_CURRENCY: &str = "currency";
 
 // TODO: Add support for offline players
 
+// Balances of an online player in the given currencies
+fn format_balances(player_uuid: &str, currencies: &[Currency]) -> String {
+    currencies
+        .iter()
+        .map(|c| c.format_short(get_balance(player_uuid, &c.name)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The requested currency, or all of them when left out
+async fn requested_currencies(args: &ConsumedArgs<'_>) -> Result<Vec<Currency>, String> {
+    match args.get(&ARG_CURRENCY) {
+        Some(Arg::Simple(name)) => economy::get_currency(name)
+            .await
+            .map(|c| vec![c])
+            .map_err(|e| e.to_string()),
+        _ => Ok(economy::currencies().await),
+    }
+}
+
 struct BalanceExecutor;
 
 #[async_trait]
@@ -36,14 +61,22 @@ impl CommandExecutor for BalanceExecutor {
             return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
         };
 
-        let player = &targets[0];
+        let currencies = match requested_currencies(args).await {
+            Ok(currencies) => currencies,
+            Err(msg) => {
+                sender
+                    .send_message(TextComponent::text(msg).color_rgb(error_colour()))
+                    .await;
+                return Ok(());
+            }
+        };
 
-        let balance = get_balance(&player.gameprofile.id.to_string());
-        let symbol = &get_config().await.value.eco_symbol;
+        let player = &targets[0];
 
         let msg = format!(
-            "{}'s balance: {}{}",
-            player.gameprofile.name, symbol, balance
+            "{}'s balance: {}",
+            player.gameprofile.name,
+            format_balances(&player.gameprofile.id.to_string(), &currencies)
         );
 
         sender
@@ -66,10 +99,13 @@ impl CommandExecutor for BalanceExecutorSelf {
     ) -> Result<(), CommandError> {
         let player = sender.as_player().unwrap();
 
-        let balance = get_balance(&player.gameprofile.id.to_string());
-        let symbol = &get_config().await.value.eco_symbol;
-
-        let msg = format!("Your balance: {}{}", symbol, balance);
+        let msg = format!(
+            "Your balance: {}",
+            format_balances(
+                &player.gameprofile.id.to_string(),
+                &economy::currencies().await
+            )
+        );
 
         sender
             .send_message(TextComponent::text(msg).color_rgb(neutral_colour()))
@@ -82,6 +118,10 @@ impl CommandExecutor for BalanceExecutorSelf {
 // TODO: Move to a proper consumer instead of SimpleArgConsumer for f64s
 pub fn init_command() -> CommandTree {
     CommandTree::new(NAMES, DESCRIPTION)
-        .then(argument(ARG_PLAYER, PlayersArgumentConsumer).execute(BalanceExecutor))
+        .then(
+            argument(ARG_PLAYER, PlayersArgumentConsumer)
+                .execute(BalanceExecutor)
+                .then(argument(ARG_CURRENCY, SimpleArgConsumer).execute(BalanceExecutor)),
+        )
         .then(require(|sender| sender.is_player()).execute(BalanceExecutorSelf))
 }
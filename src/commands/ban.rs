@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{message::MsgArgConsumer, simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    moderation,
+    utils::{failed_message, player_not_found_message, sender_name, success_colour},
+};
+
+const NAMES: [&str; 1] = ["ban"];
+const DESCRIPTION: &str = "Permanently ban a player.";
+
+const ARG_PLAYER: &str = "player";
+const ARG_REASON: &str = "reason";
+
+struct BanExecutor;
+
+#[async_trait]
+impl CommandExecutor for BanExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        server: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let reason = match args.get(&ARG_REASON) {
+            Some(Arg::Msg(reason)) => reason.clone(),
+            _ => "Banned by an operator.".to_string(),
+        };
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let issuer = sender_name(sender);
+        if let Err(e) =
+            moderation::ban_player(server, &uuid, &nickname, &reason, &issuer, None).await
+        {
+            log::error!("Failed to ban player: {}", e);
+            sender.send_message(failed_message()).await;
+            return Ok(());
+        }
+
+        sender
+            .send_message(
+                TextComponent::text(format!("Banned {}: {}", nickname, reason))
+                    .color_rgb(success_colour()),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        argument(ARG_PLAYER, SimpleArgConsumer)
+            .execute(BanExecutor)
+            .then(argument(ARG_REASON, MsgArgConsumer).execute(BanExecutor)),
+    )
+}
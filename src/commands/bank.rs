@@ -0,0 +1,341 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, literal, require},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    bank::{self, BankError},
+    cache::{find_player, get_player_info},
+    economy::{self, EconomyError},
+    utils::{
+        error_colour, failed_message, neutral_colour, player_not_found_message, success_colour,
+    },
+};
+
+const NAMES: [&str; 1] = ["bank"];
+const DESCRIPTION: &str = "Manage shared bank accounts.";
+
+const ARG_ACCOUNT: &str = "account";
+const ARG_AMOUNT: &str = "amount";
+const ARG_PLAYER: &str = "player";
+
+fn get_account_arg<'a>(args: &'a ConsumedArgs) -> Result<&'a str, CommandError> {
+    let Some(Arg::Simple(account)) = args.get(&ARG_ACCOUNT) else {
+        return Err(CommandError::InvalidConsumption(Some(ARG_ACCOUNT.into())));
+    };
+
+    Ok(account)
+}
+
+fn get_amount_arg(args: &ConsumedArgs) -> Result<f64, CommandError> {
+    let Some(Arg::Simple(amount)) = args.get(&ARG_AMOUNT) else {
+        return Err(CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())));
+    };
+
+    amount
+        .parse::<f64>()
+        .map_err(|_| CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())))
+}
+
+async fn report_error(sender: &mut CommandSender, e: BankError) {
+    if let BankError::Economy(EconomyError::Database(_)) = e {
+        log::error!("Bank command failed: {}", e);
+        sender.send_message(failed_message()).await;
+        return;
+    }
+
+    sender
+        .send_message(TextComponent::text(e.to_string()).color_rgb(error_colour()))
+        .await;
+}
+
+struct BankCreateExecutor;
+
+#[async_trait]
+impl CommandExecutor for BankCreateExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let account = get_account_arg(args)?;
+        let player = sender.as_player().unwrap();
+
+        let fee = match bank::create_account(account, &player.gameprofile.id.to_string()).await {
+            Ok(fee) => fee,
+            Err(e) => {
+                report_error(sender, e).await;
+                return Ok(());
+            }
+        };
+
+        let msg = if fee > 0.0 {
+            format!(
+                "Created bank account {} for {}.",
+                account,
+                economy::default_currency().await.format(fee)
+            )
+        } else {
+            format!("Created bank account {}.", account)
+        };
+
+        sender
+            .send_message(TextComponent::text(msg).color_rgb(success_colour()))
+            .await;
+
+        Ok(())
+    }
+}
+
+struct BankDepositExecutor;
+
+#[async_trait]
+impl CommandExecutor for BankDepositExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let account = get_account_arg(args)?;
+        let amount = get_amount_arg(args)?;
+        let player = sender.as_player().unwrap();
+
+        let balance = match bank::deposit(account, &player.gameprofile.id.to_string(), amount).await
+        {
+            Ok(balance) => balance,
+            Err(e) => {
+                report_error(sender, e).await;
+                return Ok(());
+            }
+        };
+
+        let currency = economy::default_currency().await;
+        sender
+            .send_message(
+                TextComponent::text(format!(
+                    "Deposited {} into {}, its balance is now {}.",
+                    currency.format(amount),
+                    account,
+                    currency.format(balance)
+                ))
+                .color_rgb(success_colour()),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+struct BankWithdrawExecutor;
+
+#[async_trait]
+impl CommandExecutor for BankWithdrawExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let account = get_account_arg(args)?;
+        let amount = get_amount_arg(args)?;
+        let player = sender.as_player().unwrap();
+
+        let balance =
+            match bank::withdraw(account, &player.gameprofile.id.to_string(), amount).await {
+                Ok(balance) => balance,
+                Err(e) => {
+                    report_error(sender, e).await;
+                    return Ok(());
+                }
+            };
+
+        let currency = economy::default_currency().await;
+        sender
+            .send_message(
+                TextComponent::text(format!(
+                    "Withdrew {} from {}, its balance is now {}.",
+                    currency.format(amount),
+                    account,
+                    currency.format(balance)
+                ))
+                .color_rgb(success_colour()),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+struct BankBalanceExecutor;
+
+#[async_trait]
+impl CommandExecutor for BankBalanceExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let account = get_account_arg(args)?;
+        let player = sender.as_player().unwrap();
+
+        let res = async {
+            let account = bank::get_account(account).await?;
+            if !bank::is_member(&account, &player.gameprofile.id.to_string()).await? {
+                return Err(BankError::NotMember);
+            }
+            let members = bank::get_members(&account).await?;
+            Ok((account, members))
+        }
+        .await;
+
+        let (account, members) = match res {
+            Ok(res) => res,
+            Err(e) => {
+                report_error(sender, e).await;
+                return Ok(());
+            }
+        };
+
+        // Members are shown by name, falling back to the UUID
+        let mut names = Vec::new();
+        for uuid in std::iter::once(&account.owner).chain(members.iter()) {
+            let name = match get_player_info(uuid).await {
+                Ok(Some(info)) => info.nickname,
+                _ => uuid.clone(),
+            };
+            names.push(name);
+        }
+
+        sender
+            .send_message(
+                TextComponent::text(format!(
+                    "Bank account {}: {}\nOwner: {}\nMembers: {}",
+                    account.name,
+                    economy::default_currency().await.format(account.balance),
+                    names[0],
+                    if names.len() > 1 {
+                        names[1..].join(", ")
+                    } else {
+                        "none".to_string()
+                    }
+                ))
+                .color_rgb(neutral_colour()),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+struct BankMemberExecutor {
+    add: bool,
+}
+
+#[async_trait]
+impl CommandExecutor for BankMemberExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let account = get_account_arg(args)?;
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+        let player = sender.as_player().unwrap();
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let owner_uuid = player.gameprofile.id.to_string();
+        let res = if self.add {
+            bank::add_member(account, &owner_uuid, &uuid).await
+        } else {
+            bank::remove_member(account, &owner_uuid, &uuid).await
+        };
+
+        if let Err(e) = res {
+            report_error(sender, e).await;
+            return Ok(());
+        }
+
+        let msg = if self.add {
+            format!("Added {} to bank account {}.", nickname, account)
+        } else {
+            format!("Removed {} from bank account {}.", nickname, account)
+        };
+
+        sender
+            .send_message(TextComponent::text(msg).color_rgb(success_colour()))
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        require(|sender| sender.is_player())
+            .then(
+                literal("create")
+                    .then(argument(ARG_ACCOUNT, SimpleArgConsumer).execute(BankCreateExecutor)),
+            )
+            .then(
+                literal("deposit")
+                    .then(argument(ARG_ACCOUNT, SimpleArgConsumer).then(
+                        argument(ARG_AMOUNT, SimpleArgConsumer).execute(BankDepositExecutor),
+                    )),
+            )
+            .then(
+                literal("withdraw").then(
+                    argument(ARG_ACCOUNT, SimpleArgConsumer).then(
+                        argument(ARG_AMOUNT, SimpleArgConsumer).execute(BankWithdrawExecutor),
+                    ),
+                ),
+            )
+            .then(
+                literal("balance")
+                    .then(argument(ARG_ACCOUNT, SimpleArgConsumer).execute(BankBalanceExecutor)),
+            )
+            .then(
+                literal("addmember").then(
+                    argument(ARG_ACCOUNT, SimpleArgConsumer).then(
+                        argument(ARG_PLAYER, SimpleArgConsumer)
+                            .execute(BankMemberExecutor { add: true }),
+                    ),
+                ),
+            )
+            .then(
+                literal("removemember").then(
+                    argument(ARG_ACCOUNT, SimpleArgConsumer).then(
+                        argument(ARG_PLAYER, SimpleArgConsumer)
+                            .execute(BankMemberExecutor { add: false }),
+                    ),
+                ),
+            ),
+    )
+}
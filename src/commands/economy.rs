@@ -14,9 +14,8 @@ use pumpkin::{
 use pumpkin_util::text::TextComponent;
 
 use crate::{
-    cache::{get_balance, update_balance},
-    config::get_config,
-    utils::success_colour,
+    economy::{self, Currency, EconomyError},
+    utils::{error_colour, success_colour},
 };
 
 const NAMES: [&str; 2] = ["economy", "eco"];
@@ -24,9 +23,38 @@ const DESCRIPTION: &str = "Manage player economy.";
 
 const ARG_PLAYER: &str = "player";
 const ARG_AMOUNT: &str = "amount";
+const ARG_CURRENCY: &str = "currency";
 
 // TODO: Add support for offline players
 
+// Lets the sender know which balances could not be changed
+async fn report_failure(sender: &mut CommandSender, name: &str, res: Result<(), EconomyError>) {
+    if let Err(e) = res {
+        sender
+            .send_message(TextComponent::text(format!("{}: {}", name, e)).color_rgb(error_colour()))
+            .await;
+    }
+}
+
+// The currency given to the command or the default one, None after letting
+// the sender know the currency does not exist
+async fn get_currency_arg(sender: &mut CommandSender, args: &ConsumedArgs<'_>) -> Option<Currency> {
+    let name = match args.get(&ARG_CURRENCY) {
+        Some(Arg::Simple(name)) => Some(*name),
+        _ => None,
+    };
+
+    match economy::currency_or_default(name).await {
+        Ok(currency) => Some(currency),
+        Err(e) => {
+            sender
+                .send_message(TextComponent::text(e.to_string()).color_rgb(error_colour()))
+                .await;
+            None
+        }
+    }
+}
+
 struct EcoSetExecutor;
 
 #[async_trait]
@@ -50,12 +78,22 @@ impl CommandExecutor for EcoSetExecutor {
             .parse::<f64>()
             .map_err(|_| CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())))?;
 
-        // We need to add the amount to the player's balance
+        let Some(currency) = get_currency_arg(sender, args).await else {
+            return Ok(());
+        };
+
         for target in targets {
-            update_balance(&target.gameprofile.id.to_string(), amount);
+            let res =
+                economy::set_balance(&target.gameprofile.id.to_string(), &currency.name, amount)
+                    .await;
+            report_failure(sender, &target.gameprofile.name, res).await;
         }
 
-        let msg = format!("Balance{} set.", if targets.len() == 1 { "" } else { "s" });
+        let msg = format!(
+            "Balance{} set to {}.",
+            if targets.len() == 1 { "" } else { "s" },
+            currency.format(amount)
+        );
         sender
             .send_message(TextComponent::text(msg).color_rgb(success_colour()))
             .await;
@@ -87,20 +125,21 @@ impl CommandExecutor for EcoAddExecutor {
             .parse::<f64>()
             .map_err(|_| CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())))?;
 
-        // We need to check if the amount is negative
-        if amount < 0.0 {
-            return Err(CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())));
-        }
+        let Some(currency) = get_currency_arg(sender, args).await else {
+            return Ok(());
+        };
 
         // We need to add the amount to the player's balance
         for target in targets {
-            let target_balance = get_balance(&target.gameprofile.id.to_string());
-            update_balance(&target.gameprofile.id.to_string(), target_balance + amount);
+            let res =
+                economy::deposit(&target.gameprofile.id.to_string(), &currency.name, amount).await;
+            report_failure(sender, &target.gameprofile.name, res.map(|_| ())).await;
         }
 
         let msg = format!(
-            "Balance{} increased.",
-            if targets.len() == 1 { "" } else { "s" }
+            "Balance{} increased by {}.",
+            if targets.len() == 1 { "" } else { "s" },
+            currency.format(amount)
         );
         sender
             .send_message(TextComponent::text(msg).color_rgb(success_colour()))
@@ -133,20 +172,21 @@ impl CommandExecutor for EcoRemoveExecutor {
             .parse::<f64>()
             .map_err(|_| CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())))?;
 
-        // We need to check if the amount is negative
-        if amount < 0.0 {
-            return Err(CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())));
-        }
+        let Some(currency) = get_currency_arg(sender, args).await else {
+            return Ok(());
+        };
 
-        // We need to add the amount to the player's balance
+        // Balances are never taken below zero
         for target in targets {
-            let target_balance = get_balance(&target.gameprofile.id.to_string());
-            update_balance(&target.gameprofile.id.to_string(), target_balance - amount);
+            let res =
+                economy::withdraw(&target.gameprofile.id.to_string(), &currency.name, amount).await;
+            report_failure(sender, &target.gameprofile.name, res.map(|_| ())).await;
         }
 
         let msg = format!(
-            "Balance{} reduced.",
-            if targets.len() == 1 { "" } else { "s" }
+            "Balance{} reduced by {}.",
+            if targets.len() == 1 { "" } else { "s" },
+            currency.format(amount)
         );
         sender
             .send_message(TextComponent::text(msg).color_rgb(success_colour()))
@@ -170,16 +210,24 @@ impl CommandExecutor for EcoResetExecutor {
             return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
         };
 
-        let default_amount = get_config().await.value.eco_starting_balance;
+        let Some(currency) = get_currency_arg(sender, args).await else {
+            return Ok(());
+        };
 
-        // We need to add the amount to the player's balance
         for target in targets {
-            update_balance(&target.gameprofile.id.to_string(), default_amount);
+            let res = economy::set_balance(
+                &target.gameprofile.id.to_string(),
+                &currency.name,
+                currency.starting_balance,
+            )
+            .await;
+            report_failure(sender, &target.gameprofile.name, res).await;
         }
 
         let msg = format!(
-            "Balance{} reset.",
-            if targets.len() == 1 { "" } else { "s" }
+            "Balance{} reset to {}.",
+            if targets.len() == 1 { "" } else { "s" },
+            currency.format(currency.starting_balance)
         );
         sender
             .send_message(TextComponent::text(msg).color_rgb(success_colour()))
@@ -194,24 +242,36 @@ pub fn init_command() -> CommandTree {
     CommandTree::new(NAMES, DESCRIPTION)
         .then(
             literal("set").then(
-                argument(ARG_PLAYER, PlayersArgumentConsumer)
-                    .then(argument(ARG_AMOUNT, SimpleArgConsumer).execute(EcoSetExecutor)),
+                argument(ARG_PLAYER, PlayersArgumentConsumer).then(
+                    argument(ARG_AMOUNT, SimpleArgConsumer)
+                        .execute(EcoSetExecutor)
+                        .then(argument(ARG_CURRENCY, SimpleArgConsumer).execute(EcoSetExecutor)),
+                ),
             ),
         )
         .then(
             literal("add").then(
-                argument(ARG_PLAYER, PlayersArgumentConsumer)
-                    .then(argument(ARG_AMOUNT, SimpleArgConsumer).execute(EcoAddExecutor)),
+                argument(ARG_PLAYER, PlayersArgumentConsumer).then(
+                    argument(ARG_AMOUNT, SimpleArgConsumer)
+                        .execute(EcoAddExecutor)
+                        .then(argument(ARG_CURRENCY, SimpleArgConsumer).execute(EcoAddExecutor)),
+                ),
             ),
         )
         .then(
             literal("remove").then(
-                argument(ARG_PLAYER, PlayersArgumentConsumer)
-                    .then(argument(ARG_AMOUNT, SimpleArgConsumer).execute(EcoRemoveExecutor)),
+                argument(ARG_PLAYER, PlayersArgumentConsumer).then(
+                    argument(ARG_AMOUNT, SimpleArgConsumer)
+                        .execute(EcoRemoveExecutor)
+                        .then(argument(ARG_CURRENCY, SimpleArgConsumer).execute(EcoRemoveExecutor)),
+                ),
             ),
         )
         .then(
-            literal("reset")
-                .then(argument(ARG_PLAYER, PlayersArgumentConsumer).execute(EcoResetExecutor)),
+            literal("reset").then(
+                argument(ARG_PLAYER, PlayersArgumentConsumer)
+                    .execute(EcoResetExecutor)
+                    .then(argument(ARG_CURRENCY, SimpleArgConsumer).execute(EcoResetExecutor)),
+            ),
         )
 }
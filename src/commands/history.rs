@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    moderation,
+    utils::{failed_message, mark_colour, neutral_colour, player_not_found_message},
+};
+
+const NAMES: [&str; 1] = ["history"];
+const DESCRIPTION: &str = "List past punishments of a player.";
+
+const ARG_PLAYER: &str = "player";
+
+struct HistoryExecutor;
+
+#[async_trait]
+impl CommandExecutor for HistoryExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let history = match moderation::get_history(&uuid).await {
+            Ok(history) => history,
+            Err(e) => {
+                log::error!("Failed to fetch punishment history: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        if history.is_empty() {
+            sender
+                .send_message(
+                    TextComponent::text(format!("{} has no punishments.", nickname))
+                        .color_rgb(neutral_colour()),
+                )
+                .await;
+            return Ok(());
+        }
+
+        sender
+            .send_message(
+                TextComponent::text(format!("Punishments of {}:", nickname))
+                    .color_rgb(neutral_colour()),
+            )
+            .await;
+
+        for punishment in history.iter() {
+            sender
+                .send_message(TextComponent::text(punishment.summary()).color_rgb(mark_colour()))
+                .await;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_PLAYER, SimpleArgConsumer).execute(HistoryExecutor))
+}
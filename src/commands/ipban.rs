@@ -0,0 +1,110 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{message::MsgArgConsumer, simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    ip,
+    utils::{error_colour, failed_message, player_not_found_message, sender_name, success_colour},
+};
+
+const NAMES: [&str; 1] = ["ipban"];
+const DESCRIPTION: &str = "Ban an IP address or the last address of a player.";
+
+const ARG_TARGET: &str = "target";
+const ARG_REASON: &str = "reason";
+
+struct IpbanExecutor;
+
+#[async_trait]
+impl CommandExecutor for IpbanExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        server: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(target)) = args.get(&ARG_TARGET) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_TARGET.into())));
+        };
+
+        let reason = match args.get(&ARG_REASON) {
+            Some(Arg::Msg(reason)) => reason.clone(),
+            _ => "Banned by an operator.".to_string(),
+        };
+
+        // Either an address or a player whose last address gets banned
+        let address = match target.parse::<IpAddr>() {
+            Ok(address) => address.to_string(),
+            Err(_) => {
+                let (uuid, nickname) = match find_player(target).await {
+                    Ok(Some(player)) => player,
+                    Ok(None) => {
+                        sender.send_message(player_not_found_message(target)).await;
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        log::error!("Failed to look up player {}: {}", target, e);
+                        sender.send_message(failed_message()).await;
+                        return Ok(());
+                    }
+                };
+
+                match ip::get_last_ip(&uuid).await {
+                    Ok(Some(address)) => address,
+                    Ok(None) => {
+                        sender
+                            .send_message(
+                                TextComponent::text(format!(
+                                    "No known IP address for {}.",
+                                    nickname
+                                ))
+                                .color_rgb(error_colour()),
+                            )
+                            .await;
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        log::error!("Failed to look up IP address: {}", e);
+                        sender.send_message(failed_message()).await;
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        let issuer = sender_name(sender);
+        if let Err(e) = ip::ban_ip(server, &address, &reason, &issuer, None).await {
+            log::error!("Failed to ban IP address: {}", e);
+            sender.send_message(failed_message()).await;
+            return Ok(());
+        }
+
+        sender
+            .send_message(
+                TextComponent::text(format!("Banned IP address of {}: {}", target, reason))
+                    .color_rgb(success_colour()),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        argument(ARG_TARGET, SimpleArgConsumer)
+            .execute(IpbanExecutor)
+            .then(argument(ARG_REASON, MsgArgConsumer).execute(IpbanExecutor)),
+    )
+}
@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{message::MsgArgConsumer, players::PlayersArgumentConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    net::DisconnectReason,
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    moderation::{self, PunishmentKind},
+    utils::{sender_name, success_colour},
+};
+
+const NAMES: [&str; 1] = ["kick"];
+const DESCRIPTION: &str = "Kick a player from the server.";
+
+const ARG_PLAYER: &str = "player";
+const ARG_REASON: &str = "reason";
+
+struct KickExecutor;
+
+#[async_trait]
+impl CommandExecutor for KickExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Players(targets)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let reason = match args.get(&ARG_REASON) {
+            Some(Arg::Msg(reason)) => reason.clone(),
+            _ => "Kicked by an operator.".to_string(),
+        };
+
+        let issuer = sender_name(sender);
+        for target in targets {
+            // Record the kick for the history, the kick itself happens regardless
+            if let Err(e) = moderation::add_punishment(
+                &target.gameprofile.id.to_string(),
+                PunishmentKind::Kick,
+                &reason,
+                &issuer,
+                None,
+            )
+            .await
+            {
+                log::error!("Failed to record kick: {}", e);
+            }
+
+            target
+                .kick(DisconnectReason::Kicked, moderation::kick_message(&reason))
+                .await;
+            log::info!("{} kicked {}: {}", issuer, target.gameprofile.name, reason);
+        }
+
+        let msg = format!(
+            "Kicked {} player{}.",
+            targets.len(),
+            if targets.len() == 1 { "" } else { "s" }
+        );
+        sender
+            .send_message(TextComponent::text(msg).color_rgb(success_colour()))
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        argument(ARG_PLAYER, PlayersArgumentConsumer)
+            .execute(KickExecutor)
+            .then(argument(ARG_REASON, MsgArgConsumer).execute(KickExecutor)),
+    )
+}
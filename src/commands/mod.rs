@@ -1,8 +1,31 @@
+pub mod alts;
+pub mod auction;
 pub mod balance;
+pub mod ban;
+pub mod bank;
 pub mod economy;
+pub mod history;
+pub mod ipban;
+pub mod kick;
+pub mod mute;
+pub mod nick;
 pub mod pay;
+pub mod paytoggle;
 pub mod playtime;
+pub mod realname;
 pub mod saveall;
+pub mod seen;
+pub mod sell;
+pub mod servercore;
 pub mod setspawn;
+pub mod shop;
 pub mod staffchat;
+pub mod tempban;
+pub mod tempmute;
+pub mod unban;
+pub mod unmute;
 pub mod vanish;
+pub mod warn;
+pub mod warnings;
+pub mod whois;
+pub mod worth;
@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, require},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::update_display_name,
+    nick,
+    utils::{error_colour, failed_message, neutral_colour, success_colour},
+};
+
+const NAMES: [&str; 2] = ["nick", "nickname"];
+const DESCRIPTION: &str = "Set a custom nickname, or remove it with off.";
+
+const ARG_NAME: &str = "name";
+
+struct NickExecutor;
+
+#[async_trait]
+impl CommandExecutor for NickExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_NAME) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_NAME.into())));
+        };
+
+        let player = sender.as_player().unwrap();
+        let uuid_s = player.gameprofile.id.to_string();
+
+        if name.eq_ignore_ascii_case("off") {
+            if nick::save_nick(&uuid_s, None).await.is_err() {
+                player.send_system_message(&failed_message()).await;
+                return Ok(());
+            }
+
+            update_display_name(&uuid_s, None);
+            player
+                .send_system_message(
+                    &TextComponent::text("Your nickname has been removed.")
+                        .color_rgb(neutral_colour()),
+                )
+                .await;
+            return Ok(());
+        }
+
+        let allow_colour = player.has_permission("servercore:nick.color").await;
+        let allow_format = player.has_permission("servercore:nick.format").await;
+
+        if let Err(e) = nick::validate(&uuid_s, name, allow_colour, allow_format).await {
+            player
+                .send_system_message(&TextComponent::text(e.message()).color_rgb(error_colour()))
+                .await;
+            return Ok(());
+        }
+
+        if let Err(e) = nick::save_nick(&uuid_s, Some(name)).await {
+            player
+                .send_system_message(&TextComponent::text(e.message()).color_rgb(error_colour()))
+                .await;
+            return Ok(());
+        }
+
+        update_display_name(&uuid_s, Some(name.to_string()));
+        player
+            .send_system_message(
+                &TextComponent::text("Your nickname is now ")
+                    .color_rgb(success_colour())
+                    .add_child(nick::render(name)),
+            )
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        require(|sender| sender.is_player())
+            .then(argument(ARG_NAME, SimpleArgConsumer).execute(NickExecutor)),
+    )
+}
@@ -1,22 +1,25 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use pumpkin::{
     command::{
         args::{players::PlayersArgumentConsumer, simple::SimpleArgConsumer, Arg, ConsumedArgs},
         dispatcher::CommandError,
         tree::{
-            builder::{argument, require},
+            builder::{argument, literal, require},
             CommandTree,
         },
         CommandExecutor, CommandSender,
     },
+    entity::player::Player,
     server::Server,
 };
-use pumpkin_util::text::TextComponent;
+use pumpkin_util::text::{click::ClickEvent, TextComponent};
 
 use crate::{
-    cache::{get_balance, update_balance},
     config::get_config,
-    utils::{neutral_colour, success_colour},
+    economy::{self, Currency, PendingPayment},
+    utils::{current_sec, error_colour, mark_colour, neutral_colour, success_colour},
 };
 
 const NAMES: [&str; 1] = ["pay"];
@@ -24,9 +27,135 @@ const DESCRIPTION: &str = "Pay someone from your balance.";
 
 const ARG_PLAYER: &str = "player";
 const ARG_AMOUNT: &str = "amount";
+const ARG_CURRENCY: &str = "currency";
+
+// Seconds a payment waits for /pay confirm
+const CONFIRM_TIMEOUT: i64 = 30;
 
 // TODO: Add support for offline players
 
+fn cooldown_message(cooldown: i64) -> String {
+    format!(
+        "You can pay again in {} second{}.",
+        cooldown,
+        if cooldown == 1 { "" } else { "s" }
+    )
+}
+
+// Checks the payment rules that do not depend on balances, returns the
+// message explaining why the payment is refused
+async fn check_payment(
+    player: &Player,
+    target: &Player,
+    currency: &Currency,
+    amount: f64,
+) -> Option<String> {
+    let config = get_config().await;
+
+    if amount < config.value.pay_minimum {
+        return Some(format!(
+            "The minimum payment is {}.",
+            currency.format(config.value.pay_minimum)
+        ));
+    }
+
+    let cooldown = economy::pay_cooldown_remaining(&player.gameprofile.id.to_string()).await;
+    if cooldown > 0 {
+        return Some(cooldown_message(cooldown));
+    }
+
+    match economy::accepts_payments(&target.gameprofile.id.to_string()).await {
+        Ok(true) => None,
+        Ok(false) => Some(format!(
+            "{} is not accepting payments.",
+            target.gameprofile.name
+        )),
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+async fn send_payment(player: &Player, target: &Player, currency: &Currency, amount: f64) {
+    if let Some(msg) = check_payment(player, target, currency, amount).await {
+        player
+            .send_system_message(&TextComponent::text(msg).color_rgb(error_colour()))
+            .await;
+        return;
+    }
+
+    // The check above only warns early, the cooldown is taken here
+    let previous = match economy::reserve_payment(&player.gameprofile.id.to_string()).await {
+        Ok(previous) => previous,
+        Err(cooldown) => {
+            player
+                .send_system_message(
+                    &TextComponent::text(cooldown_message(cooldown)).color_rgb(error_colour()),
+                )
+                .await;
+            return;
+        }
+    };
+
+    let taxed = !player.has_permission("servercore:pay.taxexempt").await;
+
+    // Amount, funds and the target are checked by the economy
+    let payment = match economy::transfer_taxed(
+        &player.gameprofile.id.to_string(),
+        &target.gameprofile.id.to_string(),
+        &currency.name,
+        amount,
+        taxed,
+    )
+    .await
+    {
+        Ok(payment) => payment,
+        Err(e) => {
+            economy::release_payment(&player.gameprofile.id.to_string(), previous);
+            player
+                .send_system_message(&TextComponent::text(e.to_string()).color_rgb(error_colour()))
+                .await;
+            return;
+        }
+    };
+
+    let (sent_msg, received_msg) = if payment.tax > 0.0 {
+        (
+            format!(
+                "You paid {} to {}, of which {} was tax.",
+                currency.format(payment.amount),
+                target.gameprofile.name,
+                currency.format(payment.tax)
+            ),
+            format!(
+                "{} paid you {} ({} tax deducted).",
+                player.gameprofile.name,
+                currency.format(payment.amount - payment.tax),
+                currency.format(payment.tax)
+            ),
+        )
+    } else {
+        (
+            format!(
+                "You paid {} to {}.",
+                currency.format(payment.amount),
+                target.gameprofile.name
+            ),
+            format!(
+                "{} paid you {}.",
+                player.gameprofile.name,
+                currency.format(payment.amount)
+            ),
+        )
+    };
+
+    player
+        .send_system_message(&TextComponent::text(sent_msg).color_rgb(success_colour()))
+        .await;
+
+    target
+        .send_system_message(&TextComponent::text(received_msg).color_rgb(neutral_colour()))
+        .await;
+}
+
 struct PayExecutor;
 
 #[async_trait]
@@ -54,41 +183,99 @@ impl CommandExecutor for PayExecutor {
             .parse::<f64>()
             .map_err(|_| CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())))?;
 
-        // We need to check if the amount is negative
-        if amount < 0.0 {
-            return Err(CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())));
+        let player = sender.as_player().unwrap();
+        let target = targets.first().unwrap();
+
+        let name = match args.get(&ARG_CURRENCY) {
+            Some(Arg::Simple(name)) => Some(*name),
+            _ => None,
+        };
+        let currency = match economy::currency_or_default(name).await {
+            Ok(currency) => currency,
+            Err(e) => {
+                player
+                    .send_system_message(
+                        &TextComponent::text(e.to_string()).color_rgb(error_colour()),
+                    )
+                    .await;
+                return Ok(());
+            }
+        };
+
+        if let Some(msg) = check_payment(&player, target, &currency, amount).await {
+            player
+                .send_system_message(&TextComponent::text(msg).color_rgb(error_colour()))
+                .await;
+            return Ok(());
         }
 
-        let player = sender.as_player().unwrap();
-        let payer_balance = get_balance(&player.gameprofile.id.to_string());
+        // Large payments wait for confirmation to catch typos
+        let config = get_config().await;
+        let threshold = config.value.pay_confirm_threshold;
+        if threshold > 0.0 && amount >= threshold {
+            economy::set_pending_payment(
+                &player.gameprofile.id.to_string(),
+                PendingPayment {
+                    target: Arc::clone(target),
+                    currency: currency.clone(),
+                    amount,
+                    expires_at: current_sec() + CONFIRM_TIMEOUT,
+                },
+            );
 
-        // Check if the player can afford to pay
-        if payer_balance < amount {
-            return Err(CommandError::InvalidConsumption(Some(ARG_AMOUNT.into())));
+            let msg = TextComponent::text(format!(
+                "You are about to pay {} to {}. ",
+                currency.format(amount),
+                target.gameprofile.name
+            ))
+            .color_rgb(neutral_colour())
+            .add_child(
+                TextComponent::text("[Confirm]")
+                    .color_rgb(mark_colour())
+                    .bold()
+                    .click_event(ClickEvent::RunCommand("/pay confirm".into())),
+            )
+            .add_child(
+                TextComponent::text(format!(
+                    " or run /pay confirm within {} seconds.",
+                    CONFIRM_TIMEOUT
+                ))
+                .color_rgb(neutral_colour()),
+            );
+            player.send_system_message(&msg).await;
+            return Ok(());
         }
 
-        let target = targets.first().unwrap();
-        let target_balance = get_balance(&target.gameprofile.id.to_string());
+        send_payment(&player, target, &currency, amount).await;
 
-        // Update the balances
-        update_balance(&player.gameprofile.id.to_string(), payer_balance - amount);
-        update_balance(&target.gameprofile.id.to_string(), target_balance + amount);
+        Ok(())
+    }
+}
 
-        let symbol = get_config().await.value.eco_symbol.clone();
+struct PayConfirmExecutor;
 
-        let sent_msg = format!(
-            "You paid {}{} to {}.",
-            symbol, amount, target.gameprofile.name
-        );
-        let received_msg = format!("{} paid you {}{}.", player.gameprofile.name, symbol, amount);
+#[async_trait]
+impl CommandExecutor for PayConfirmExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let player = sender.as_player().unwrap();
 
-        player
-            .send_system_message(&TextComponent::text(sent_msg).color_rgb(success_colour()))
-            .await;
+        let Some(pending) = economy::take_pending_payment(&player.gameprofile.id.to_string())
+        else {
+            player
+                .send_system_message(
+                    &TextComponent::text("You have no payment to confirm.")
+                        .color_rgb(error_colour()),
+                )
+                .await;
+            return Ok(());
+        };
 
-        target
-            .send_system_message(&TextComponent::text(received_msg).color_rgb(neutral_colour()))
-            .await;
+        send_payment(&player, &pending.target, &pending.currency, pending.amount).await;
 
         Ok(())
     }
@@ -97,9 +284,14 @@ impl CommandExecutor for PayExecutor {
 // TODO: Move to a proper consumer instead of SimpleArgConsumer for f64s
 pub fn init_command() -> CommandTree {
     CommandTree::new(NAMES, DESCRIPTION).then(
-        require(|sender| sender.is_player()).then(
-            argument(ARG_PLAYER, PlayersArgumentConsumer)
-                .then(argument(ARG_AMOUNT, SimpleArgConsumer).execute(PayExecutor)),
-        ),
+        require(|sender| sender.is_player())
+            .then(literal("confirm").execute(PayConfirmExecutor))
+            .then(
+                argument(ARG_PLAYER, PlayersArgumentConsumer).then(
+                    argument(ARG_AMOUNT, SimpleArgConsumer)
+                        .execute(PayExecutor)
+                        .then(argument(ARG_CURRENCY, SimpleArgConsumer).execute(PayExecutor)),
+                ),
+            ),
     )
 }
@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::ConsumedArgs,
+        dispatcher::CommandError,
+        tree::{builder::require, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    economy,
+    utils::{failed_message, neutral_colour},
+};
+
+const NAMES: [&str; 1] = ["paytoggle"];
+const DESCRIPTION: &str = "Toggle whether other players can pay you.";
+
+struct PaytoggleExecutor;
+
+#[async_trait]
+impl CommandExecutor for PaytoggleExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let player = sender.as_player().unwrap();
+        let uuid_s = player.gameprofile.id.to_string();
+
+        let res = match economy::accepts_payments(&uuid_s).await {
+            Ok(accepts) => economy::set_accepts_payments(&uuid_s, !accepts)
+                .await
+                .map(|_| !accepts),
+            Err(e) => Err(e),
+        };
+
+        let msg = match res {
+            Ok(true) => "You are accepting payments again.",
+            Ok(false) => "You are no longer accepting payments.",
+            Err(e) => {
+                log::error!("Failed to toggle payments: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        sender
+            .send_message(TextComponent::text(msg).color_rgb(neutral_colour()))
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(require(|sender| sender.is_player()).execute(PaytoggleExecutor))
+}
@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    nick,
+    utils::{error_colour, failed_message, neutral_colour},
+};
+
+const NAMES: [&str; 1] = ["realname"];
+const DESCRIPTION: &str = "Find the account name behind a nickname.";
+
+const ARG_NICK: &str = "nickname";
+
+struct RealnameExecutor;
+
+#[async_trait]
+impl CommandExecutor for RealnameExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(nickname)) = args.get(&ARG_NICK) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_NICK.into())));
+        };
+
+        let tc = match nick::find_by_nick(nickname).await {
+            Ok(Some((_, name))) => TextComponent::text(format!("{} is {}.", nickname, name))
+                .color_rgb(neutral_colour()),
+            Ok(None) => TextComponent::text(format!("Nobody is nicknamed {}.", nickname))
+                .color_rgb(error_colour()),
+            Err(e) => {
+                log::error!("Failed to look up nickname: {}", e);
+                failed_message()
+            }
+        };
+
+        sender.send_message(tc).await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_NICK, SimpleArgConsumer).execute(RealnameExecutor))
+}
@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::{find_player, get_join_time, get_player_info},
+    utils::{
+        current_sec, failed_message, format_duration, neutral_colour, player_not_found_message,
+    },
+};
+
+const NAMES: [&str; 1] = ["seen"];
+const DESCRIPTION: &str = "See when a player was last online.";
+
+const ARG_PLAYER: &str = "player";
+
+struct SeenExecutor;
+
+#[async_trait]
+impl CommandExecutor for SeenExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let msg = match get_join_time(&uuid) {
+            Some(jt) => format!(
+                "{} is online, for {}.",
+                nickname,
+                format_duration(current_sec() - jt)
+            ),
+            None => match get_player_info(&uuid)
+                .await
+                .ok()
+                .flatten()
+                .and_then(|info| info.last_seen)
+            {
+                Some(last_seen) => format!(
+                    "{} was last seen {} ago.",
+                    nickname,
+                    format_duration(current_sec() - last_seen)
+                ),
+                None => format!("{} is offline, last seen unknown.", nickname),
+            },
+        };
+
+        sender
+            .send_message(TextComponent::text(msg).color_rgb(neutral_colour()))
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_PLAYER, SimpleArgConsumer).execute(SeenExecutor))
+}
@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, literal, require},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    economy::{self, EconomyError},
+    shop::{
+        worth::{self, SellTarget},
+        ShopError,
+    },
+    utils::{error_colour, failed_message, success_colour},
+};
+
+const NAMES: [&str; 1] = ["sell"];
+const DESCRIPTION: &str = "Sell items from your inventory.";
+
+const ARG_ITEM: &str = "item";
+const ARG_AMOUNT: &str = "amount";
+
+fn get_amount_arg(args: &ConsumedArgs) -> Result<Option<u32>, CommandError> {
+    match args.get(&ARG_AMOUNT) {
+        Some(Arg::Simple(amount)) => amount
+            .parse::<u32>()
+            .map(Some)
+            .map_err(|_| CommandError::InvalidConsumption(Some(ARG_AMOUNT.into()))),
+        _ => Ok(None),
+    }
+}
+
+async fn report_error(sender: &mut CommandSender, e: ShopError) {
+    if let ShopError::Economy(EconomyError::Database(_)) = e {
+        log::error!("Sell command failed: {}", e);
+        sender.send_message(failed_message()).await;
+        return;
+    }
+
+    sender
+        .send_message(TextComponent::text(e.to_string()).color_rgb(error_colour()))
+        .await;
+}
+
+async fn sell(
+    sender: &mut CommandSender,
+    target: SellTarget,
+    amount: Option<u32>,
+) -> Result<(), CommandError> {
+    let player = sender.as_player().unwrap();
+
+    let sale = match worth::sell(&player, target, amount).await {
+        Ok(sale) => sale,
+        Err(e) => {
+            report_error(sender, e).await;
+            return Ok(());
+        }
+    };
+
+    let currency = economy::default_currency().await;
+    let items = sale
+        .items
+        .iter()
+        .map(|i| format!("{} {}", i.quantity, worth::display_name(i.item)))
+        .collect::<Vec<_>>();
+    sender
+        .send_message(
+            TextComponent::text(format!(
+                "Sold {} for {}, your balance is now {}.",
+                items.join(", "),
+                currency.format(sale.price),
+                currency.format(sale.balance)
+            ))
+            .color_rgb(success_colour()),
+        )
+        .await;
+
+    Ok(())
+}
+
+struct SellHandExecutor;
+
+#[async_trait]
+impl CommandExecutor for SellHandExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let amount = get_amount_arg(args)?;
+        sell(sender, SellTarget::Hand, amount).await
+    }
+}
+
+struct SellAllExecutor;
+
+#[async_trait]
+impl CommandExecutor for SellAllExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        sell(sender, SellTarget::All, None).await
+    }
+}
+
+struct SellItemExecutor;
+
+#[async_trait]
+impl CommandExecutor for SellItemExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_ITEM) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_ITEM.into())));
+        };
+        let amount = get_amount_arg(args)?;
+
+        let Some(item) = worth::find_item(name) else {
+            sender
+                .send_message(
+                    TextComponent::text("That item does not exist.").color_rgb(error_colour()),
+                )
+                .await;
+            return Ok(());
+        };
+
+        sell(sender, SellTarget::Item(item), amount).await
+    }
+}
+
+// TODO: Move to a proper consumer instead of SimpleArgConsumer for amounts
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        require(|sender| sender.is_player())
+            .then(
+                literal("hand")
+                    .execute(SellHandExecutor)
+                    .then(argument(ARG_AMOUNT, SimpleArgConsumer).execute(SellHandExecutor)),
+            )
+            .then(literal("all").execute(SellAllExecutor))
+            .then(
+                argument(ARG_ITEM, SimpleArgConsumer)
+                    .execute(SellItemExecutor)
+                    .then(argument(ARG_AMOUNT, SimpleArgConsumer).execute(SellItemExecutor)),
+            ),
+    )
+}
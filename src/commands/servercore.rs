@@ -0,0 +1,330 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{message::MsgArgConsumer, simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, literal},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    backup, essentials,
+    transfer::{self, ImportMode},
+    utils::{
+        current_sec, error_colour, failed_message, format_duration, mark_colour, neutral_colour,
+        success_colour,
+    },
+};
+
+const NAMES: [&str; 1] = ["servercore"];
+const DESCRIPTION: &str = "Manage the servercore plugin.";
+
+const ARG_BACKUP: &str = "backup";
+const ARG_FILE: &str = "file";
+const ARG_DIRECTORY: &str = "directory";
+
+struct BackupNowExecutor;
+
+#[async_trait]
+impl CommandExecutor for BackupNowExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let msg = match backup::backup_now().await {
+            Ok(name) => TextComponent::text(format!("Backed up database to {}.", name))
+                .color_rgb(success_colour()),
+            Err(e) => {
+                log::error!("Failed to back up database: {}", e);
+                TextComponent::text(format!("Backup failed: {}", e)).color_rgb(error_colour())
+            }
+        };
+        sender.send_message(msg).await;
+
+        Ok(())
+    }
+}
+
+struct BackupListExecutor;
+
+#[async_trait]
+impl CommandExecutor for BackupListExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let backups = match backup::list_backups().await {
+            Ok(backups) => backups,
+            Err(e) => {
+                log::error!("Failed to list backups: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        if backups.is_empty() {
+            sender
+                .send_message(
+                    TextComponent::text("There are no backups.").color_rgb(neutral_colour()),
+                )
+                .await;
+            return Ok(());
+        }
+
+        sender
+            .send_message(TextComponent::text("Backups:").color_rgb(neutral_colour()))
+            .await;
+        for b in backups.iter() {
+            let line = format!(
+                "{} - {} ago, {} KiB",
+                b.name,
+                format_duration(current_sec() - b.created_at),
+                b.size / 1024
+            );
+            sender
+                .send_message(TextComponent::text(line).color_rgb(mark_colour()))
+                .await;
+        }
+
+        Ok(())
+    }
+}
+
+struct BackupRestoreExecutor;
+
+#[async_trait]
+impl CommandExecutor for BackupRestoreExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_BACKUP) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_BACKUP.into())));
+        };
+
+        let msg = match backup::stage_restore(name).await {
+            Ok(()) => TextComponent::text(format!(
+                "Backup {} will be restored when the server restarts.",
+                name
+            ))
+            .color_rgb(success_colour()),
+            Err(e) => {
+                TextComponent::text(format!("Can not restore: {}", e)).color_rgb(error_colour())
+            }
+        };
+        sender.send_message(msg).await;
+
+        Ok(())
+    }
+}
+
+struct ExportExecutor;
+
+#[async_trait]
+impl CommandExecutor for ExportExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(file)) = args.get(&ARG_FILE) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_FILE.into())));
+        };
+
+        let msg = match transfer::export(file).await {
+            Ok((path, count)) => {
+                TextComponent::text(format!("Exported {} rows to {}.", count, path.display()))
+                    .color_rgb(success_colour())
+            }
+            Err(e) => {
+                log::error!("Failed to export data: {}", e);
+                TextComponent::text(format!("Export failed: {}", e)).color_rgb(error_colour())
+            }
+        };
+        sender.send_message(msg).await;
+
+        Ok(())
+    }
+}
+
+struct ImportExecutor(ImportMode);
+
+#[async_trait]
+impl CommandExecutor for ImportExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        server: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(file)) = args.get(&ARG_FILE) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_FILE.into())));
+        };
+
+        // Cached players would be written back over the replaced rows
+        if self.0 == ImportMode::Replace && !server.get_all_players().await.is_empty() {
+            sender
+                .send_message(
+                    TextComponent::text(
+                        "Replacing data is only possible while no players are online.",
+                    )
+                    .color_rgb(error_colour()),
+                )
+                .await;
+            return Ok(());
+        }
+
+        let report = match transfer::import(file, self.0).await {
+            Ok(report) => report,
+            Err(e) => {
+                log::error!("Failed to import data: {}", e);
+                sender
+                    .send_message(
+                        TextComponent::text(format!("Import failed: {}", e))
+                            .color_rgb(error_colour()),
+                    )
+                    .await;
+                return Ok(());
+            }
+        };
+
+        sender
+            .send_message(
+                TextComponent::text(format!("Import of {} ({}):", file, self.0.as_str()))
+                    .color_rgb(neutral_colour()),
+            )
+            .await;
+        for t in report.tables.iter() {
+            let line = format!(
+                "{}: {} inserted, {} skipped, {} orphaned",
+                t.table, t.inserted, t.skipped, t.orphaned
+            );
+            sender
+                .send_message(TextComponent::text(line).color_rgb(mark_colour()))
+                .await;
+        }
+        if !report.ignored.is_empty() {
+            sender
+                .send_message(
+                    TextComponent::text(format!("Ignored: {}", report.ignored.join(", ")))
+                        .color_rgb(mark_colour()),
+                )
+                .await;
+        }
+
+        let msg = if self.0 == ImportMode::DryRun {
+            TextComponent::text("Nothing was written, import with merge or replace to apply.")
+                .color_rgb(neutral_colour())
+        } else {
+            TextComponent::text("Import complete.").color_rgb(success_colour())
+        };
+        sender.send_message(msg).await;
+
+        Ok(())
+    }
+}
+
+struct EssentialsImportExecutor;
+
+#[async_trait]
+impl CommandExecutor for EssentialsImportExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Msg(dir)) = args.get(&ARG_DIRECTORY) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_DIRECTORY.into())));
+        };
+
+        let report = match essentials::import(dir).await {
+            Ok(report) => report,
+            Err(e) => {
+                log::error!("Failed to import Essentials data: {}", e);
+                sender
+                    .send_message(
+                        TextComponent::text(format!("Import failed: {}", e))
+                            .color_rgb(error_colour()),
+                    )
+                    .await;
+                return Ok(());
+            }
+        };
+
+        sender
+            .send_message(
+                TextComponent::text(format!("Essentials import of {}:", dir))
+                    .color_rgb(neutral_colour()),
+            )
+            .await;
+        for (record, counts) in [
+            ("Players", &report.players),
+            ("Homes", &report.homes),
+            ("Warps", &report.warps),
+            ("Nicknames", &report.nicknames),
+        ] {
+            let line = format!(
+                "{}: {} imported, {} skipped, {} conflicting",
+                record, counts.imported, counts.skipped, counts.conflicting
+            );
+            sender
+                .send_message(TextComponent::text(line).color_rgb(mark_colour()))
+                .await;
+        }
+        if !report.details.is_empty() {
+            sender
+                .send_message(
+                    TextComponent::text(format!(
+                        "{} records were not imported, see the server log for details.",
+                        report.details.len()
+                    ))
+                    .color_rgb(neutral_colour()),
+                )
+                .await;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(
+            literal("backup")
+                .then(literal("now").execute(BackupNowExecutor))
+                .then(literal("list").execute(BackupListExecutor))
+                .then(
+                    literal("restore").then(
+                        argument(ARG_BACKUP, SimpleArgConsumer).execute(BackupRestoreExecutor),
+                    ),
+                ),
+        )
+        .then(literal("export").then(argument(ARG_FILE, SimpleArgConsumer).execute(ExportExecutor)))
+        .then(
+            literal("import-essentials")
+                .then(argument(ARG_DIRECTORY, MsgArgConsumer).execute(EssentialsImportExecutor)),
+        )
+        .then(
+            literal("import").then(
+                argument(ARG_FILE, SimpleArgConsumer)
+                    .execute(ImportExecutor(ImportMode::DryRun))
+                    .then(literal("dry-run").execute(ImportExecutor(ImportMode::DryRun)))
+                    .then(literal("merge").execute(ImportExecutor(ImportMode::Merge)))
+                    .then(literal("replace").execute(ImportExecutor(ImportMode::Replace))),
+            ),
+        )
+}
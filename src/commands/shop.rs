@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, require},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    shop::{
+        get_shop,
+        gui::{self, Page},
+    },
+    utils::error_colour,
+};
+
+const NAMES: [&str; 1] = ["shop"];
+const DESCRIPTION: &str = "Buy and sell items.";
+
+const ARG_CATEGORY: &str = "category";
+
+struct ShopExecutor;
+
+#[async_trait]
+impl CommandExecutor for ShopExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let player = sender.as_player().unwrap();
+
+        // Categories can be opened directly, names are matched
+        // case-insensitively
+        let page = match args.get(&ARG_CATEGORY) {
+            Some(Arg::Simple(name)) => {
+                let shop = get_shop().await;
+                let Some(index) = shop
+                    .categories
+                    .iter()
+                    .position(|c| c.name.eq_ignore_ascii_case(name))
+                else {
+                    sender
+                        .send_message(
+                            TextComponent::text("That shop category does not exist.")
+                                .color_rgb(error_colour()),
+                        )
+                        .await;
+                    return Ok(());
+                };
+                Page::Category(index, 0)
+            }
+            _ => Page::Categories,
+        };
+
+        gui::open(&player, page).await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        require(|sender| sender.is_player())
+            .execute(ShopExecutor)
+            .then(argument(ARG_CATEGORY, SimpleArgConsumer).execute(ShopExecutor)),
+    )
+}
@@ -3,21 +3,26 @@ use pumpkin::{
     command::{
         args::{message::MsgArgConsumer, Arg, ConsumedArgs},
         dispatcher::CommandError,
-        tree::{builder::argument, CommandTree},
+        tree::{
+            builder::{argument, require},
+            CommandTree,
+        },
         CommandExecutor, CommandSender,
     },
     server::Server,
 };
-use pumpkin_util::{text::TextComponent, PermissionLvl};
+use pumpkin_util::text::TextComponent;
 
-use crate::utils::mark_colour;
-
-const NAMES: [&str; 2] = ["staffchat", "sc"];
-const DESCRIPTION: &str = "Send a message to all staffmembers online.";
+use crate::{
+    staffchat::{self, Channel},
+    utils::{neutral_colour, success_colour},
+};
 
 const ARG_MESSAGE: &str = "message";
 
-struct StaffchatExecutor;
+struct StaffchatExecutor {
+    channel: Channel,
+}
 
 #[async_trait]
 impl CommandExecutor for StaffchatExecutor {
@@ -38,21 +43,56 @@ impl CommandExecutor for StaffchatExecutor {
             name = "Server".to_string();
         }
 
-        let msg = format!("[SC] {}: {}", name, msg);
-        let tc = TextComponent::text(msg).color_rgb(mark_colour());
+        staffchat::send(server, &self.channel, &name, msg).await;
 
-        let players = server.get_all_players().await;
-        for player in players.iter() {
-            if player.permission_lvl.load().ge(&PermissionLvl::One) {
-                player.send_system_message(&tc).await;
-            }
-        }
+        Ok(())
+    }
+}
+
+struct StaffchatToggleExecutor {
+    channel: Channel,
+}
+
+#[async_trait]
+impl CommandExecutor for StaffchatToggleExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let player = sender.as_player().unwrap();
+
+        let tc = if staffchat::toggle(&player.gameprofile.id.to_string(), &self.channel.name) {
+            TextComponent::text(format!(
+                "Your chat messages now go to {}.",
+                self.channel.prefix
+            ))
+            .color_rgb(success_colour())
+        } else {
+            TextComponent::text("Your chat messages now go to public chat.")
+                .color_rgb(neutral_colour())
+        };
+
+        player.send_system_message(&tc).await;
 
         Ok(())
     }
 }
 
-pub fn init_command() -> CommandTree {
-    CommandTree::new(NAMES, DESCRIPTION)
-        .then(argument(ARG_MESSAGE, MsgArgConsumer).execute(StaffchatExecutor))
+pub fn init_command(channel: &Channel) -> CommandTree {
+    CommandTree::new(
+        channel.names.clone(),
+        format!("Send a message to the {} channel.", channel.name),
+    )
+    .then(
+        argument(ARG_MESSAGE, MsgArgConsumer).execute(StaffchatExecutor {
+            channel: channel.clone(),
+        }),
+    )
+    .then(
+        require(|sender| sender.is_player()).execute(StaffchatToggleExecutor {
+            channel: channel.clone(),
+        }),
+    )
 }
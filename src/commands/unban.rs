@@ -0,0 +1,99 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    ip,
+    moderation::{self, PunishmentKind},
+    utils::{
+        failed_message, neutral_colour, player_not_found_message, sender_name, success_colour,
+    },
+};
+
+const NAMES: [&str; 1] = ["unban"];
+const DESCRIPTION: &str = "Lift the ban of a player or IP address.";
+
+const ARG_PLAYER: &str = "player";
+
+struct UnbanExecutor;
+
+#[async_trait]
+impl CommandExecutor for UnbanExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        // Addresses banned with /ipban
+        if let Ok(address) = name.parse::<IpAddr>() {
+            let address = address.to_string();
+            let tc = match ip::unban_ip(&address).await {
+                Ok(true) => {
+                    log::info!("{} unbanned IP {}", sender_name(sender), address);
+                    TextComponent::text(format!("Unbanned {}.", address))
+                        .color_rgb(success_colour())
+                }
+                Ok(false) => TextComponent::text(format!("{} is not banned.", address))
+                    .color_rgb(neutral_colour()),
+                Err(e) => {
+                    log::error!("Failed to unban IP address: {}", e);
+                    failed_message()
+                }
+            };
+
+            sender.send_message(tc).await;
+            return Ok(());
+        }
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let tc = match moderation::revoke(&uuid, PunishmentKind::Ban).await {
+            Ok(true) => {
+                log::info!("{} unbanned {}", sender_name(sender), nickname);
+                TextComponent::text(format!("Unbanned {}.", nickname)).color_rgb(success_colour())
+            }
+            Ok(false) => TextComponent::text(format!("{} is not banned.", nickname))
+                .color_rgb(neutral_colour()),
+            Err(e) => {
+                log::error!("Failed to unban player: {}", e);
+                failed_message()
+            }
+        };
+
+        sender.send_message(tc).await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_PLAYER, SimpleArgConsumer).execute(UnbanExecutor))
+}
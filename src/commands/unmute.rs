@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    moderation,
+    utils::{
+        failed_message, neutral_colour, player_not_found_message, sender_name, success_colour,
+    },
+};
+
+const NAMES: [&str; 1] = ["unmute"];
+const DESCRIPTION: &str = "Lift the mute of a player.";
+
+const ARG_PLAYER: &str = "player";
+
+struct UnmuteExecutor;
+
+#[async_trait]
+impl CommandExecutor for UnmuteExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let tc = match moderation::unmute_player(&uuid).await {
+            Ok(true) => {
+                log::info!("{} unmuted {}", sender_name(sender), nickname);
+                TextComponent::text(format!("Unmuted {}.", nickname)).color_rgb(success_colour())
+            }
+            Ok(false) => TextComponent::text(format!("{} is not muted.", nickname))
+                .color_rgb(neutral_colour()),
+            Err(e) => {
+                log::error!("Failed to unmute player: {}", e);
+                failed_message()
+            }
+        };
+
+        sender.send_message(tc).await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_PLAYER, SimpleArgConsumer).execute(UnmuteExecutor))
+}
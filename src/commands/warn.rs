@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{message::MsgArgConsumer, simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    moderation,
+    utils::{failed_message, player_not_found_message, sender_name, success_colour},
+};
+
+const NAMES: [&str; 1] = ["warn"];
+const DESCRIPTION: &str = "Warn a player.";
+
+const ARG_PLAYER: &str = "player";
+const ARG_REASON: &str = "reason";
+
+struct WarnExecutor;
+
+#[async_trait]
+impl CommandExecutor for WarnExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        server: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let Some(Arg::Msg(reason)) = args.get(&ARG_REASON) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_REASON.into())));
+        };
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let issuer = sender_name(sender);
+        let count = match moderation::warn_player(server, &uuid, &nickname, reason, &issuer).await {
+            Ok(count) => count,
+            Err(e) => {
+                log::error!("Failed to warn player: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let msg = format!(
+            "Warned {} ({} active warning{}).",
+            nickname,
+            count,
+            if count == 1 { "" } else { "s" }
+        );
+        sender
+            .send_message(TextComponent::text(msg).color_rgb(success_colour()))
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION).then(
+        argument(ARG_PLAYER, SimpleArgConsumer)
+            .then(argument(ARG_REASON, MsgArgConsumer).execute(WarnExecutor)),
+    )
+}
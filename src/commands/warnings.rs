@@ -0,0 +1,126 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, require},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::find_player,
+    moderation,
+    utils::{error_colour, failed_message, mark_colour, neutral_colour, player_not_found_message},
+};
+
+const NAMES: [&str; 1] = ["warnings"];
+const DESCRIPTION: &str = "List active warnings.";
+
+const ARG_PLAYER: &str = "player";
+
+async fn send_warnings(sender: &CommandSender, player_uuid: &str, header: String) {
+    let warnings = match moderation::get_warnings(player_uuid).await {
+        Ok(warnings) => warnings,
+        Err(e) => {
+            log::error!("Failed to fetch warnings: {}", e);
+            sender.send_message(failed_message()).await;
+            return;
+        }
+    };
+
+    if warnings.is_empty() {
+        sender
+            .send_message(TextComponent::text("No active warnings.").color_rgb(neutral_colour()))
+            .await;
+        return;
+    }
+
+    sender
+        .send_message(TextComponent::text(header).color_rgb(neutral_colour()))
+        .await;
+
+    for warning in warnings.iter() {
+        sender
+            .send_message(TextComponent::text(warning.summary()).color_rgb(mark_colour()))
+            .await;
+    }
+}
+
+struct WarningsExecutor;
+
+#[async_trait]
+impl CommandExecutor for WarningsExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        if !sender.has_permission("servercore:warnings.others").await {
+            sender
+                .send_message(
+                    TextComponent::text("You can not see the warnings of other players.")
+                        .color_rgb(error_colour()),
+                )
+                .await;
+            return Ok(());
+        }
+
+        let (uuid, nickname) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        send_warnings(sender, &uuid, format!("Active warnings of {}:", nickname)).await;
+
+        Ok(())
+    }
+}
+
+struct WarningsExecutorSelf;
+
+#[async_trait]
+impl CommandExecutor for WarningsExecutorSelf {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        _: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let player = sender.as_player().unwrap();
+
+        send_warnings(
+            sender,
+            &player.gameprofile.id.to_string(),
+            "Your active warnings:".to_string(),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        // Viewing warnings of another player requires servercore:warnings.others
+        .then(argument(ARG_PLAYER, SimpleArgConsumer).execute(WarningsExecutor))
+        .then(require(|sender| sender.is_player()).execute(WarningsExecutorSelf))
+}
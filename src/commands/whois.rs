@@ -0,0 +1,163 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{builder::argument, CommandTree},
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::{find_player, get_player_info, get_playtime_display, is_afk, is_loaded},
+    config::get_config,
+    economy, ip,
+    moderation::{self, PunishmentKind},
+    nick,
+    utils::{
+        current_sec, failed_message, format_duration, mark_colour, neutral_colour, parse_duration,
+        player_not_found_message,
+    },
+};
+
+const NAMES: [&str; 1] = ["whois"];
+const DESCRIPTION: &str = "Show information about a player.";
+
+const ARG_PLAYER: &str = "player";
+
+fn ago(time: Option<i64>) -> String {
+    match time {
+        Some(t) => format!("{} ago", format_duration(current_sec() - t)),
+        None => "unknown".to_string(),
+    }
+}
+
+fn punishment_state(punishment: Option<moderation::Punishment>) -> String {
+    match punishment {
+        Some(p) => match p.remaining() {
+            Some(r) => format!("yes, for {} ({})", format_duration(r), p.reason),
+            None => format!("yes, permanently ({})", p.reason),
+        },
+        None => "no".to_string(),
+    }
+}
+
+struct WhoisExecutor;
+
+#[async_trait]
+impl CommandExecutor for WhoisExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        let Some(Arg::Simple(name)) = args.get(&ARG_PLAYER) else {
+            return Err(CommandError::InvalidConsumption(Some(ARG_PLAYER.into())));
+        };
+
+        let (uuid, _) = match find_player(name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to look up player {}: {}", name, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let info = match get_player_info(&uuid).await {
+            Ok(Some(info)) => info,
+            Ok(None) => {
+                sender.send_message(player_not_found_message(name)).await;
+                return Ok(());
+            }
+            Err(e) => {
+                log::error!("Failed to fetch player info: {}", e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        let mut balances = Vec::new();
+        for currency in economy::currencies().await {
+            let balance = economy::balance(&uuid, &currency.name).await.unwrap_or(0.0);
+            balances.push(currency.format_short(balance));
+        }
+        let mute = moderation::get_active(&uuid, PunishmentKind::Mute)
+            .await
+            .ok()
+            .flatten();
+        let ban = moderation::get_active(&uuid, PunishmentKind::Ban)
+            .await
+            .ok()
+            .flatten();
+
+        // /vanish does not hide players yet, so there is no vanish state to show
+        let afk_timeout = parse_duration(&get_config().await.value.afk_timeout);
+        let status = if !is_loaded(&uuid) {
+            "offline"
+        } else if afk_timeout.is_some_and(|timeout| is_afk(&uuid, timeout)) {
+            "online, AFK"
+        } else {
+            "online"
+        };
+
+        let names = nick::get_name_history(&uuid)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|n| format!("{} ({})", n.name, ago(Some(n.changed_at))))
+            .collect::<Vec<_>>();
+
+        let mut lines = vec![
+            format!("UUID: {}", info.uuid),
+            format!(
+                "Nickname: {}",
+                info.display_name
+                    .as_deref()
+                    .map_or("none".to_string(), nick::strip_codes)
+            ),
+            format!("Name history: {}", names.join(", ")),
+            format!("Status: {}", status),
+            format!("First join: {}", ago(info.first_join)),
+            format!("Last join: {}", ago(info.last_join)),
+            format!("Last seen: {}", ago(info.last_seen)),
+            format!("Playtime: {}", get_playtime_display(&uuid).await),
+            format!("Balance: {}", balances.join(", ")),
+            format!("Muted: {}", punishment_state(mute)),
+            format!("Banned: {}", punishment_state(ban)),
+        ];
+
+        // Addresses are only shown to those allowed to see them
+        if sender.has_permission("servercore:whois.ip").await {
+            let address = ip::get_last_ip(&uuid).await.ok().flatten();
+            lines.push(format!("IP: {}", address.unwrap_or("unknown".to_string())));
+        }
+
+        sender
+            .send_message(
+                TextComponent::text(format!("Whois {}:", info.nickname))
+                    .color_rgb(neutral_colour()),
+            )
+            .await;
+
+        for line in lines {
+            sender
+                .send_message(TextComponent::text(line).color_rgb(mark_colour()))
+                .await;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_PLAYER, SimpleArgConsumer).execute(WhoisExecutor))
+}
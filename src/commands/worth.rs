@@ -0,0 +1,99 @@
+use async_trait::async_trait;
+use pumpkin::{
+    command::{
+        args::{simple::SimpleArgConsumer, Arg, ConsumedArgs},
+        dispatcher::CommandError,
+        tree::{
+            builder::{argument, require},
+            CommandTree,
+        },
+        CommandExecutor, CommandSender,
+    },
+    server::Server,
+};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    economy,
+    shop::{worth, ShopError},
+    utils::{error_colour, failed_message, neutral_colour},
+};
+
+const NAMES: [&str; 1] = ["worth"];
+const DESCRIPTION: &str = "Show what an item sells for.";
+
+const ARG_ITEM: &str = "item";
+
+struct WorthExecutor;
+
+#[async_trait]
+impl CommandExecutor for WorthExecutor {
+    async fn execute<'a>(
+        &self,
+        sender: &mut CommandSender,
+        _: &Server,
+        args: &ConsumedArgs<'a>,
+    ) -> Result<(), CommandError> {
+        // Without an item the one in hand is used
+        let (item, missing) = match args.get(&ARG_ITEM) {
+            Some(Arg::Simple(name)) => (worth::find_item(name), "That item does not exist."),
+            _ => match sender.as_player() {
+                Some(player) => (
+                    worth::held_item(&player).await,
+                    "You are not holding an item.",
+                ),
+                None => (None, "You are not holding an item."),
+            },
+        };
+
+        let Some(item) = item else {
+            sender
+                .send_message(TextComponent::text(missing).color_rgb(error_colour()))
+                .await;
+            return Ok(());
+        };
+
+        let currency = economy::default_currency().await;
+        let stack = item.components.max_stack_size as u32;
+        let prices = async {
+            Ok::<_, ShopError>((
+                worth::price(&currency, item, 1).await?,
+                worth::price(&currency, item, stack).await?,
+            ))
+        }
+        .await;
+
+        let msg = match prices {
+            Ok((Some(one), Some(all))) if stack > 1 => format!(
+                "1 {} is worth {}, {} are worth {}.",
+                worth::display_name(item),
+                currency.format(one),
+                stack,
+                currency.format(all)
+            ),
+            Ok((Some(one), _)) => format!(
+                "1 {} is worth {}.",
+                worth::display_name(item),
+                currency.format(one)
+            ),
+            Ok((None, _)) => format!("{} can not be sold.", worth::display_name(item)),
+            Err(e) => {
+                log::error!("Failed to get the worth of {}: {}", item.registry_key, e);
+                sender.send_message(failed_message()).await;
+                return Ok(());
+            }
+        };
+
+        sender
+            .send_message(TextComponent::text(msg).color_rgb(neutral_colour()))
+            .await;
+
+        Ok(())
+    }
+}
+
+pub fn init_command() -> CommandTree {
+    CommandTree::new(NAMES, DESCRIPTION)
+        .then(argument(ARG_ITEM, SimpleArgConsumer).execute(WorthExecutor))
+        .then(require(|sender| sender.is_player()).execute(WorthExecutor))
+}
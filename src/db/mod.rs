@@ -0,0 +1,1441 @@
+use async_trait::async_trait;
+use dashmap::DashMap;
+use sqlx::AnyPool;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::OnceCell;
+
+use crate::{config::get_config, economy};
+
+mod mysql;
+mod postgres;
+mod sqlite;
+
+// Bumped whenever tables or columns change, databases of a newer
+// version are refused instead of being written to
+pub const SCHEMA_VERSION: i64 = 15;
+
+// A database the plugin can store its data in. Queries throughout the plugin
+// are written for SQLite with $N placeholders bound in order, backends
+// rewrite them into their own dialect. Sums are integers on SQLite only, so
+// they are cast with CAST(... AS BIGINT) to decode as i64 everywhere.
+// Inserts that may hit a unique index end in ON CONFLICT DO NOTHING.
+#[async_trait]
+pub trait Backend: std::fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Opens the database, creating it when it does not exist yet
+    async fn connect(&self) -> Result<AnyPool, Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn tables(
+        &self,
+        pool: &AnyPool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn columns(
+        &self,
+        pool: &AnyPool,
+        table: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Copies the database to a file while it is in use
+    async fn backup(
+        &self,
+        _pool: &AnyPool,
+        _dest: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Err(format!(
+            "Backups of {} databases are not supported, use the database's own tools.",
+            self.name()
+        )
+        .into())
+    }
+
+    // Rewrites a query into this backend's dialect
+    fn rewrite<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(sql)
+    }
+
+    // Rewrites a CREATE or ALTER statement, mapping the column types
+    fn ddl<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(sql)
+    }
+
+    // A TEXT column as part of an index
+    fn text_key<'a>(&self, column: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(column)
+    }
+}
+
+// Integer casts as written in queries, see Backend
+const BIGINT_CAST: &str = "AS BIGINT)";
+// Inserts skipping rows that already exist, see Backend
+const IGNORE_CONFLICT: &str = "ON CONFLICT DO NOTHING";
+
+// Maps the SQLite column types used in the table definitions
+fn map_types(sql: &str, primary_key: &str, integer: &str, real: &str) -> String {
+    sql.replace("INTEGER PRIMARY KEY", primary_key)
+        .replace("INTEGER", integer)
+        .replace("REAL", real)
+}
+
+#[derive(Debug)]
+pub struct DB {
+    pub pool: AnyPool,
+    backend: Box<dyn Backend>,
+    // Rewritten queries, keyed by the original
+    queries: DashMap<&'static str, &'static str>,
+}
+
+impl DB {
+    pub async fn init(
+        backend: Box<dyn Backend>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let pool = backend.connect().await?;
+
+        let existing = backend.tables(&pool).await?;
+
+        let stored_version = schema_version(&pool, &existing).await?;
+        if let Some(version) = stored_version {
+            if version > SCHEMA_VERSION {
+                return Err(format!(
+                    "Database has schema version {}, this version of the plugin supports up to {}.",
+                    version, SCHEMA_VERSION
+                )
+                .into());
+            }
+        }
+
+        if !existing.contains(&"players".to_string()) {
+            log::info!("Setting up database.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE players (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    nickname TEXT NOT NULL,
+                    balance REAL NOT NULL DEFAULT 0,
+                    playtime INTEGER NOT NULL DEFAULT 0
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE homes (
+                    id INTEGER PRIMARY KEY,
+                    user_id INTEGER NOT NULL,
+                    name TEXT NOT NULL,
+                    x REAL NOT NULL,
+                    y REAL NOT NULL,
+                    z REAL NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE warps (
+                    id INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    x REAL NOT NULL,
+                    y REAL NOT NULL,
+                    z REAL NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        add_missing_column(&pool, backend.as_ref(), "players", "first_join", "INTEGER").await?;
+        add_missing_column(&pool, backend.as_ref(), "players", "last_join", "INTEGER").await?;
+        add_missing_column(&pool, backend.as_ref(), "players", "last_seen", "INTEGER").await?;
+        add_missing_column(&pool, backend.as_ref(), "players", "display_name", "TEXT").await?;
+        add_missing_column(
+            &pool,
+            backend.as_ref(),
+            "players",
+            "display_name_plain",
+            "TEXT",
+        )
+        .await?;
+        add_missing_column(
+            &pool,
+            backend.as_ref(),
+            "players",
+            "version",
+            "INTEGER NOT NULL DEFAULT 0",
+        )
+        .await?;
+        // Where the player logged out, filled by imports from other plugins
+        for column in ["logout_x", "logout_y", "logout_z"] {
+            add_missing_column(&pool, backend.as_ref(), "players", column, "REAL").await?;
+        }
+        add_missing_column(
+            &pool,
+            backend.as_ref(),
+            "players",
+            "pay_disabled",
+            "INTEGER NOT NULL DEFAULT 0",
+        )
+        .await?;
+
+        // Two players could both pass the nickname check before either saved
+        if stored_version.unwrap_or(0) < 12 {
+            clear_duplicate_nicknames(&pool, backend.as_ref()).await?;
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX players_display_name ON players ({})",
+                backend.text_key("display_name_plain")
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        // Balances moved out of players when currencies were added, the
+        // old column is left in place and copied into the default currency
+        if !existing.contains(&"balances".to_string()) {
+            log::info!("Setting up balances table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE balances (
+                    id INTEGER PRIMARY KEY,
+                    player_id INTEGER NOT NULL,
+                    currency TEXT NOT NULL,
+                    balance REAL NOT NULL DEFAULT 0
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.rewrite(
+                "INSERT INTO balances (player_id, currency, balance)
+                    SELECT id, $1, balance FROM players",
+            ))
+            .bind(economy::default_currency().await.name)
+            .execute(&pool)
+            .await?;
+        }
+
+        // Servers could both create a balance row for the same currency
+        if stored_version.unwrap_or(0) < 11 {
+            merge_balances(&pool, backend.as_ref()).await?;
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX balances_player_currency ON balances (player_id, {})",
+                backend.text_key("currency")
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"staffchat_messages".to_string()) {
+            log::info!("Setting up staffchat table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE staffchat_messages (
+                    id INTEGER PRIMARY KEY,
+                    channel TEXT NOT NULL,
+                    sender TEXT NOT NULL,
+                    message TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"punishments".to_string()) {
+            log::info!("Setting up punishments table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE punishments (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    reason TEXT NOT NULL,
+                    issuer TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    expires_at INTEGER,
+                    active INTEGER NOT NULL DEFAULT 1
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"warnings".to_string()) {
+            log::info!("Setting up warnings table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE warnings (
+                    id INTEGER PRIMARY KEY,
+                    player_id INTEGER NOT NULL,
+                    reason TEXT NOT NULL,
+                    issuer TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    expires_at INTEGER
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"player_sessions".to_string()) {
+            log::info!("Setting up player sessions table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE player_sessions (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    ip TEXT NOT NULL,
+                    joined_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"ip_bans".to_string()) {
+            log::info!("Setting up IP bans table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE ip_bans (
+                    id INTEGER PRIMARY KEY,
+                    ip TEXT NOT NULL,
+                    reason TEXT NOT NULL,
+                    issuer TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    expires_at INTEGER,
+                    active INTEGER NOT NULL DEFAULT 1
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"name_history".to_string()) {
+            log::info!("Setting up name history table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE name_history (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    changed_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"bank_accounts".to_string()) {
+            log::info!("Setting up bank tables.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE bank_accounts (
+                    id INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    owner TEXT NOT NULL,
+                    balance REAL NOT NULL DEFAULT 0,
+                    created_at INTEGER NOT NULL,
+                    last_interest INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE bank_members (
+                    id INTEGER PRIMARY KEY,
+                    account TEXT NOT NULL,
+                    uuid TEXT NOT NULL,
+                    added_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE bank_transactions (
+                    id INTEGER PRIMARY KEY,
+                    account TEXT NOT NULL,
+                    uuid TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    amount REAL NOT NULL,
+                    created_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        // Account names are unique regardless of case, the lowercased name
+        // carries the index
+        add_missing_column(&pool, backend.as_ref(), "bank_accounts", "name_key", "TEXT").await?;
+        if stored_version.unwrap_or(0) < 13 {
+            sqlx::query("UPDATE bank_accounts SET name_key = LOWER(name) WHERE name_key IS NULL")
+                .execute(&pool)
+                .await?;
+            rename_duplicate_accounts(&pool, backend.as_ref()).await?;
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX bank_accounts_name ON bank_accounts ({})",
+                backend.text_key("name_key")
+            ))
+            .execute(&pool)
+            .await?;
+
+            merge_bank_members(&pool, backend.as_ref()).await?;
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX bank_members_account_uuid ON bank_members ({}, {})",
+                backend.text_key("account"),
+                backend.text_key("uuid")
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"payouts".to_string()) {
+            log::info!("Setting up payout tables.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE payout_schedule (
+                    id INTEGER PRIMARY KEY,
+                    kind TEXT NOT NULL,
+                    last_run INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE payouts (
+                    id INTEGER PRIMARY KEY,
+                    kind TEXT NOT NULL,
+                    uuid TEXT NOT NULL,
+                    amount REAL NOT NULL,
+                    paid_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        // Servers starting together could both schedule the same payout
+        if stored_version.unwrap_or(0) < 10 {
+            merge_payout_schedules(&pool, backend.as_ref()).await?;
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX payout_schedule_kind ON payout_schedule ({})",
+                backend.text_key("kind")
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        // A payout is paid once per player and run, the run being the time
+        // it was claimed at. Payouts from before have no run.
+        add_missing_column(&pool, backend.as_ref(), "payouts", "period", "INTEGER").await?;
+        add_missing_column(
+            &pool,
+            backend.as_ref(),
+            "payout_schedule",
+            "paying",
+            "INTEGER NOT NULL DEFAULT 0",
+        )
+        .await?;
+        if stored_version.unwrap_or(0) < 14 {
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX payouts_kind_period_uuid ON payouts ({}, period, {})",
+                backend.text_key("kind"),
+                backend.text_key("uuid")
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"shop_trades".to_string()) {
+            log::info!("Setting up shop tables.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE shop_stock (
+                    id INTEGER PRIMARY KEY,
+                    category TEXT NOT NULL,
+                    item TEXT NOT NULL,
+                    stock INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE shop_trades (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    category TEXT NOT NULL,
+                    item TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    quantity INTEGER NOT NULL,
+                    price REAL NOT NULL,
+                    currency TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        // Servers loading the shop together could both create a stock row
+        if stored_version.unwrap_or(0) < 15 {
+            merge_shop_stock(&pool, backend.as_ref()).await?;
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX shop_stock_item ON shop_stock ({}, {})",
+                backend.text_key("category"),
+                backend.text_key("item")
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"item_sales".to_string()) {
+            log::info!("Setting up item sales table.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE item_sales (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    item TEXT NOT NULL,
+                    quantity INTEGER NOT NULL,
+                    price REAL NOT NULL,
+                    currency TEXT NOT NULL,
+                    sold_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"auction_listings".to_string()) {
+            log::info!("Setting up auction house tables.");
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE auction_listings (
+                    id INTEGER PRIMARY KEY,
+                    seller TEXT NOT NULL,
+                    item TEXT NOT NULL,
+                    item_key TEXT NOT NULL,
+                    quantity INTEGER NOT NULL,
+                    price REAL,
+                    start_bid REAL,
+                    top_bid REAL,
+                    top_bidder TEXT,
+                    buyer TEXT,
+                    state TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    expires_at INTEGER NOT NULL,
+                    closed_at INTEGER
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+
+            sqlx::query(&backend.ddl(
+                "
+                CREATE TABLE auction_mailbox (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    item TEXT,
+                    item_key TEXT,
+                    quantity INTEGER NOT NULL,
+                    amount REAL NOT NULL,
+                    reason TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                )",
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        if !existing.contains(&"schema_version".to_string()) {
+            sqlx::query(&backend.ddl("CREATE TABLE schema_version (version INTEGER NOT NULL)"))
+                .execute(&pool)
+                .await?;
+            sqlx::query(&backend.rewrite("INSERT INTO schema_version (version) VALUES ($1)"))
+                .bind(SCHEMA_VERSION)
+                .execute(&pool)
+                .await?;
+        } else {
+            sqlx::query(&backend.rewrite("UPDATE schema_version SET version = $1"))
+                .bind(SCHEMA_VERSION)
+                .execute(&pool)
+                .await?;
+        }
+
+        Ok(DB {
+            pool,
+            backend,
+            queries: DashMap::new(),
+        })
+    }
+
+    pub async fn backup(&self, dest: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.backend.backup(&self.pool, dest).await
+    }
+
+    pub async fn columns(
+        &self,
+        table: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        self.backend.columns(&self.pool, table).await
+    }
+
+    // A query built at runtime in this database's dialect
+    pub fn rewrite(&self, sql: &str) -> String {
+        self.backend.rewrite(sql).into_owned()
+    }
+
+    // The query in this database's dialect, rewrites are kept for the
+    // lifetime of the plugin so they can be passed to sqlx like the original
+    pub fn sql(&self, sql: &'static str) -> &'static str {
+        match self.backend.rewrite(sql) {
+            Cow::Borrowed(_) => sql,
+            Cow::Owned(rewritten) => *self
+                .queries
+                .entry(sql)
+                .or_insert_with(|| &*Box::leak(rewritten.into_boxed_str())),
+        }
+    }
+}
+
+// Adds a column to a table created by an older version of the plugin
+async fn add_missing_column(
+    pool: &AnyPool,
+    backend: &dyn Backend,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let columns = backend.columns(pool, table).await?;
+
+    if !columns.contains(&column.to_string()) {
+        log::info!("Adding column {} to {}.", column, table);
+        sqlx::query(&backend.ddl(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        )))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Merges balance rows of the same player and currency into one holding
+// their total
+async fn merge_balances(
+    pool: &AnyPool,
+    backend: &dyn Backend,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let duplicates = sqlx::query_as::<_, (i64, String, i64, f64)>(
+        "SELECT player_id, currency, MIN(id), SUM(balance) FROM balances
+            GROUP BY player_id, currency HAVING COUNT(*) > 1",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (player_id, currency, id, balance) in duplicates {
+        log::info!(
+            "Merging duplicate {} balances of player {}.",
+            currency,
+            player_id
+        );
+        let mut tx = pool.begin().await?;
+        sqlx::query(&backend.rewrite("UPDATE balances SET balance = $1 WHERE id = $2"))
+            .bind(balance)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            &backend.rewrite(
+                "DELETE FROM balances WHERE player_id = $1 AND currency = $2 AND id != $3",
+            ),
+        )
+        .bind(player_id)
+        .bind(&currency)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+// Accounts whose names differ only in case get their id appended, except
+// for the oldest one. Nothing is merged, the accounts may have different
+// owners.
+async fn rename_duplicate_accounts(
+    pool: &AnyPool,
+    backend: &dyn Backend,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let duplicates = sqlx::query_as::<_, (i64, String)>(
+        "SELECT a.id, a.name FROM bank_accounts a
+            WHERE a.id > (SELECT MIN(b.id) FROM bank_accounts b WHERE b.name_key = a.name_key)",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (id, name) in duplicates {
+        let renamed = format!("{}-{}", name, id);
+        log::info!("Renaming duplicate bank account {} to {}.", name, renamed);
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            &backend.rewrite("UPDATE bank_accounts SET name = $1, name_key = $2 WHERE id = $3"),
+        )
+        .bind(&renamed)
+        .bind(renamed.to_lowercase())
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        for table in ["bank_members", "bank_transactions"] {
+            sqlx::query(&backend.rewrite(&format!(
+                "UPDATE {} SET account = $1 WHERE account = $2",
+                table
+            )))
+            .bind(&renamed)
+            .bind(&name)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+// Keeps one membership row per account and player
+async fn merge_bank_members(
+    pool: &AnyPool,
+    backend: &dyn Backend,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let duplicates = sqlx::query_as::<_, (String, String, i64)>(
+        "SELECT account, uuid, MIN(id) FROM bank_members
+            GROUP BY account, uuid HAVING COUNT(*) > 1",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (account, uuid, id) in duplicates {
+        log::info!("Merging duplicate members of bank account {}.", account);
+        sqlx::query(
+            &backend
+                .rewrite("DELETE FROM bank_members WHERE account = $1 AND uuid = $2 AND id != $3"),
+        )
+        .bind(&account)
+        .bind(&uuid)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Keeps a nickname with the player who has had their row the longest, the
+// others lose theirs
+async fn clear_duplicate_nicknames(
+    pool: &AnyPool,
+    backend: &dyn Backend,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let duplicates = sqlx::query_as::<_, (String, i64)>(
+        "SELECT display_name_plain, MIN(id) FROM players
+            WHERE display_name_plain IS NOT NULL
+            GROUP BY display_name_plain HAVING COUNT(*) > 1",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (name, id) in duplicates {
+        log::info!("Removing duplicate nickname {} from other players.", name);
+        sqlx::query(&backend.rewrite(
+            "UPDATE players SET display_name = NULL, display_name_plain = NULL
+                WHERE display_name_plain = $1 AND id != $2",
+        ))
+        .bind(&name)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Keeps one stock row per shop item. Stock changes were applied to every
+// row of the item, the lowest stock is kept in case one was not.
+async fn merge_shop_stock(
+    pool: &AnyPool,
+    backend: &dyn Backend,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let duplicates = sqlx::query_as::<_, (String, String, i64, i64)>(
+        "SELECT category, item, MIN(id), MIN(stock) FROM shop_stock
+            GROUP BY category, item HAVING COUNT(*) > 1",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (category, item, id, stock) in duplicates {
+        log::info!("Merging duplicate stock of {} in {}.", item, category);
+        let mut tx = pool.begin().await?;
+        sqlx::query(&backend.rewrite("UPDATE shop_stock SET stock = $1 WHERE id = $2"))
+            .bind(stock)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            &backend
+                .rewrite("DELETE FROM shop_stock WHERE category = $1 AND item = $2 AND id != $3"),
+        )
+        .bind(&category)
+        .bind(&item)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+// Keeps one schedule row per payout kind, the one that ran last
+async fn merge_payout_schedules(
+    pool: &AnyPool,
+    backend: &dyn Backend,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let duplicates = sqlx::query_as::<_, (String, i64, i64)>(
+        "SELECT kind, MIN(id), MAX(last_run) FROM payout_schedule
+            GROUP BY kind HAVING COUNT(*) > 1",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (kind, id, last_run) in duplicates {
+        log::info!("Merging duplicate {} payout schedules.", kind);
+        let mut tx = pool.begin().await?;
+        sqlx::query(&backend.rewrite("UPDATE payout_schedule SET last_run = $1 WHERE id = $2"))
+            .bind(last_run)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&backend.rewrite("DELETE FROM payout_schedule WHERE kind = $1 AND id != $2"))
+            .bind(&kind)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+// Schema version of a database, None for databases from before versions
+// were tracked
+pub async fn schema_version(
+    pool: &AnyPool,
+    tables: &[String],
+) -> Result<Option<i64>, Box<dyn std::error::Error + Send + Sync>> {
+    if !tables.contains(&"schema_version".to_string()) {
+        return Ok(None);
+    }
+
+    let version = sqlx::query_scalar::<_, i64>("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(Some(version))
+}
+
+// Location of the SQLite database, None when another backend is used
+pub async fn sqlite_path(data_folder: &Path) -> Option<String> {
+    let config = get_config().await;
+    if config.value.db_backend.to_lowercase() != "sqlite" {
+        return None;
+    }
+
+    Some(format!(
+        "{}/{}",
+        data_folder.to_str().unwrap(),
+        config.value.db_path
+    ))
+}
+
+// Whether a query failed on a unique index
+pub fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
+}
+
+static DB_INSTANCE: OnceCell<Arc<DB>> = OnceCell::const_new();
+
+pub async fn setup_db(path: &PathBuf) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    sqlx::any::install_default_drivers();
+
+    let config = get_config().await;
+    let backend: Box<dyn Backend> = match config.value.db_backend.to_lowercase().as_str() {
+        "sqlite" => Box::new(sqlite::Sqlite::new(sqlite_path(path).await.unwrap())),
+        "postgres" | "postgresql" => Box::new(postgres::Postgres::new(config.value.db_url.clone())),
+        "mysql" | "mariadb" => Box::new(mysql::MySql::new(config.value.db_url.clone())),
+        other => return Err(format!("Unknown database backend: {}", other).into()),
+    };
+
+    log::info!("Using {} database backend.", backend.name());
+    let db = DB::init(backend).await?;
+    if let Err(e) = DB_INSTANCE.set(Arc::new(db)) {
+        return Err(e.into());
+    };
+
+    Ok(())
+}
+
+pub async fn get_db() -> Arc<DB> {
+    DB_INSTANCE.get().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLES: [&str; 21] = [
+        "players",
+        "homes",
+        "warps",
+        "balances",
+        "staffchat_messages",
+        "punishments",
+        "warnings",
+        "player_sessions",
+        "ip_bans",
+        "name_history",
+        "bank_accounts",
+        "bank_members",
+        "bank_transactions",
+        "payout_schedule",
+        "payouts",
+        "shop_stock",
+        "shop_trades",
+        "item_sales",
+        "auction_listings",
+        "auction_mailbox",
+        "schema_version",
+    ];
+
+    // The migrations read the default currency from the config, tests use
+    // the default config in a temporary folder
+    async fn setup() {
+        static SETUP: OnceCell<()> = OnceCell::const_new();
+        SETUP
+            .get_or_init(|| async {
+                let dir =
+                    std::env::temp_dir().join(format!("servercore-test-{}", std::process::id()));
+                std::fs::create_dir_all(&dir).unwrap();
+                crate::config::setup_config(&dir).await.unwrap();
+                sqlx::any::install_default_drivers();
+            })
+            .await;
+    }
+
+    fn temp_db_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "servercore-test-{}-{}.db",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    // Checks a migrated database, shared by every backend
+    async fn check_backend(db: &DB) {
+        let tables = db.backend.tables(&db.pool).await.unwrap();
+        for table in TABLES {
+            assert!(tables.contains(&table.to_string()), "missing {}", table);
+        }
+        assert_eq!(
+            schema_version(&db.pool, &tables).await.unwrap(),
+            Some(SCHEMA_VERSION)
+        );
+
+        // Sums decode as integers on every backend
+        let item = format!("test_item_{}", current_nanos());
+        let sold = sqlx::query_scalar::<_, i64>(db.sql(
+            "SELECT CAST(COALESCE(SUM(quantity), 0) AS BIGINT) FROM item_sales WHERE item = $1",
+        ))
+        .bind(&item)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(sold, 0);
+
+        for quantity in [3i64, 4] {
+            sqlx::query(db.sql(
+                "INSERT INTO item_sales (uuid, item, quantity, price, currency, sold_at)
+                    VALUES ($1, $2, $3, $4, $5, $6)",
+            ))
+            .bind("test")
+            .bind(&item)
+            .bind(quantity)
+            .bind(1.5)
+            .bind("money")
+            .bind(0i64)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        }
+
+        let sold = sqlx::query_scalar::<_, i64>(db.sql(
+            "SELECT CAST(COALESCE(SUM(quantity), 0) AS BIGINT) FROM item_sales WHERE item = $1",
+        ))
+        .bind(&item)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(sold, 7);
+
+        sqlx::query(db.sql("DELETE FROM item_sales WHERE item = $1"))
+            .bind(&item)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let (items, amount) = sqlx::query_as::<_, (i64, f64)>(db.sql(
+            "SELECT CAST(COALESCE(SUM(CASE WHEN item IS NULL THEN 0 ELSE 1 END), 0) AS BIGINT),
+                    COALESCE(SUM(amount), 0.0)
+                FROM auction_mailbox WHERE uuid = $1",
+        ))
+        .bind(&item)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!((items, amount), (0, 0.0));
+    }
+
+    fn current_nanos() -> u128 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    }
+
+    #[tokio::test]
+    async fn sqlite_migrates_new_database() {
+        setup().await;
+        let db = DB::init(Box::new(sqlite::Sqlite::new(":memory:".to_string())))
+            .await
+            .unwrap();
+        check_backend(&db).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_migrates_old_database() {
+        setup().await;
+        let path = temp_db_path("old");
+
+        // Tables as created before anything was migrated
+        {
+            let pool = AnyPool::connect(&format!("sqlite://{}?mode=rwc", path))
+                .await
+                .unwrap();
+            sqlx::query(
+                "CREATE TABLE players (
+                    id INTEGER PRIMARY KEY,
+                    uuid TEXT NOT NULL,
+                    nickname TEXT NOT NULL,
+                    balance REAL NOT NULL DEFAULT 0,
+                    playtime INTEGER NOT NULL DEFAULT 0
+                )",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            for table in ["homes", "warps"] {
+                sqlx::query(&format!(
+                    "CREATE TABLE {} (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+                    table
+                ))
+                .execute(&pool)
+                .await
+                .unwrap();
+            }
+            sqlx::query(
+                "INSERT INTO players (uuid, nickname, balance) VALUES ('a', 'Steve', 12.5)",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            pool.close().await;
+        }
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        check_backend(&db).await;
+
+        let columns = db.columns("players").await.unwrap();
+        for column in ["first_join", "display_name", "version", "pay_disabled"] {
+            assert!(columns.contains(&column.to_string()), "missing {}", column);
+        }
+
+        // The old balance column was copied into the default currency
+        let balances = sqlx::query_as::<_, (String, f64)>(
+            "SELECT currency, balance FROM balances WHERE player_id = 1",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            balances,
+            vec![(economy::default_currency().await.name, 12.5)]
+        );
+        db.pool.close().await;
+
+        // Running the migrations again changes nothing
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM balances")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+        db.pool.close().await;
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn sqlite_refuses_newer_schema() {
+        setup().await;
+        let path = temp_db_path("newer");
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        sqlx::query("UPDATE schema_version SET version = $1")
+            .bind(SCHEMA_VERSION + 1)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.pool.close().await;
+
+        assert!(DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn sqlite_and_postgres_keep_queries() {
+        let sql = "SELECT id FROM players WHERE uuid = $1 AND version = $2";
+        assert!(matches!(
+            sqlite::Sqlite::new(String::new()).rewrite(sql),
+            Cow::Borrowed(s) if s == sql
+        ));
+        assert!(matches!(
+            postgres::Postgres::new(String::new()).rewrite(sql),
+            Cow::Borrowed(s) if s == sql
+        ));
+    }
+
+    #[test]
+    fn mysql_replaces_placeholders() {
+        let mysql = mysql::MySql::new(String::new());
+        assert_eq!(
+            mysql.rewrite("UPDATE a SET b = $1 WHERE c = $2 AND d = $10 AND e = '$'"),
+            "UPDATE a SET b = ? WHERE c = ? AND d = ? AND e = '$'"
+        );
+        assert_eq!(
+            mysql.rewrite("SELECT CAST(COALESCE(SUM(quantity), 0) AS BIGINT) FROM item_sales"),
+            "SELECT CAST(COALESCE(SUM(quantity), 0) AS SIGNED) FROM item_sales"
+        );
+        assert_eq!(
+            mysql.rewrite("INSERT INTO payout_schedule (kind) VALUES ($1) ON CONFLICT DO NOTHING"),
+            "INSERT INTO payout_schedule (kind) VALUES (?) ON DUPLICATE KEY UPDATE id = id"
+        );
+        assert!(matches!(
+            mysql.rewrite("SELECT COUNT(*) FROM players"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(mysql.text_key("kind"), "kind(191)");
+    }
+
+    // Unique indexes and the schema version they were added in
+    const INDEXES: [(i64, &str); 7] = [
+        (10, "payout_schedule_kind"),
+        (11, "balances_player_currency"),
+        (12, "players_display_name"),
+        (13, "bank_accounts_name"),
+        (13, "bank_members_account_uuid"),
+        (14, "payouts_kind_period_uuid"),
+        (15, "shop_stock_item"),
+    ];
+
+    // Turns a fresh database into one of an older version, without the
+    // indexes added since
+    async fn downgrade(pool: &AnyPool, version: i64) {
+        for (added, index) in INDEXES {
+            if added > version {
+                sqlx::query(&format!("DROP INDEX {}", index))
+                    .execute(pool)
+                    .await
+                    .unwrap();
+            }
+        }
+        sqlx::query("UPDATE schema_version SET version = $1")
+            .bind(version)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_merges_duplicate_balances() {
+        setup().await;
+        let path = temp_db_path("balances");
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        // A database from before the index, two servers created a row each
+        downgrade(&db.pool, 10).await;
+        for balance in [10.0, 2.5] {
+            sqlx::query(
+                "INSERT INTO balances (player_id, currency, balance) VALUES (1, 'money', $1)",
+            )
+            .bind(balance)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        }
+        db.pool.close().await;
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        let balances = sqlx::query_as::<_, (String, f64)>(
+            "SELECT currency, balance FROM balances WHERE player_id = 1",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(balances, vec![("money".to_string(), 12.5)]);
+
+        let res = sqlx::query(db.sql(
+            "INSERT INTO balances (player_id, currency, balance) VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING",
+        ))
+        .bind(1i64)
+        .bind("money")
+        .bind(0.0)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(res.rows_affected(), 0);
+        db.pool.close().await;
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn sqlite_merges_duplicate_schedules() {
+        setup().await;
+        let path = temp_db_path("schedules");
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        // A database from before the indexes, with a schedule row per server
+        downgrade(&db.pool, 9).await;
+        for last_run in [100i64, 200] {
+            sqlx::query("INSERT INTO payout_schedule (kind, last_run) VALUES ('interest', $1)")
+                .bind(last_run)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        db.pool.close().await;
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        let schedules =
+            sqlx::query_as::<_, (String, i64)>("SELECT kind, last_run FROM payout_schedule")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(schedules, vec![("interest".to_string(), 200)]);
+
+        // Later inserts of the same kind are skipped
+        sqlx::query(db.sql(
+            "INSERT INTO payout_schedule (kind, last_run) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        ))
+        .bind("interest")
+        .bind(300i64)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM payout_schedule")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+        db.pool.close().await;
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn sqlite_clears_duplicate_nicknames() {
+        setup().await;
+        let path = temp_db_path("nicknames");
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        downgrade(&db.pool, 11).await;
+        for (uuid, name) in [("a", "Steve"), ("b", "Alex"), ("c", "Herobrine")] {
+            sqlx::query(
+                "INSERT INTO players (uuid, nickname, display_name, display_name_plain)
+                    VALUES ($1, $2, '&cNotch', 'notch')",
+            )
+            .bind(uuid)
+            .bind(name)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        }
+        db.pool.close().await;
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        let nicknamed = sqlx::query_scalar::<_, String>(
+            "SELECT uuid FROM players WHERE display_name_plain IS NOT NULL",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(nicknamed, vec!["a".to_string()]);
+
+        let err = sqlx::query("UPDATE players SET display_name_plain = 'notch' WHERE uuid = 'b'")
+            .execute(&db.pool)
+            .await
+            .unwrap_err();
+        assert!(is_unique_violation(&err));
+        db.pool.close().await;
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn sqlite_renames_duplicate_accounts() {
+        setup().await;
+        let path = temp_db_path("accounts");
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        downgrade(&db.pool, 12).await;
+        for (name, owner) in [("Vault", "a"), ("vault", "b")] {
+            sqlx::query(
+                "INSERT INTO bank_accounts (name, owner, balance, created_at, last_interest)
+                    VALUES ($1, $2, 5, 0, 0)",
+            )
+            .bind(name)
+            .bind(owner)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO bank_members (account, uuid, added_at) VALUES ($1, 'c', 0)")
+                .bind(name)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("INSERT INTO bank_members (account, uuid, added_at) VALUES ('Vault', 'c', 1)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.pool.close().await;
+
+        let db = DB::init(Box::new(sqlite::Sqlite::new(path.clone())))
+            .await
+            .unwrap();
+        let accounts = sqlx::query_as::<_, (String, String)>(
+            "SELECT name, name_key FROM bank_accounts ORDER BY id",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            accounts,
+            vec![
+                ("Vault".to_string(), "vault".to_string()),
+                ("vault-2".to_string(), "vault-2".to_string()),
+            ]
+        );
+        let members =
+            sqlx::query_scalar::<_, String>("SELECT account FROM bank_members ORDER BY id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(members, vec!["Vault".to_string(), "vault-2".to_string()]);
+        db.pool.close().await;
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn ddl_maps_types() {
+        let sql = "CREATE TABLE t (id INTEGER PRIMARY KEY, a INTEGER NOT NULL, b REAL)";
+        assert_eq!(sqlite::Sqlite::new(String::new()).ddl(sql), sql);
+        assert_eq!(
+            postgres::Postgres::new(String::new()).ddl(sql),
+            "CREATE TABLE t (id BIGSERIAL PRIMARY KEY, a BIGINT NOT NULL, b DOUBLE PRECISION)"
+        );
+        assert_eq!(
+            mysql::MySql::new(String::new()).ddl(sql),
+            "CREATE TABLE t (id BIGINT AUTO_INCREMENT PRIMARY KEY, a BIGINT NOT NULL, b DOUBLE)"
+        );
+    }
+
+    // Server backends are tested against a local database when its URL is
+    // set, e.g. a container started with
+    // docker run -e POSTGRES_PASSWORD=test -p 5432:5432 postgres
+    #[tokio::test]
+    async fn postgres_backend() {
+        let Ok(url) = std::env::var("SERVERCORE_TEST_POSTGRES_URL") else {
+            return;
+        };
+        setup().await;
+        let db = DB::init(Box::new(postgres::Postgres::new(url)))
+            .await
+            .unwrap();
+        check_backend(&db).await;
+    }
+
+    #[tokio::test]
+    async fn mysql_backend() {
+        let Ok(url) = std::env::var("SERVERCORE_TEST_MYSQL_URL") else {
+            return;
+        };
+        setup().await;
+        let db = DB::init(Box::new(mysql::MySql::new(url))).await.unwrap();
+        check_backend(&db).await;
+    }
+}
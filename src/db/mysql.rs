@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use sqlx::{migrate::MigrateDatabase, AnyPool, Row};
+
+use super::{map_types, Backend};
+
+#[derive(Debug)]
+pub struct MySql {
+    url: String,
+}
+
+impl MySql {
+    pub fn new(url: String) -> Self {
+        MySql { url }
+    }
+}
+
+// MySQL only knows positional ? placeholders, the $N placeholders are
+// always bound in order so they can be replaced one for one
+fn replace_placeholders(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '$' && chars.peek().is_some_and(|n| n.is_ascii_digit()) {
+            while chars.peek().is_some_and(|n| n.is_ascii_digit()) {
+                chars.next();
+            }
+            out.push('?');
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+#[async_trait]
+impl Backend for MySql {
+    fn name(&self) -> &'static str {
+        "MySQL"
+    }
+
+    async fn connect(&self) -> Result<AnyPool, Box<dyn std::error::Error + Send + Sync>> {
+        if !sqlx::MySql::database_exists(&self.url).await? {
+            log::info!("Creating new MySQL database.");
+            sqlx::MySql::create_database(&self.url).await?;
+        } else {
+            log::info!("Connecting to MySQL database.");
+        }
+
+        let pool = AnyPool::connect(&self.url).await?;
+        Ok(pool)
+    }
+
+    async fn tables(
+        &self,
+        pool: &AnyPool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let tables = sqlx::query(
+            "SELECT CAST(table_name AS CHAR) FROM information_schema.tables
+                WHERE table_schema = DATABASE()",
+        )
+        .map(|row: sqlx::any::AnyRow| row.get::<String, _>(0))
+        .fetch_all(pool)
+        .await?;
+
+        Ok(tables)
+    }
+
+    async fn columns(
+        &self,
+        pool: &AnyPool,
+        table: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let columns = sqlx::query(
+            "SELECT CAST(column_name AS CHAR) FROM information_schema.columns
+                WHERE table_schema = DATABASE() AND table_name = ?",
+        )
+        .bind(table)
+        .map(|row: sqlx::any::AnyRow| row.get::<String, _>(0))
+        .fetch_all(pool)
+        .await?;
+
+        Ok(columns)
+    }
+
+    // MySQL casts to SIGNED instead of BIGINT and has no ON CONFLICT, a
+    // duplicate key update that changes nothing skips the row instead
+    fn rewrite<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        if sql.contains('$')
+            || sql.contains(super::BIGINT_CAST)
+            || sql.contains(super::IGNORE_CONFLICT)
+        {
+            Cow::Owned(
+                replace_placeholders(sql)
+                    .replace(super::BIGINT_CAST, "AS SIGNED)")
+                    .replace(super::IGNORE_CONFLICT, "ON DUPLICATE KEY UPDATE id = id"),
+            )
+        } else {
+            Cow::Borrowed(sql)
+        }
+    }
+
+    fn ddl<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        Cow::Owned(map_types(
+            sql,
+            "BIGINT AUTO_INCREMENT PRIMARY KEY",
+            "BIGINT",
+            "DOUBLE",
+        ))
+    }
+
+    // Only a prefix of TEXT columns can be indexed, 191 characters fit the
+    // smallest key size of utf8mb4 tables
+    fn text_key<'a>(&self, column: &'a str) -> Cow<'a, str> {
+        Cow::Owned(format!("{}(191)", column))
+    }
+}
@@ -0,0 +1,79 @@
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use sqlx::{migrate::MigrateDatabase, AnyPool, Row};
+
+use super::{map_types, Backend};
+
+#[derive(Debug)]
+pub struct Postgres {
+    url: String,
+}
+
+impl Postgres {
+    pub fn new(url: String) -> Self {
+        Postgres { url }
+    }
+}
+
+#[async_trait]
+impl Backend for Postgres {
+    fn name(&self) -> &'static str {
+        "PostgreSQL"
+    }
+
+    async fn connect(&self) -> Result<AnyPool, Box<dyn std::error::Error + Send + Sync>> {
+        if !sqlx::Postgres::database_exists(&self.url).await? {
+            log::info!("Creating new PostgreSQL database.");
+            sqlx::Postgres::create_database(&self.url).await?;
+        } else {
+            log::info!("Connecting to PostgreSQL database.");
+        }
+
+        let pool = AnyPool::connect(&self.url).await?;
+        Ok(pool)
+    }
+
+    async fn tables(
+        &self,
+        pool: &AnyPool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        // Identifiers are their own type in information_schema
+        let tables = sqlx::query(
+            "SELECT CAST(table_name AS TEXT) FROM information_schema.tables
+                WHERE table_schema = current_schema()",
+        )
+        .map(|row: sqlx::any::AnyRow| row.get::<String, _>(0))
+        .fetch_all(pool)
+        .await?;
+
+        Ok(tables)
+    }
+
+    async fn columns(
+        &self,
+        pool: &AnyPool,
+        table: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let columns = sqlx::query(
+            "SELECT CAST(column_name AS TEXT) FROM information_schema.columns
+                WHERE table_schema = current_schema() AND table_name = $1",
+        )
+        .bind(table)
+        .map(|row: sqlx::any::AnyRow| row.get::<String, _>(0))
+        .fetch_all(pool)
+        .await?;
+
+        Ok(columns)
+    }
+
+    // Integers are stored as BIGINT so they decode as i64 like in SQLite
+    fn ddl<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        Cow::Owned(map_types(
+            sql,
+            "BIGSERIAL PRIMARY KEY",
+            "BIGINT",
+            "DOUBLE PRECISION",
+        ))
+    }
+}
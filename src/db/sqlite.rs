@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use sqlx::{migrate::MigrateDatabase, AnyPool, Row};
+
+use super::Backend;
+
+#[derive(Debug)]
+pub struct Sqlite {
+    path: String,
+}
+
+impl Sqlite {
+    pub fn new(path: String) -> Self {
+        Sqlite { path }
+    }
+}
+
+#[async_trait]
+impl Backend for Sqlite {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    async fn connect(&self) -> Result<AnyPool, Box<dyn std::error::Error + Send + Sync>> {
+        if !sqlx::Sqlite::database_exists(&self.path).await? {
+            log::info!("Creating new database at: {}", self.path);
+            sqlx::Sqlite::create_database(&self.path).await?;
+        } else {
+            log::info!("Loading database at: {}", self.path);
+        }
+
+        let pool = AnyPool::connect(&format!("sqlite://{}", self.path)).await?;
+        Ok(pool)
+    }
+
+    async fn tables(
+        &self,
+        pool: &AnyPool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let tables = sqlx::query("SELECT name FROM sqlite_master WHERE type='table'")
+            .map(|row: sqlx::any::AnyRow| row.get::<String, _>(0))
+            .fetch_all(pool)
+            .await?;
+
+        Ok(tables)
+    }
+
+    async fn backup(
+        &self,
+        pool: &AnyPool,
+        dest: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Writes a consistent, compacted copy without blocking writers for long
+        sqlx::query("VACUUM INTO $1")
+            .bind(dest)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn columns(
+        &self,
+        pool: &AnyPool,
+        table: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .map(|row: sqlx::any::AnyRow| row.get::<String, _>("name"))
+            .fetch_all(pool)
+            .await?;
+
+        Ok(columns)
+    }
+}
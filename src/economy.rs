@@ -0,0 +1,838 @@
+use std::sync::Arc;
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use lazy_static::lazy_static;
+use pumpkin::{entity::player::Player, plugin::Cancellable};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{
+    api::{self, BalanceChangeCause, BalanceChangeEvent, EventPhase, PaymentEvent},
+    cache::{add_balance, get_balance, is_loaded, rebase_balance},
+    config::{get_config, CurrencyValue, PayTaxTierValue},
+    db::get_db,
+    utils::current_sec,
+};
+
+pub use crate::api::EconomyError;
+
+impl From<sqlx::Error> for EconomyError {
+    fn from(e: sqlx::Error) -> Self {
+        EconomyError::Database(e.to_string())
+    }
+}
+
+// A currency from the config, amounts are kept to its decimals
+#[derive(Clone, Debug, PartialEq)]
+pub struct Currency {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u32,
+    pub starting_balance: f64,
+    pub symbol_suffix: bool,
+    pub singular: String,
+    pub plural: String,
+    pub thousands_separator: String,
+    pub decimal_separator: String,
+    pub compact: bool,
+}
+
+// Suffixes of compact amounts, each a thousand times the previous one
+const COMPACT_SUFFIXES: [&str; 5] = ["K", "M", "B", "T", "Q"];
+
+impl Currency {
+    fn from_value(value: &CurrencyValue) -> Self {
+        Currency {
+            name: value.name.to_lowercase(),
+            symbol: value.symbol.clone(),
+            decimals: value.decimals,
+            starting_balance: value.starting_balance,
+            symbol_suffix: value.symbol_position.eq_ignore_ascii_case("suffix"),
+            singular: value.singular.clone(),
+            plural: value.plural.clone(),
+            thousands_separator: value.thousands_separator.clone(),
+            decimal_separator: value.decimal_separator.clone(),
+            compact: value.compact,
+        }
+    }
+
+    pub fn round(&self, amount: f64) -> f64 {
+        let factor = 10f64.powi(self.decimals as i32);
+        (amount * factor).round() / factor
+    }
+
+    // Digits with separators, e.g. 1,234.50
+    fn format_number(&self, amount: f64, decimals: usize) -> String {
+        let digits = format!("{:.*}", decimals, amount.abs());
+        let (int, fraction) = match digits.split_once('.') {
+            Some((int, fraction)) => (int, Some(fraction)),
+            None => (digits.as_str(), None),
+        };
+
+        let mut number = String::new();
+        for (i, c) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i) % 3 == 0 {
+                number.push_str(&self.thousands_separator);
+            }
+            number.push(c);
+        }
+        if let Some(fraction) = fraction {
+            number.push_str(&self.decimal_separator);
+            number.push_str(fraction);
+        }
+
+        number
+    }
+
+    // Adds the sign, symbol and name around a formatted number
+    fn decorate(&self, amount: f64, number: String) -> String {
+        let sign = if amount < 0.0 { "-" } else { "" };
+        let mut text = if self.symbol_suffix {
+            format!("{}{}{}", sign, number, self.symbol)
+        } else {
+            format!("{}{}{}", sign, self.symbol, number)
+        };
+
+        let name = if amount.abs() == 1.0 || self.plural.is_empty() {
+            &self.singular
+        } else {
+            &self.plural
+        };
+        if !name.is_empty() {
+            text.push(' ');
+            text.push_str(name);
+        }
+
+        text
+    }
+
+    // An amount with the currency symbol, as shown to players
+    pub fn format(&self, amount: f64) -> String {
+        // Avoids showing -0.00 for tiny negative amounts
+        let amount = match self.round(amount) {
+            a if a == 0.0 => 0.0,
+            a => a,
+        };
+        self.decorate(amount, self.format_number(amount, self.decimals as usize))
+    }
+
+    // Large amounts shortened to e.g. 1.2M, smaller ones as by format
+    pub fn format_compact(&self, amount: f64) -> String {
+        let mut value = self.round(amount).abs();
+        let mut suffix = None;
+        for s in COMPACT_SUFFIXES {
+            // Rounding 999,950 to one decimal would show 1000.0K
+            if value < 999.95 {
+                break;
+            }
+            value /= 1000.0;
+            suffix = Some(s);
+        }
+
+        let Some(suffix) = suffix else {
+            return self.format(amount);
+        };
+
+        let mut number = self.format_number(value, 1);
+        let zero = format!("{}0", self.decimal_separator);
+        if number.ends_with(&zero) {
+            number.truncate(number.len() - zero.len());
+        }
+        number.push_str(suffix);
+
+        self.decorate(amount, number)
+    }
+
+    // Compact for currencies configured that way, used in summaries
+    pub fn format_short(&self, amount: f64) -> String {
+        if self.compact {
+            self.format_compact(amount)
+        } else {
+            self.format(amount)
+        }
+    }
+}
+
+// Configured currencies, the default one first
+pub async fn currencies() -> Vec<Currency> {
+    let config = get_config().await;
+    if config.value.currencies.is_empty() {
+        // Configs from before multiple currencies
+        return vec![Currency {
+            name: "money".to_string(),
+            symbol: config.value.eco_symbol.clone(),
+            decimals: 2,
+            starting_balance: config.value.eco_starting_balance,
+            symbol_suffix: false,
+            singular: String::new(),
+            plural: String::new(),
+            thousands_separator: ",".to_string(),
+            decimal_separator: ".".to_string(),
+            compact: false,
+        }];
+    }
+
+    config
+        .value
+        .currencies
+        .iter()
+        .map(Currency::from_value)
+        .collect()
+}
+
+pub async fn default_currency() -> Currency {
+    currencies().await.remove(0)
+}
+
+// Currency by name, names are matched case-insensitively
+pub async fn get_currency(name: &str) -> Result<Currency, EconomyError> {
+    currencies()
+        .await
+        .into_iter()
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| EconomyError::UnknownCurrency(name.to_string()))
+}
+
+// The named currency, or the default one when commands leave it out
+pub async fn currency_or_default(name: Option<&str>) -> Result<Currency, EconomyError> {
+    match name {
+        Some(name) => get_currency(name).await,
+        None => Ok(default_currency().await),
+    }
+}
+
+#[derive(Clone)]
+pub struct PendingPayment {
+    pub target: Arc<Player>,
+    pub currency: Currency,
+    pub amount: f64,
+    pub expires_at: i64,
+}
+
+lazy_static! {
+    // Serializes balance changes, so a balance can not change between
+    // checking and updating it
+    static ref LOCK: Mutex<()> = Mutex::new(());
+    // Payer UUID -> Payment awaiting /pay confirm
+    static ref PENDING_PAYMENTS: DashMap<String, PendingPayment> = DashMap::new();
+    // Payer UUID -> Time of their last payment
+    static ref LAST_PAYMENTS: DashMap<String, i64> = DashMap::new();
+}
+
+pub async fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().await
+}
+
+pub(crate) fn check_amount(amount: f64) -> Result<(), EconomyError> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(EconomyError::InvalidAmount);
+    }
+
+    Ok(())
+}
+
+// Balance of any player that joined before. Players without a row for the
+// currency have not held it yet and have nothing.
+pub async fn balance(player_uuid: &str, currency: &str) -> Result<f64, EconomyError> {
+    if is_loaded(player_uuid) {
+        return Ok(get_balance(player_uuid, currency));
+    }
+
+    let db = get_db().await;
+    let balance = sqlx::query_scalar::<_, Option<f64>>(db.sql(
+        "SELECT b.balance FROM players p
+            LEFT JOIN balances b ON b.player_id = p.id AND b.currency = $1
+            WHERE p.uuid = $2",
+    ))
+    .bind(currency)
+    .bind(player_uuid)
+    .fetch_optional(&db.pool)
+    .await?
+    .ok_or(EconomyError::PlayerNotFound)?;
+
+    Ok(balance.unwrap_or(0.0))
+}
+
+// Changes a balance, online players are changed in the cache and offline
+// players directly in the database. Withdrawals never overdraw, returns the
+// new balance. Callers must hold the lock.
+pub(crate) async fn apply(
+    player_uuid: &str,
+    currency: &str,
+    delta: f64,
+) -> Result<f64, EconomyError> {
+    if is_loaded(player_uuid) {
+        if get_balance(player_uuid, currency) + delta < 0.0 {
+            return Err(EconomyError::InsufficientFunds);
+        }
+        if let Some(balance) = add_balance(player_uuid, currency, delta) {
+            return Ok(balance);
+        }
+    }
+
+    let db = get_db().await;
+    create_row(&mut db.pool.acquire().await?, player_uuid, currency).await?;
+
+    // The check is part of the update, other servers may change the row too
+    let res = sqlx::query(db.sql(
+        "UPDATE balances SET balance = balance + $1
+            WHERE player_id = (SELECT id FROM players WHERE uuid = $2) AND currency = $3
+            AND balance + $4 >= 0",
+    ))
+    .bind(delta)
+    .bind(player_uuid)
+    .bind(currency)
+    .bind(delta)
+    .execute(&db.pool)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        // Tells a missing player apart from a lacking balance
+        balance(player_uuid, currency).await?;
+        return Err(EconomyError::InsufficientFunds);
+    }
+
+    balance(player_uuid, currency).await
+}
+
+// Creates the balance row of a player that has not held the currency yet.
+// Another server may create it at the same time, the unique index keeps it
+// to one row.
+async fn create_row(
+    conn: &mut sqlx::AnyConnection,
+    player_uuid: &str,
+    currency: &str,
+) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    let Some(id) = sqlx::query_scalar::<_, i64>(db.sql("SELECT id FROM players WHERE uuid = $1"))
+        .bind(player_uuid)
+        .fetch_optional(&mut *conn)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    sqlx::query(db.sql(
+        "INSERT INTO balances (player_id, currency, balance) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING",
+    ))
+    .bind(id)
+    .bind(currency)
+    .bind(0.0)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// A balance change written in a transaction that has not committed yet
+#[must_use]
+pub(crate) struct StagedChange {
+    player_uuid: String,
+    currency: String,
+    delta: f64,
+    cached: bool,
+}
+
+impl StagedChange {
+    // Brings the cache in line once the transaction committed, returns the
+    // new balance
+    pub(crate) async fn committed(self) -> Result<f64, EconomyError> {
+        if self.cached {
+            if let Some(balance) = rebase_balance(&self.player_uuid, &self.currency, self.delta) {
+                return Ok(balance);
+            }
+        }
+
+        balance(&self.player_uuid, &self.currency).await
+    }
+}
+
+// Changes a balance in the database as part of a larger transaction, so it
+// commits or rolls back together with the rest. Withdrawals never overdraw,
+// online players are checked against their cached balance. Callers must
+// hold the lock until the returned change is committed.
+pub(crate) async fn apply_in(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    player_uuid: &str,
+    currency: &str,
+    delta: f64,
+) -> Result<StagedChange, EconomyError> {
+    // The cache also holds changes that are not synced yet
+    let cached = is_loaded(player_uuid);
+    if cached && get_balance(player_uuid, currency) + delta < 0.0 {
+        return Err(EconomyError::InsufficientFunds);
+    }
+
+    let db = get_db().await;
+    create_row(&mut **tx, player_uuid, currency).await?;
+
+    let mut query = sqlx::query(db.sql(if cached {
+        "UPDATE balances SET balance = balance + $1
+            WHERE player_id = (SELECT id FROM players WHERE uuid = $2) AND currency = $3"
+    } else {
+        "UPDATE balances SET balance = balance + $1
+            WHERE player_id = (SELECT id FROM players WHERE uuid = $2) AND currency = $3
+            AND balance + $4 >= 0"
+    }))
+    .bind(delta)
+    .bind(player_uuid)
+    .bind(currency);
+    if !cached {
+        query = query.bind(delta);
+    }
+    let res = query.execute(&mut **tx).await?;
+
+    if res.rows_affected() == 0 {
+        // Tells a missing player apart from a lacking balance
+        balance(player_uuid, currency).await?;
+        return Err(EconomyError::InsufficientFunds);
+    }
+
+    Ok(StagedChange {
+        player_uuid: player_uuid.to_string(),
+        currency: currency.to_string(),
+        delta,
+        cached,
+    })
+}
+
+// Fires the pre-event of a balance change, returns the amount listeners
+// settled on
+pub(crate) async fn before_change(
+    player_uuid: &str,
+    currency: &Currency,
+    cause: BalanceChangeCause,
+    amount: f64,
+) -> Result<f64, EconomyError> {
+    let event = api::fire(BalanceChangeEvent::new(
+        player_uuid,
+        &currency.name,
+        cause,
+        amount,
+    ))
+    .await;
+    if event.cancelled() {
+        return Err(EconomyError::Cancelled);
+    }
+
+    Ok(currency.round(event.amount))
+}
+
+pub(crate) async fn after_change(
+    player_uuid: &str,
+    currency: &Currency,
+    cause: BalanceChangeCause,
+    amount: f64,
+    balance: f64,
+) {
+    let mut event = BalanceChangeEvent::new(player_uuid, &currency.name, cause, amount);
+    event.phase = EventPhase::Post;
+    event.balance = Some(balance);
+    api::fire(event).await;
+}
+
+pub async fn deposit(player_uuid: &str, currency: &str, amount: f64) -> Result<f64, EconomyError> {
+    let currency = get_currency(currency).await?;
+    let amount = currency.round(amount);
+    check_amount(amount)?;
+    let amount = before_change(player_uuid, &currency, BalanceChangeCause::Deposit, amount).await?;
+    check_amount(amount)?;
+
+    let balance = {
+        let _guard = lock().await;
+        apply(player_uuid, &currency.name, amount).await?
+    };
+
+    after_change(
+        player_uuid,
+        &currency,
+        BalanceChangeCause::Deposit,
+        amount,
+        balance,
+    )
+    .await;
+    Ok(balance)
+}
+
+pub async fn withdraw(player_uuid: &str, currency: &str, amount: f64) -> Result<f64, EconomyError> {
+    let currency = get_currency(currency).await?;
+    let amount = currency.round(amount);
+    check_amount(amount)?;
+    let amount =
+        before_change(player_uuid, &currency, BalanceChangeCause::Withdraw, amount).await?;
+    check_amount(amount)?;
+
+    let balance = {
+        let _guard = lock().await;
+        apply(player_uuid, &currency.name, -amount).await?
+    };
+
+    after_change(
+        player_uuid,
+        &currency,
+        BalanceChangeCause::Withdraw,
+        amount,
+        balance,
+    )
+    .await;
+    Ok(balance)
+}
+
+// A completed payment
+#[derive(Clone, Copy, Debug)]
+pub struct Payment {
+    // Amount taken from the payer
+    pub amount: f64,
+    // Part of the amount the recipient did not get
+    pub tax: f64,
+}
+
+// Tax on a /pay payment of the given amount, rounded to the currency's
+// decimals
+pub async fn pay_tax(currency: &Currency, amount: f64) -> f64 {
+    let config = get_config().await;
+    tax(
+        &config.value.pay_tax_mode,
+        config.value.pay_tax_rate,
+        &config.value.pay_tax_tiers,
+        currency,
+        amount,
+    )
+}
+
+// Tax under a mode, tiered taxes use the rate of the highest tier the amount
+// reaches
+fn tax(mode: &str, rate: f64, tiers: &[PayTaxTierValue], currency: &Currency, amount: f64) -> f64 {
+    let tax = match mode.to_lowercase().as_str() {
+        "percent" => amount * rate / 100.0,
+        "flat" => rate,
+        "tiered" => tiers
+            .iter()
+            .filter(|t| amount >= t.min)
+            .max_by(|a, b| a.min.total_cmp(&b.min))
+            .map_or(0.0, |t| amount * t.rate / 100.0),
+        _ => 0.0,
+    };
+
+    currency.round(tax).max(0.0)
+}
+
+// Moves money between players, either both balances change or neither does
+pub async fn transfer(
+    from_uuid: &str,
+    to_uuid: &str,
+    currency: &str,
+    amount: f64,
+) -> Result<(), EconomyError> {
+    transfer_taxed(from_uuid, to_uuid, currency, amount, false).await?;
+    Ok(())
+}
+
+// Moves money between players, the recipient gets the amount minus the
+// /pay tax which goes to the tax account if there is one. Exempt payers pass
+// taxed as false.
+pub async fn transfer_taxed(
+    from_uuid: &str,
+    to_uuid: &str,
+    currency: &str,
+    amount: f64,
+    taxed: bool,
+) -> Result<Payment, EconomyError> {
+    let currency = get_currency(currency).await?;
+    let amount = currency.round(amount);
+    check_amount(amount)?;
+    if from_uuid == to_uuid {
+        return Err(EconomyError::SamePlayer);
+    }
+
+    let tax = if taxed {
+        pay_tax(&currency, amount).await
+    } else {
+        0.0
+    };
+    let mut event = PaymentEvent::new(from_uuid, to_uuid, &currency.name, amount);
+    event.tax = tax;
+    let event = api::fire(event).await;
+    if event.cancelled() {
+        return Err(EconomyError::Cancelled);
+    }
+    let amount = currency.round(event.amount);
+    check_amount(amount)?;
+    // Listeners that only changed the amount get the tax on the new amount
+    let tax = if event.tax != tax {
+        currency.round(event.tax).max(0.0)
+    } else if taxed {
+        pay_tax(&currency, amount).await
+    } else {
+        0.0
+    };
+    // The recipient has to get something
+    check_amount(amount - tax)?;
+
+    let tax_account = get_config().await.value.pay_tax_account.clone();
+    let pays_tax_account = tax > 0.0 && !tax_account.is_empty();
+
+    {
+        let _guard = lock().await;
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let mut changes = vec![
+            apply_in(&mut tx, from_uuid, &currency.name, -amount).await?,
+            apply_in(&mut tx, to_uuid, &currency.name, amount - tax).await?,
+        ];
+        // Taxes without an account leave the economy, nothing is taken
+        // when the account is missing
+        if pays_tax_account {
+            match apply_in(&mut tx, &tax_account, &currency.name, tax).await {
+                Err(EconomyError::PlayerNotFound) => {
+                    log::error!("Tax account {} does not exist", tax_account);
+                    return Err(EconomyError::TaxAccountNotFound);
+                }
+                res => changes.push(res?),
+            }
+        }
+        tx.commit().await?;
+
+        // The money moved, a balance that can not be read back is only logged
+        for change in changes {
+            if let Err(e) = change.committed().await {
+                log::error!("Failed to read a balance after a transfer: {}", e);
+            }
+        }
+    }
+
+    let mut event = PaymentEvent::new(from_uuid, to_uuid, &currency.name, amount);
+    event.phase = EventPhase::Post;
+    event.tax = tax;
+    api::fire(event).await;
+
+    Ok(Payment { amount, tax })
+}
+
+// Sets a balance outright, used by administrators
+pub async fn set_balance(
+    player_uuid: &str,
+    currency: &str,
+    amount: f64,
+) -> Result<(), EconomyError> {
+    let currency = get_currency(currency).await?;
+    let amount = currency.round(amount);
+    if !amount.is_finite() || amount < 0.0 {
+        return Err(EconomyError::InvalidAmount);
+    }
+    let amount = before_change(player_uuid, &currency, BalanceChangeCause::Set, amount).await?;
+    if !amount.is_finite() || amount < 0.0 {
+        return Err(EconomyError::InvalidAmount);
+    }
+
+    {
+        let _guard = lock().await;
+        let current = balance(player_uuid, &currency.name).await?;
+        if current != amount {
+            apply(player_uuid, &currency.name, amount - current).await?;
+        }
+    }
+
+    after_change(
+        player_uuid,
+        &currency,
+        BalanceChangeCause::Set,
+        amount,
+        amount,
+    )
+    .await;
+    Ok(())
+}
+
+pub fn set_pending_payment(payer_uuid: &str, payment: PendingPayment) {
+    PENDING_PAYMENTS.insert(payer_uuid.to_string(), payment);
+}
+
+// Takes the payment awaiting confirmation, None if there is none or it expired
+pub fn take_pending_payment(payer_uuid: &str) -> Option<PendingPayment> {
+    PENDING_PAYMENTS
+        .remove(payer_uuid)
+        .map(|(_, p)| p)
+        .filter(|p| p.expires_at >= current_sec())
+}
+
+// Seconds until the player may pay again
+pub async fn pay_cooldown_remaining(payer_uuid: &str) -> i64 {
+    let cooldown = get_config().await.value.pay_cooldown;
+    LAST_PAYMENTS
+        .get(payer_uuid)
+        .map_or(0, |last| (*last + cooldown - current_sec()).max(0))
+}
+
+// Starts the cooldown unless it is still running, in one step so that two
+// quick payments can not both pass. Returns the time of the previous payment
+// to hand back to release_payment, or the seconds left.
+pub async fn reserve_payment(payer_uuid: &str) -> Result<Option<i64>, i64> {
+    let cooldown = get_config().await.value.pay_cooldown;
+    let now = current_sec();
+    match LAST_PAYMENTS.entry(payer_uuid.to_string()) {
+        Entry::Occupied(mut entry) => {
+            let remaining = *entry.get() + cooldown - now;
+            if remaining > 0 {
+                return Err(remaining);
+            }
+            Ok(Some(entry.insert(now)))
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(now);
+            Ok(None)
+        }
+    }
+}
+
+// Gives back a reserved cooldown after the payment failed
+pub fn release_payment(payer_uuid: &str, previous: Option<i64>) {
+    match previous {
+        Some(last) => {
+            LAST_PAYMENTS.insert(payer_uuid.to_string(), last);
+        }
+        None => {
+            LAST_PAYMENTS.remove(payer_uuid);
+        }
+    }
+}
+
+// Forgets the payment a player that left was asked to confirm. Cooldowns
+// stay so rejoining does not reset them, only those that ran out are dropped.
+pub async fn clear_payments(payer_uuid: &str) {
+    PENDING_PAYMENTS.remove(payer_uuid);
+
+    let cooldown = get_config().await.value.pay_cooldown;
+    let now = current_sec();
+    LAST_PAYMENTS.retain(|_, last| *last + cooldown > now);
+}
+
+// Whether a player accepts payments, players turn them off with /paytoggle
+pub async fn accepts_payments(player_uuid: &str) -> Result<bool, EconomyError> {
+    let db = get_db().await;
+    let disabled =
+        sqlx::query_scalar::<_, i64>(db.sql("SELECT pay_disabled FROM players WHERE uuid = $1"))
+            .bind(player_uuid)
+            .fetch_optional(&db.pool)
+            .await?
+            .ok_or(EconomyError::PlayerNotFound)?;
+
+    Ok(disabled == 0)
+}
+
+pub async fn set_accepts_payments(player_uuid: &str, accepts: bool) -> Result<(), EconomyError> {
+    let db = get_db().await;
+    sqlx::query(db.sql("UPDATE players SET pay_disabled = $1 WHERE uuid = $2"))
+        .bind(!accepts as i64)
+        .bind(player_uuid)
+        .execute(&db.pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn currency(decimals: u32, thousands: &str, decimal: &str) -> Currency {
+        Currency {
+            name: "money".to_string(),
+            symbol: "$".to_string(),
+            decimals,
+            starting_balance: 0.0,
+            symbol_suffix: false,
+            singular: String::new(),
+            plural: String::new(),
+            thousands_separator: thousands.to_string(),
+            decimal_separator: decimal.to_string(),
+            compact: true,
+        }
+    }
+
+    #[test]
+    fn formats_amounts() {
+        let money = currency(2, ",", ".");
+        assert_eq!(money.format(0.0), "$0.00");
+        assert_eq!(money.format(1234567.891), "$1,234,567.89");
+        assert_eq!(money.format(-1234.5), "-$1,234.50");
+        // Negative zero and amounts rounding to zero have no sign
+        assert_eq!(money.format(-0.0), "$0.00");
+        assert_eq!(money.format(-0.001), "$0.00");
+    }
+
+    #[test]
+    fn formats_without_decimals() {
+        let money = currency(0, ".", ",");
+        assert_eq!(money.format(1234567.0), "$1.234.567");
+        assert_eq!(money.format(999.6), "$1.000");
+        assert_eq!(money.format_compact(1260000.0), "$1,3M");
+        assert_eq!(money.format_compact(2000.0), "$2K");
+    }
+
+    #[test]
+    fn formats_compact_amounts() {
+        let money = currency(2, ",", ".");
+        assert_eq!(money.format_compact(999.94), "$999.94");
+        // Would otherwise show as 1000.0K
+        assert_eq!(money.format_compact(999.95), "$1K");
+        assert_eq!(money.format_compact(999_950.0), "$1M");
+        assert_eq!(money.format_compact(1500.0), "$1.5K");
+        assert_eq!(money.format_compact(-1500.0), "-$1.5K");
+        assert_eq!(money.format_compact(-0.0), "$0.00");
+        assert_eq!(money.format_compact(2.5e18), "$2,500Q");
+    }
+
+    #[test]
+    fn formats_currency_names() {
+        let mut coins = currency(0, ",", ".");
+        coins.symbol_suffix = true;
+        coins.symbol = "c".to_string();
+        coins.singular = "coin".to_string();
+        coins.plural = "coins".to_string();
+        assert_eq!(coins.format(1.0), "1c coin");
+        assert_eq!(coins.format(-1.0), "-1c coin");
+        assert_eq!(coins.format(0.0), "0c coins");
+        assert_eq!(coins.format(2.0), "2c coins");
+        // Rounds to one before picking the name
+        assert_eq!(coins.format(1.2), "1c coin");
+
+        coins.plural = String::new();
+        assert_eq!(coins.format(5.0), "5c coin");
+    }
+
+    #[test]
+    fn picks_the_highest_tax_tier() {
+        let money = currency(2, ",", ".");
+        let tiers = [
+            PayTaxTierValue {
+                min: 1000.0,
+                rate: 2.5,
+            },
+            PayTaxTierValue {
+                min: 0.0,
+                rate: 1.0,
+            },
+            PayTaxTierValue {
+                min: 10000.0,
+                rate: 5.0,
+            },
+        ];
+        assert_eq!(tax("tiered", 0.0, &tiers, &money, 500.0), 5.0);
+        assert_eq!(tax("tiered", 0.0, &tiers, &money, 1000.0), 25.0);
+        assert_eq!(tax("Tiered", 0.0, &tiers, &money, 20000.0), 1000.0);
+        // Amounts below every tier are not taxed
+        assert_eq!(tax("tiered", 0.0, &tiers[..1], &money, 999.99), 0.0);
+        assert_eq!(tax("tiered", 0.0, &[], &money, 500.0), 0.0);
+    }
+
+    #[test]
+    fn taxes_by_mode() {
+        let money = currency(2, ",", ".");
+        assert_eq!(tax("percent", 1.5, &[], &money, 33.33), 0.5);
+        assert_eq!(tax("flat", 2.0, &[], &money, 33.33), 2.0);
+        assert_eq!(tax("flat", -2.0, &[], &money, 33.33), 0.0);
+        assert_eq!(tax("none", 50.0, &[], &money, 33.33), 0.0);
+    }
+}
@@ -0,0 +1,381 @@
+use std::path::{Path, PathBuf};
+
+use serde_yaml::Value;
+
+use crate::{
+    config::get_data_folder,
+    db::{get_db, is_unique_violation},
+    economy,
+    nick::{self, NickError},
+};
+
+#[derive(Debug, Default)]
+pub struct Counts {
+    pub imported: usize,
+    pub skipped: usize,
+    pub conflicting: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub players: Counts,
+    pub homes: Counts,
+    pub warps: Counts,
+    pub nicknames: Counts,
+    // One line for every record that was not imported
+    pub details: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Record {
+    Player,
+    Home,
+    Warp,
+    Nickname,
+}
+
+impl ImportReport {
+    fn counts(&mut self, record: Record) -> &mut Counts {
+        match record {
+            Record::Player => &mut self.players,
+            Record::Home => &mut self.homes,
+            Record::Warp => &mut self.warps,
+            Record::Nickname => &mut self.nicknames,
+        }
+    }
+
+    fn skip(&mut self, record: Record, detail: String) {
+        self.counts(record).skipped += 1;
+        self.details.push(format!("Skipped {}", detail));
+    }
+
+    fn conflict(&mut self, record: Record, detail: String) {
+        self.counts(record).conflicting += 1;
+        self.details.push(format!("Conflict {}", detail));
+    }
+}
+
+#[derive(Debug)]
+struct Location {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+// Essentials writes numbers as strings in some versions
+fn number(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn string(value: Option<&Value>) -> Option<String> {
+    value?.as_str().map(|s| s.to_string())
+}
+
+fn location(value: Option<&Value>) -> Option<Location> {
+    let value = value?;
+    Some(Location {
+        x: number(value.get("x"))?,
+        y: number(value.get("y"))?,
+        z: number(value.get("z"))?,
+    })
+}
+
+// Essentials names userdata files after the hyphenated UUID
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36
+        && s.chars().enumerate().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+// Relative directories are looked up in the plugin folder
+fn resolve_dir(dir: &str) -> PathBuf {
+    let path = Path::new(dir);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        get_data_folder().join(path)
+    }
+}
+
+async fn read_yaml(
+    dir: &Path,
+) -> Result<Vec<(String, Result<Value, String>)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_none_or(|e| e != "yml") {
+            continue;
+        }
+
+        let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+        let value = match tokio::fs::read_to_string(&path).await {
+            Ok(data) => serde_yaml::from_str::<Value>(&data).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        files.push((stem, value));
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+// Imports EssentialsX userdata and warps from its plugin folder. Players
+// and warps that already exist are reported as conflicts and left alone.
+pub async fn import(dir: &str) -> Result<ImportReport, Box<dyn std::error::Error + Send + Sync>> {
+    let dir = resolve_dir(dir);
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory.", dir.display()).into());
+    }
+
+    let mut report = ImportReport::default();
+    import_players(&dir.join("userdata"), &mut report).await?;
+    import_warps(&dir.join("warps"), &mut report).await?;
+
+    log::info!(
+        "Imported {} players, {} homes and {} warps from {}.",
+        report.players.imported,
+        report.homes.imported,
+        report.warps.imported,
+        dir.display()
+    );
+    for detail in report.details.iter() {
+        log::info!("{}", detail);
+    }
+
+    Ok(report)
+}
+
+async fn import_players(
+    dir: &Path,
+    report: &mut ImportReport,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+    let currency = economy::default_currency().await;
+
+    for (uuid, data) in read_yaml(dir).await? {
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                report.skip(Record::Player, format!("player {}: {}", uuid, e));
+                continue;
+            }
+        };
+
+        if !is_uuid(&uuid) {
+            report.skip(
+                Record::Player,
+                format!("player {}: file name is not a UUID", uuid),
+            );
+            continue;
+        }
+
+        let Some(nickname) =
+            string(data.get("last-account-name")).or_else(|| string(data.get("lastAccountName")))
+        else {
+            report.skip(Record::Player, format!("player {}: no account name", uuid));
+            continue;
+        };
+
+        let existing =
+            sqlx::query_scalar::<_, i64>(db.sql("SELECT id FROM players WHERE uuid = $1"))
+                .bind(&uuid)
+                .fetch_optional(&db.pool)
+                .await?;
+        if existing.is_some() {
+            report.conflict(
+                Record::Player,
+                format!("player {} ({}): already exists", nickname, uuid),
+            );
+            continue;
+        }
+
+        // A balance that could not be spent or paid out is not guessed at
+        let balance = match data.get("money") {
+            None => 0.0,
+            Some(money) => match number(Some(money)) {
+                Some(m) if m.is_finite() && m >= 0.0 => currency.round(m),
+                _ => {
+                    report.skip(
+                        Record::Player,
+                        format!("player {} ({}): invalid balance", nickname, uuid),
+                    );
+                    continue;
+                }
+            },
+        };
+        let logout = location(data.get("logoutlocation"));
+        // Timestamps are in milliseconds
+        let last_seen = number(data.get("timestamps").and_then(|t| t.get("logout")))
+            .map(|t| (t / 1000.0) as i64);
+
+        let mut homes = Vec::new();
+        if let Some(Value::Mapping(entries)) = data.get("homes") {
+            for (name, home) in entries.iter() {
+                let name = name.as_str().unwrap_or_default().to_string();
+                match location(Some(home)) {
+                    Some(loc) => homes.push((name, loc)),
+                    None => report.skip(
+                        Record::Home,
+                        format!("home {} of {}: no valid location", name, nickname),
+                    ),
+                }
+            }
+        }
+
+        // Essentials uses § and may prefix nicknames with ~
+        let mut display_name = None;
+        if let Some(raw) = string(data.get("nickname")) {
+            let raw = raw.replace('§', "&");
+            let raw = raw.trim_start_matches('~');
+
+            match nick::validate(&uuid, raw, true, true).await {
+                Ok(()) => display_name = Some(raw.to_string()),
+                Err(NickError::Taken) => report.conflict(
+                    Record::Nickname,
+                    format!("nickname {} of {}: already taken", raw, nickname),
+                ),
+                Err(e) => report.skip(
+                    Record::Nickname,
+                    format!("nickname {} of {}: {}", raw, nickname, e.message()),
+                ),
+            }
+        }
+
+        // A player is imported with everything belonging to them or not at
+        // all, a partly imported player would be a conflict on the next run
+        let mut tx = db.pool.begin().await?;
+
+        sqlx::query(db.sql(
+            "INSERT INTO players (uuid, nickname, last_seen, logout_x, logout_y, logout_z)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+        ))
+        .bind(&uuid)
+        .bind(&nickname)
+        .bind(last_seen)
+        .bind(logout.as_ref().map(|l| l.x))
+        .bind(logout.as_ref().map(|l| l.y))
+        .bind(logout.as_ref().map(|l| l.z))
+        .execute(&mut *tx)
+        .await?;
+
+        let player_id =
+            sqlx::query_scalar::<_, i64>(db.sql("SELECT id FROM players WHERE uuid = $1"))
+                .bind(&uuid)
+                .fetch_one(&mut *tx)
+                .await?;
+
+        // EssentialsX has a single currency, it becomes the default one
+        sqlx::query(
+            db.sql("INSERT INTO balances (player_id, currency, balance) VALUES ($1, $2, $3)"),
+        )
+        .bind(player_id)
+        .bind(&currency.name)
+        .bind(balance)
+        .execute(&mut *tx)
+        .await?;
+
+        for (name, loc) in homes.iter() {
+            sqlx::query(
+                db.sql("INSERT INTO homes (user_id, name, x, y, z) VALUES ($1, $2, $3, $4, $5)"),
+            )
+            .bind(player_id)
+            .bind(name)
+            .bind(loc.x)
+            .bind(loc.y)
+            .bind(loc.z)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        if let Some(raw) = &display_name {
+            let res = sqlx::query(db.sql(
+                "UPDATE players SET display_name = $1, display_name_plain = $2 WHERE uuid = $3",
+            ))
+            .bind(raw)
+            .bind(nick::strip_codes(raw).to_lowercase())
+            .bind(&uuid)
+            .execute(&mut *tx)
+            .await;
+            // Taken by a player on the server since it was checked, the
+            // transaction is dropped so the player is left for the next run
+            match res {
+                Ok(_) => {}
+                Err(e) if is_unique_violation(&e) => {
+                    report.conflict(
+                        Record::Nickname,
+                        format!("nickname {} of {}: already taken", raw, nickname),
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        tx.commit().await?;
+
+        report.players.imported += 1;
+        report.homes.imported += homes.len();
+        if display_name.is_some() {
+            report.nicknames.imported += 1;
+        }
+    }
+
+    Ok(())
+}
+
+async fn import_warps(
+    dir: &Path,
+    report: &mut ImportReport,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    for (stem, data) in read_yaml(dir).await? {
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                report.skip(Record::Warp, format!("warp {}: {}", stem, e));
+                continue;
+            }
+        };
+
+        // File names are lowercased by Essentials, the name keeps its case
+        let name = string(data.get("name")).unwrap_or(stem);
+        let Some(loc) = location(Some(&data)) else {
+            report.skip(Record::Warp, format!("warp {}: no valid location", name));
+            continue;
+        };
+
+        let existing = sqlx::query_scalar::<_, i64>(
+            db.sql("SELECT id FROM warps WHERE LOWER(name) = LOWER($1)"),
+        )
+        .bind(&name)
+        .fetch_optional(&db.pool)
+        .await?;
+        if existing.is_some() {
+            report.conflict(Record::Warp, format!("warp {}: already exists", name));
+            continue;
+        }
+
+        sqlx::query(db.sql("INSERT INTO warps (name, x, y, z) VALUES ($1, $2, $3, $4)"))
+            .bind(&name)
+            .bind(loc.x)
+            .bind(loc.y)
+            .bind(loc.z)
+            .execute(&db.pool)
+            .await?;
+        report.warps.imported += 1;
+    }
+
+    Ok(())
+}
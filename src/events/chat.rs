@@ -0,0 +1,65 @@
+use crate::{
+    bridge,
+    cache::{get_display_name, mark_active},
+    moderation, nick, staffchat,
+};
+use async_trait::async_trait;
+use pumpkin::{
+    plugin::{
+        player::{player_chat::PlayerChatEvent, PlayerEvent},
+        Cancellable, EventHandler,
+    },
+    server::Server,
+};
+use pumpkin_api_macros::with_runtime;
+use pumpkin_util::text::TextComponent;
+use std::sync::Arc;
+
+pub struct ChatHandler;
+
+#[with_runtime(global)]
+#[async_trait]
+impl EventHandler<PlayerChatEvent> for ChatHandler {
+    async fn handle_blocking(&self, server: &Arc<Server>, event: &mut PlayerChatEvent) {
+        let p = event.get_player().clone();
+        let uuid_s = p.gameprofile.id.to_string();
+        mark_active(&uuid_s);
+
+        if let Some(mute) = moderation::get_mute(&uuid_s).await {
+            event.set_cancelled(true);
+            p.send_system_message(&moderation::mute_message(&mute))
+                .await;
+            return;
+        }
+
+        // Redirect the message to staffchat if the player toggled it
+        let Some(channel) = staffchat::get_toggled(&uuid_s) else {
+            let display_name = get_display_name(&uuid_s);
+            bridge::post_chat(&nick::strip_codes(&display_name), &event.message).await;
+
+            // Players with a nickname get their message sent with it instead
+            if display_name != p.gameprofile.name {
+                event.set_cancelled(true);
+
+                let tc = TextComponent::text("<")
+                    .add_child(nick::render(&display_name))
+                    .add_child(TextComponent::text(format!("> {}", event.message)));
+                for recipient in event.recipients.iter() {
+                    recipient.send_system_message(&tc).await;
+                }
+
+                log::info!("<{}> {}", nick::strip_codes(&display_name), event.message);
+            }
+            return;
+        };
+
+        match staffchat::get_channel(&channel).await {
+            // The player may have lost access since toggling
+            Some(channel) if p.has_permission(&channel.use_permission()).await => {
+                event.set_cancelled(true);
+                staffchat::send(server, &channel, &p.gameprofile.name, &event.message).await;
+            }
+            _ => staffchat::clear(&uuid_s),
+        }
+    }
+}
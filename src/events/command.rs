@@ -0,0 +1,54 @@
+use crate::{cache::mark_active, moderation, staffchat};
+use async_trait::async_trait;
+use pumpkin::{
+    plugin::{
+        player::{player_command_send::PlayerCommandSendEvent, PlayerEvent},
+        Cancellable, EventHandler,
+    },
+    server::Server,
+};
+use pumpkin_api_macros::with_runtime;
+use std::sync::Arc;
+
+// Vanilla commands that send chat to other players
+const CHAT_COMMANDS: [&str; 7] = ["msg", "tell", "w", "me", "say", "teammsg", "tm"];
+
+pub struct CommandHandler;
+
+#[with_runtime(global)]
+#[async_trait]
+impl EventHandler<PlayerCommandSendEvent> for CommandHandler {
+    async fn handle_blocking(&self, _server: &Arc<Server>, event: &mut PlayerCommandSendEvent) {
+        let p = event.get_player().clone();
+        mark_active(&p.gameprofile.id.to_string());
+
+        let Some(mute) = moderation::get_mute(&p.gameprofile.id.to_string()).await else {
+            return;
+        };
+
+        // Strip the slash and namespace, e.g. /minecraft:msg
+        let label = event
+            .command
+            .trim_start_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let label = label.rsplit(':').next().unwrap_or_default();
+
+        let mut blocked = CHAT_COMMANDS.contains(&label);
+        if !blocked {
+            // The staffchat channels are chat commands as well
+            blocked = staffchat::get_channels()
+                .await
+                .iter()
+                .any(|c| c.names.iter().any(|n| n == label));
+        }
+
+        if blocked {
+            event.set_cancelled(true);
+            p.send_system_message(&moderation::mute_message(&mute))
+                .await;
+        }
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
-    cache::{get_nickname, load_player},
+    auction, bridge,
+    cache::{get_display_name, load_player, JoinRefused},
+    ip,
+    moderation::{self, PunishmentKind},
+    nick, staffchat,
     utils::neutral_colour,
 };
 use async_trait::async_trait;
 use pumpkin::{
     plugin::{
         player::{player_join::PlayerJoinEvent, PlayerEvent},
-        EventHandler,
+        Cancellable, EventHandler,
     },
     server::Server,
 };
@@ -19,9 +23,83 @@ pub struct JoinHandler;
 #[with_runtime(global)]
 #[async_trait]
 impl EventHandler<PlayerJoinEvent> for JoinHandler {
-    async fn handle_blocking(&self, _server: &Arc<Server>, event: &mut PlayerJoinEvent) {
+    async fn handle_blocking(&self, server: &Arc<Server>, event: &mut PlayerJoinEvent) {
+        // Refuse banned players before they get loaded into the cache
+        let uuid_s = event.get_player().gameprofile.id.to_string();
+        let ban = match moderation::get_active(&uuid_s, PunishmentKind::Ban).await {
+            Ok(ban) => ban,
+            Err(err) => {
+                log::error!("Could not check bans: {}", err);
+
+                event.set_cancelled(true);
+                event
+                    .get_player()
+                    .kick(
+                        pumpkin::net::DisconnectReason::Kicked,
+                        TextComponent::text("Could not load player data.")
+                            .color_rgb(neutral_colour()),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        if let Some(ban) = ban {
+            event.set_cancelled(true);
+            event
+                .get_player()
+                .kick(
+                    pumpkin::net::DisconnectReason::Kicked,
+                    moderation::ban_message(&ban),
+                )
+                .await;
+            return;
+        }
+
+        let ip_s = ip::player_ip(event.get_player()).await;
+        match ip::get_active_ban(&ip_s).await {
+            Ok(Some(ban)) => {
+                event.set_cancelled(true);
+                event
+                    .get_player()
+                    .kick(
+                        pumpkin::net::DisconnectReason::Kicked,
+                        ip::ban_message(&ban),
+                    )
+                    .await;
+                return;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                log::error!("Could not check IP bans: {}", err);
+
+                event.set_cancelled(true);
+                event
+                    .get_player()
+                    .kick(
+                        pumpkin::net::DisconnectReason::Kicked,
+                        TextComponent::text("Could not load player data.")
+                            .color_rgb(neutral_colour()),
+                    )
+                    .await;
+                return;
+            }
+        }
+
         let np = match load_player(&event.get_player()).await {
             Ok(np) => np,
+            Err(err) if err.is::<JoinRefused>() => {
+                event.set_cancelled(true);
+                event
+                    .get_player()
+                    .kick(
+                        pumpkin::net::DisconnectReason::Kicked,
+                        TextComponent::text("You are not allowed to join this server.")
+                            .color_rgb(neutral_colour()),
+                    )
+                    .await;
+                return;
+            }
             Err(err) => {
                 log::error!("Could not load player data: {}", err);
 
@@ -38,19 +116,35 @@ impl EventHandler<PlayerJoinEvent> for JoinHandler {
             }
         };
 
-        if np {
+        if let Err(e) = moderation::load_mute(&uuid_s).await {
+            log::error!("Could not load mute: {}", e);
+        }
+
+        if let Err(e) = ip::alert_banned_alts(server, event.get_player()).await {
+            log::error!("Could not check alts: {}", e);
+        }
+
+        let nn = nick::strip_codes(&get_display_name(&uuid_s));
+        let msg = if np {
             // Teleport player to spawn
-            event.join_message = TextComponent::text(format!(
-                "Welcome, {}!",
-                get_nickname(&event.get_player().gameprofile.id.to_string(),)
-            ))
-            .color_rgb(neutral_colour());
+            format!("Welcome, {}!", nn)
         } else {
-            event.join_message = TextComponent::text(format!(
-                "Welcome back, {}!",
-                get_nickname(&event.get_player().gameprofile.id.to_string(),)
-            ))
-            .color_rgb(neutral_colour());
+            format!("Welcome back, {}!", nn)
+        };
+
+        bridge::post_join_leave(&msg).await;
+        event.join_message = TextComponent::text(msg).color_rgb(neutral_colour());
+
+        if let Err(e) = staffchat::replay_history(event.get_player()).await {
+            log::error!("Could not replay staffchat history: {}", e);
+        }
+
+        if let Err(e) = auction::reconcile(server, event.get_player()).await {
+            log::error!("Could not settle auction house items: {}", e);
+        }
+
+        if let Err(e) = auction::notify_mailbox(event.get_player()).await {
+            log::error!("Could not check auction mailbox: {}", e);
         }
     }
 }
@@ -1,12 +1,14 @@
 use crate::{
-    cache::{get_nickname, resolve_player},
+    bridge,
+    cache::{get_display_name, is_loaded, resolve_player},
+    economy, moderation, nick, staffchat,
     utils::neutral_colour,
 };
 use async_trait::async_trait;
 use pumpkin::{
     plugin::{
         player::{player_leave::PlayerLeaveEvent, PlayerEvent},
-        EventHandler,
+        Cancellable, EventHandler,
     },
     server::Server,
 };
@@ -21,14 +23,27 @@ pub struct LeaveHandler;
 impl EventHandler<PlayerLeaveEvent> for LeaveHandler {
     async fn handle_blocking(&self, _server: &Arc<Server>, event: &mut PlayerLeaveEvent) {
         let p = event.get_player();
-        let nn = get_nickname(&p.gameprofile.id.to_string());
+
+        // Players refused at join (e.g. banned) never joined to begin with
+        if !is_loaded(&p.gameprofile.id.to_string()) {
+            event.set_cancelled(true);
+            return;
+        }
+
+        let nn = nick::strip_codes(&get_display_name(&p.gameprofile.id.to_string()));
+
+        staffchat::clear(&p.gameprofile.id.to_string());
+        moderation::unload_mute(&p.gameprofile.id.to_string());
+        economy::clear_payments(&p.gameprofile.id.to_string()).await;
 
         // This also deletes the player from cache
         if let Err(e) = resolve_player(&event.get_player().gameprofile.id.to_string()).await {
             panic!("Failed to resolve player: {}", e);
         }
 
-        event.leave_message =
-            TextComponent::text(format!("Goodbye, {}!", nn)).color_rgb(neutral_colour());
+        let msg = format!("Goodbye, {}!", nn);
+        bridge::post_join_leave(&msg).await;
+
+        event.leave_message = TextComponent::text(msg).color_rgb(neutral_colour());
     }
 }
@@ -1,2 +1,5 @@
+pub mod chat;
+pub mod command;
 pub mod join;
 pub mod leave;
+pub mod movement;
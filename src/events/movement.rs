@@ -0,0 +1,26 @@
+use crate::cache::mark_active;
+use async_trait::async_trait;
+use pumpkin::{
+    plugin::{
+        player::{player_move::PlayerMoveEvent, PlayerEvent},
+        EventHandler,
+    },
+    server::Server,
+};
+use pumpkin_api_macros::with_runtime;
+use std::sync::Arc;
+
+pub struct MoveHandler;
+
+#[with_runtime(global)]
+#[async_trait]
+impl EventHandler<PlayerMoveEvent> for MoveHandler {
+    async fn handle(&self, _server: &Arc<Server>, event: &PlayerMoveEvent) {
+        // Only turning the camera does not count as activity
+        if event.from.x == event.to.x && event.from.y == event.to.y && event.from.z == event.to.z {
+            return;
+        }
+
+        mark_active(&event.get_player().gameprofile.id.to_string());
+    }
+}
@@ -0,0 +1,232 @@
+use std::time::Duration;
+
+use pumpkin::{entity::player::Player, net::DisconnectReason, server::Server};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    config::get_config,
+    db::get_db,
+    moderation::{self, PunishmentKind},
+    utils::{current_sec, mark_colour},
+};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct IpBan {
+    pub id: i64,
+    pub ip: String,
+    pub reason: String,
+    pub issuer: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl IpBan {
+    pub fn remaining(&self) -> Option<i64> {
+        self.expires_at.map(|e| (e - current_sec()).max(0))
+    }
+}
+
+// The address a player is connected from, without the port
+pub async fn player_ip(player: &Player) -> String {
+    player.client.address().await.ip().to_string()
+}
+
+pub async fn record_session(
+    player_uuid: &str,
+    ip: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    sqlx::query(db.sql("INSERT INTO player_sessions (uuid, ip, joined_at) VALUES ($1, $2, $3)"))
+        .bind(player_uuid)
+        .bind(ip)
+        .bind(current_sec())
+        .execute(&db.pool)
+        .await?;
+
+    Ok(())
+}
+
+// Deletes sessions older than the retention period, returns the amount deleted
+pub async fn purge_sessions() -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let retention_days = get_config().await.value.ip_retention_days;
+    if retention_days <= 0 {
+        return Ok(0);
+    }
+
+    let db = get_db().await;
+    let res = sqlx::query(db.sql("DELETE FROM player_sessions WHERE joined_at < $1"))
+        .bind(current_sec() - retention_days * 86400)
+        .execute(&db.pool)
+        .await?;
+
+    Ok(res.rows_affected())
+}
+
+// Purges old sessions now and every hour after
+pub fn start_purge_task() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            match purge_sessions().await {
+                Ok(0) => {}
+                Ok(n) => log::info!("Purged {} expired player sessions.", n),
+                Err(e) => log::error!("Failed to purge player sessions: {}", e),
+            }
+        }
+    });
+}
+
+pub async fn get_last_ip(
+    player_uuid: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let ip = sqlx::query_scalar::<_, String>(
+        db.sql("SELECT ip FROM player_sessions WHERE uuid = $1 ORDER BY id DESC LIMIT 1"),
+    )
+    .bind(player_uuid)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    Ok(ip)
+}
+
+// Accounts that connected from any address the player connected from,
+// returns (uuid, nickname)
+pub async fn get_alts(
+    player_uuid: &str,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let alts = sqlx::query_as::<_, (String, String)>(db.sql(
+        "SELECT DISTINCT p.uuid, p.nickname FROM player_sessions a
+            INNER JOIN player_sessions b ON b.ip = a.ip AND b.uuid <> a.uuid
+            INNER JOIN players p ON p.uuid = b.uuid
+            WHERE a.uuid = $1
+            ORDER BY p.nickname",
+    ))
+    .bind(player_uuid)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(alts)
+}
+
+// Bans an address and disconnects everyone connected from it
+pub async fn ban_ip(
+    server: &Server,
+    ip: &str,
+    reason: &str,
+    issuer: &str,
+    expires_at: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    sqlx::query(
+        db.sql("INSERT INTO ip_bans (ip, reason, issuer, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)"),
+    )
+    .bind(ip)
+    .bind(reason)
+    .bind(issuer)
+    .bind(current_sec())
+    .bind(expires_at)
+    .execute(&db.pool)
+    .await?;
+
+    if let Some(ban) = get_active_ban(ip).await? {
+        for player in server.get_all_players().await.iter() {
+            if player_ip(player).await == ip {
+                player
+                    .kick(DisconnectReason::Kicked, ban_message(&ban))
+                    .await;
+            }
+        }
+    }
+
+    log::info!("{} banned IP {}: {}", issuer, ip, reason);
+    Ok(())
+}
+
+// Revokes all active bans of an address, returns whether any were active
+pub async fn unban_ip(ip: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let res = sqlx::query(db.sql(
+        "UPDATE ip_bans SET active = 0
+            WHERE ip = $1 AND active = 1 AND (expires_at IS NULL OR expires_at > $2)",
+    ))
+    .bind(ip)
+    .bind(current_sec())
+    .execute(&db.pool)
+    .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn get_active_ban(
+    ip: &str,
+) -> Result<Option<IpBan>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let ban = sqlx::query_as::<_, IpBan>(db.sql(
+        "SELECT id, ip, reason, issuer, created_at, expires_at FROM ip_bans
+            WHERE ip = $1 AND active = 1 AND (expires_at IS NULL OR expires_at > $2)
+            ORDER BY id DESC LIMIT 1",
+    ))
+    .bind(ip)
+    .bind(current_sec())
+    .fetch_optional(&db.pool)
+    .await?;
+
+    Ok(ban)
+}
+
+pub fn ban_message(ban: &IpBan) -> TextComponent {
+    moderation::banned_message(&ban.reason, ban.remaining())
+}
+
+// Lets staff know when a player shares an address with a banned account
+pub async fn alert_banned_alts(
+    server: &Server,
+    player: &Player,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !get_config().await.value.alt_alert {
+        return Ok(());
+    }
+
+    let mut banned = Vec::new();
+    for (uuid, nickname) in get_alts(&player.gameprofile.id.to_string()).await? {
+        if moderation::get_active(&uuid, PunishmentKind::Ban)
+            .await?
+            .is_some()
+        {
+            banned.push(nickname);
+        }
+    }
+
+    if banned.is_empty() {
+        return Ok(());
+    }
+
+    let msg = format!(
+        "[Alts] {} shares an IP with banned account{}: {}",
+        player.gameprofile.name,
+        if banned.len() == 1 { "" } else { "s" },
+        banned.join(", ")
+    );
+    log::info!("{}", msg);
+
+    let tc = TextComponent::text(msg).color_rgb(mark_colour());
+    for staff in server.get_all_players().await.iter() {
+        if staff.has_permission("servercore:alts.alert").await {
+            staff.send_system_message(&tc).await;
+        }
+    }
+
+    Ok(())
+}
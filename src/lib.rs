@@ -1,17 +1,57 @@
+pub mod api;
+// Plugins using the api depend on this crate without the plugin feature
+#[cfg(feature = "plugin")]
+mod auction;
+#[cfg(feature = "plugin")]
+mod backup;
+#[cfg(feature = "plugin")]
+mod bank;
+#[cfg(feature = "plugin")]
+mod bridge;
+#[cfg(feature = "plugin")]
 mod cache;
+#[cfg(feature = "plugin")]
 mod commands;
+#[cfg(feature = "plugin")]
 mod config;
+#[cfg(feature = "plugin")]
 mod db;
+#[cfg(feature = "plugin")]
+mod economy;
+#[cfg(feature = "plugin")]
+mod essentials;
+#[cfg(feature = "plugin")]
 mod events;
+#[cfg(feature = "plugin")]
+mod ip;
+#[cfg(feature = "plugin")]
+mod moderation;
+#[cfg(feature = "plugin")]
+mod nick;
+#[cfg(feature = "plugin")]
+mod payouts;
+#[cfg(feature = "plugin")]
+mod shop;
+#[cfg(feature = "plugin")]
+mod staffchat;
+#[cfg(feature = "plugin")]
+mod transfer;
+#[cfg(feature = "plugin")]
 mod utils;
 
+#[cfg(feature = "plugin")]
 use core::panic;
+#[cfg(feature = "plugin")]
 use std::sync::Arc;
 
+#[cfg(feature = "plugin")]
 use pumpkin::plugin::{Context, EventPriority};
+#[cfg(feature = "plugin")]
 use pumpkin_api_macros::{plugin_impl, plugin_method};
+#[cfg(feature = "plugin")]
 use pumpkin_util::permission::Permission;
 
+#[cfg(feature = "plugin")]
 async fn register_perms(ctx: &Arc<Context>) -> Result<(), String> {
     let playtime_perm = Permission::new(
         "servercore:playtime.see",
@@ -34,6 +74,48 @@ async fn register_perms(ctx: &Arc<Context>) -> Result<(), String> {
     );
     ctx.register_permission(pay_perm).await?;
 
+    let paytoggle_perm = Permission::new(
+        "servercore:paytoggle.use",
+        "Use the paytoggle command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(paytoggle_perm).await?;
+
+    let bank_perm = Permission::new(
+        "servercore:bank.use",
+        "Use the bank command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(bank_perm).await?;
+
+    let shop_perm = Permission::new(
+        "servercore:shop.use",
+        "Use the shop command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(shop_perm).await?;
+
+    let sell_perm = Permission::new(
+        "servercore:sell.use",
+        "Use the sell command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(sell_perm).await?;
+
+    let worth_perm = Permission::new(
+        "servercore:worth.use",
+        "Use the worth command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(worth_perm).await?;
+
+    let ah_perm = Permission::new(
+        "servercore:ah.use",
+        "Use the auction house command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(ah_perm).await?;
+
     let balance_perm = Permission::new(
         "servercore:balance.see",
         "Use the balance command",
@@ -42,7 +124,49 @@ async fn register_perms(ctx: &Arc<Context>) -> Result<(), String> {
 
     ctx.register_permission(balance_perm).await?;
 
+    let warnings_perm = Permission::new(
+        "servercore:warnings.see",
+        "Use the warnings command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(warnings_perm).await?;
+
+    let seen_perm = Permission::new(
+        "servercore:seen.use",
+        "Use the seen command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(seen_perm).await?;
+
+    let nick_perm = Permission::new(
+        "servercore:nick.use",
+        "Use the nick command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(nick_perm).await?;
+
+    let realname_perm = Permission::new(
+        "servercore:realname.use",
+        "Use the realname command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Zero),
+    );
+    ctx.register_permission(realname_perm).await?;
+
     // 1 perms
+    let nick_color_perm = Permission::new(
+        "servercore:nick.color",
+        "Use colours in nicknames",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::One),
+    );
+    ctx.register_permission(nick_color_perm).await?;
+
+    let nick_format_perm = Permission::new(
+        "servercore:nick.format",
+        "Use formatting in nicknames",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::One),
+    );
+    ctx.register_permission(nick_format_perm).await?;
+
     let vanish_perm = Permission::new(
         "servercore:vanish.use",
         "Use the vanish command",
@@ -57,7 +181,140 @@ async fn register_perms(ctx: &Arc<Context>) -> Result<(), String> {
     );
     ctx.register_permission(staffchat_perm).await?;
 
+    let staffchat_receive_perm = Permission::new(
+        "servercore:staffchat.receive",
+        "Receive staffchat messages",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::One),
+    );
+    ctx.register_permission(staffchat_receive_perm).await?;
+
+    for channel in staffchat::get_channels().await.iter() {
+        if channel.name == staffchat::DEFAULT_CHANNEL {
+            continue;
+        }
+
+        let channel_perm = Permission::new(
+            &channel.use_permission(),
+            &format!("Use the {} chat channel", channel.name),
+            pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::One),
+        );
+        ctx.register_permission(channel_perm).await?;
+
+        let channel_receive_perm = Permission::new(
+            &channel.receive_permission(),
+            &format!("Receive {} chat channel messages", channel.name),
+            pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::One),
+        );
+        ctx.register_permission(channel_receive_perm).await?;
+    }
+
+    // 2 perms
+    let kick_perm = Permission::new(
+        "servercore:kick.use",
+        "Use the kick command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(kick_perm).await?;
+
+    let tempban_perm = Permission::new(
+        "servercore:tempban.use",
+        "Use the tempban command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(tempban_perm).await?;
+
+    let warnings_others_perm = Permission::new(
+        "servercore:warnings.others",
+        "See the warnings of other players",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(warnings_others_perm).await?;
+
+    let history_perm = Permission::new(
+        "servercore:history.use",
+        "Use the history command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(history_perm).await?;
+
+    let mute_perm = Permission::new(
+        "servercore:mute.use",
+        "Use the mute command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(mute_perm).await?;
+
+    let tempmute_perm = Permission::new(
+        "servercore:tempmute.use",
+        "Use the tempmute command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(tempmute_perm).await?;
+
+    let unmute_perm = Permission::new(
+        "servercore:unmute.use",
+        "Use the unmute command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(unmute_perm).await?;
+
+    let warn_perm = Permission::new(
+        "servercore:warn.use",
+        "Use the warn command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(warn_perm).await?;
+
+    let alts_perm = Permission::new(
+        "servercore:alts.use",
+        "Use the alts command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(alts_perm).await?;
+
+    let alts_alert_perm = Permission::new(
+        "servercore:alts.alert",
+        "Get alerted when an alt of a banned player joins",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(alts_alert_perm).await?;
+
+    let whois_perm = Permission::new(
+        "servercore:whois.use",
+        "Use the whois command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Two),
+    );
+    ctx.register_permission(whois_perm).await?;
+
     // 3 perms
+    let ban_perm = Permission::new(
+        "servercore:ban.use",
+        "Use the ban command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Three),
+    );
+    ctx.register_permission(ban_perm).await?;
+
+    let unban_perm = Permission::new(
+        "servercore:unban.use",
+        "Use the unban command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Three),
+    );
+    ctx.register_permission(unban_perm).await?;
+
+    let ipban_perm = Permission::new(
+        "servercore:ipban.use",
+        "Use the ipban command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Three),
+    );
+    ctx.register_permission(ipban_perm).await?;
+
+    let whois_ip_perm = Permission::new(
+        "servercore:whois.ip",
+        "See IP addresses in the whois command",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Three),
+    );
+    ctx.register_permission(whois_ip_perm).await?;
+
     let setspawn_perm = Permission::new(
         "servercore:setspawn.use",
         "Use the setspawn command",
@@ -79,9 +336,25 @@ async fn register_perms(ctx: &Arc<Context>) -> Result<(), String> {
     );
     ctx.register_permission(economy_perm).await?;
 
+    // 4 perms
+    let pay_taxexempt_perm = Permission::new(
+        "servercore:pay.taxexempt",
+        "Pay other players without paying tax",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Four),
+    );
+    ctx.register_permission(pay_taxexempt_perm).await?;
+
+    let servercore_perm = Permission::new(
+        "servercore:servercore.use",
+        "Use the servercore command to manage backups, exports and imports",
+        pumpkin_util::permission::PermissionDefault::Op(pumpkin_util::PermissionLvl::Four),
+    );
+    ctx.register_permission(servercore_perm).await?;
+
     Ok(())
 }
 
+#[cfg(feature = "plugin")]
 #[plugin_method]
 async fn on_load(&mut self, server: Arc<Context>) -> Result<(), String> {
     pumpkin::init_log!();
@@ -90,10 +363,19 @@ async fn on_load(&mut self, server: Arc<Context>) -> Result<(), String> {
         panic!("Failed to setup config: {}", e);
     };
 
+    // A staged restore has to be swapped in before the database is opened
+    if let Err(e) = backup::apply_pending_restore(&server.get_data_folder()).await {
+        log::error!("Failed to restore backup: {}", e);
+    }
+
     if let Err(e) = db::setup_db(&server.get_data_folder()).await {
         panic!("Failed to setup database: {}", e);
     };
 
+    if let Err(e) = shop::setup_shop().await {
+        panic!("Failed to setup shop: {}", e);
+    };
+
     if let Err(e) = register_perms(&server).await {
         panic!("Failed to register permissions: {}", e);
     };
@@ -113,6 +395,27 @@ async fn on_load(&mut self, server: Arc<Context>) -> Result<(), String> {
             true,
         )
         .await;
+    server
+        .register_event(
+            Arc::new(events::chat::ChatHandler),
+            EventPriority::Highest,
+            true,
+        )
+        .await;
+    server
+        .register_event(
+            Arc::new(events::command::CommandHandler),
+            EventPriority::Highest,
+            true,
+        )
+        .await;
+    server
+        .register_event(
+            Arc::new(events::movement::MoveHandler),
+            EventPriority::Lowest,
+            false,
+        )
+        .await;
 
     // Commands
     server
@@ -125,17 +428,40 @@ async fn on_load(&mut self, server: Arc<Context>) -> Result<(), String> {
         .register_command(commands::pay::init_command(), "servercore:pay.use")
         .await;
     server
-        .register_command(commands::balance::init_command(), "servercore:balance.see")
+        .register_command(
+            commands::paytoggle::init_command(),
+            "servercore:paytoggle.use",
+        )
         .await;
     server
-        .register_command(commands::vanish::init_command(), "servercore:vanish.use")
+        .register_command(commands::bank::init_command(), "servercore:bank.use")
         .await;
     server
-        .register_command(
-            commands::staffchat::init_command(),
-            "servercore:staffchat.use",
-        )
+        .register_command(commands::shop::init_command(), "servercore:shop.use")
         .await;
+    server
+        .register_command(commands::sell::init_command(), "servercore:sell.use")
+        .await;
+    server
+        .register_command(commands::worth::init_command(), "servercore:worth.use")
+        .await;
+    server
+        .register_command(commands::auction::init_command(), "servercore:ah.use")
+        .await;
+    server
+        .register_command(commands::balance::init_command(), "servercore:balance.see")
+        .await;
+    server
+        .register_command(commands::vanish::init_command(), "servercore:vanish.use")
+        .await;
+    for channel in staffchat::get_channels().await.iter() {
+        server
+            .register_command(
+                commands::staffchat::init_command(channel),
+                &channel.use_permission(),
+            )
+            .await;
+    }
     server
         .register_command(
             commands::setspawn::init_command(),
@@ -148,19 +474,108 @@ async fn on_load(&mut self, server: Arc<Context>) -> Result<(), String> {
     server
         .register_command(commands::economy::init_command(), "servercore:economy.use")
         .await;
+    server
+        .register_command(commands::kick::init_command(), "servercore:kick.use")
+        .await;
+    server
+        .register_command(commands::tempban::init_command(), "servercore:tempban.use")
+        .await;
+    server
+        .register_command(commands::history::init_command(), "servercore:history.use")
+        .await;
+    server
+        .register_command(commands::mute::init_command(), "servercore:mute.use")
+        .await;
+    server
+        .register_command(
+            commands::tempmute::init_command(),
+            "servercore:tempmute.use",
+        )
+        .await;
+    server
+        .register_command(commands::unmute::init_command(), "servercore:unmute.use")
+        .await;
+    server
+        .register_command(commands::warn::init_command(), "servercore:warn.use")
+        .await;
+    server
+        .register_command(
+            commands::warnings::init_command(),
+            "servercore:warnings.see",
+        )
+        .await;
+    server
+        .register_command(commands::ban::init_command(), "servercore:ban.use")
+        .await;
+    server
+        .register_command(commands::unban::init_command(), "servercore:unban.use")
+        .await;
+    server
+        .register_command(commands::ipban::init_command(), "servercore:ipban.use")
+        .await;
+    server
+        .register_command(commands::alts::init_command(), "servercore:alts.use")
+        .await;
+    server
+        .register_command(commands::seen::init_command(), "servercore:seen.use")
+        .await;
+    server
+        .register_command(commands::whois::init_command(), "servercore:whois.use")
+        .await;
+    server
+        .register_command(
+            commands::servercore::init_command(),
+            "servercore:servercore.use",
+        )
+        .await;
+    server
+        .register_command(commands::nick::init_command(), "servercore:nick.use")
+        .await;
+    server
+        .register_command(
+            commands::realname::init_command(),
+            "servercore:realname.use",
+        )
+        .await;
+
+    // Removes IP addresses past the retention period
+    ip::start_purge_task();
+
+    // Scheduled database backups
+    backup::start_backup_task().await;
+
+    // Network sync of balance and playtime
+    cache::start_sync_task(config::get_config().await.value.sync_interval);
+
+    // Interest on bank accounts
+    bank::start_interest_task();
+
+    // Closing expired auction house listings
+    auction::start_expiry_task();
+
+    // Interest on balances and income for online players
+    payouts::start_payout_task(server.server.clone());
+
+    // Chat bridge
+    if let Err(e) = bridge::start(server.server.clone()).await {
+        log::error!("Failed to start chat bridge: {}", e);
+    }
 
     Ok(())
 }
 
+#[cfg(feature = "plugin")]
 #[plugin_impl]
 pub struct MyPlugin {}
 
+#[cfg(feature = "plugin")]
 impl MyPlugin {
     pub fn new() -> Self {
         MyPlugin {}
     }
 }
 
+#[cfg(feature = "plugin")]
 impl Default for MyPlugin {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,491 @@
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use pumpkin::{net::DisconnectReason, server::Server};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    cache::is_loaded,
+    config::get_config,
+    db::get_db,
+    utils::{current_sec, error_colour, format_duration, parse_duration},
+};
+
+// Issuer recorded for punishments applied by the warning escalation ladder
+const ESCALATION_ISSUER: &str = "Automatic";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PunishmentKind {
+    Ban,
+    Kick,
+    Mute,
+}
+
+impl PunishmentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PunishmentKind::Ban => "ban",
+            PunishmentKind::Kick => "kick",
+            PunishmentKind::Mute => "mute",
+        }
+    }
+}
+
+lazy_static! {
+    // User UUID -> Active mute of online players
+    static ref MUTES: DashMap<String, Punishment> = DashMap::new();
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Punishment {
+    pub id: i64,
+    pub uuid: String,
+    pub kind: String,
+    pub reason: String,
+    pub issuer: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    // Stored as an integer, not every backend has booleans
+    pub active: i64,
+}
+
+impl Punishment {
+    // Seconds until the punishment expires, None if it is permanent
+    pub fn remaining(&self) -> Option<i64> {
+        self.expires_at.map(|e| (e - current_sec()).max(0))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_some_and(|r| r == 0)
+    }
+
+    // Single line summary as shown in /history
+    pub fn summary(&self) -> String {
+        let state = if self.kind == PunishmentKind::Kick.as_str() {
+            String::new()
+        } else if self.active == 0 {
+            " (revoked)".to_string()
+        } else if self.is_expired() {
+            " (expired)".to_string()
+        } else {
+            match self.remaining() {
+                Some(r) => format!(" (expires in {})", format_duration(r)),
+                None => " (permanent)".to_string(),
+            }
+        };
+
+        format!(
+            "#{} [{}] {} - by {}, {} ago{}",
+            self.id,
+            self.kind.to_uppercase(),
+            self.reason,
+            self.issuer,
+            format_duration(current_sec() - self.created_at),
+            state
+        )
+    }
+}
+
+pub async fn add_punishment(
+    player_uuid: &str,
+    kind: PunishmentKind,
+    reason: &str,
+    issuer: &str,
+    expires_at: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    // Kicks are over the moment they happen, they are only kept for history
+    let active = (kind != PunishmentKind::Kick) as i64;
+
+    sqlx::query(db.sql(
+        "INSERT INTO punishments (uuid, kind, reason, issuer, created_at, expires_at, active)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    ))
+    .bind(player_uuid)
+    .bind(kind.as_str())
+    .bind(reason)
+    .bind(issuer)
+    .bind(current_sec())
+    .bind(expires_at)
+    .bind(active)
+    .execute(&db.pool)
+    .await?;
+
+    Ok(())
+}
+
+// Bans a player and disconnects them if they are online
+pub async fn ban_player(
+    server: &Server,
+    player_uuid: &str,
+    nickname: &str,
+    reason: &str,
+    issuer: &str,
+    expires_at: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    add_punishment(player_uuid, PunishmentKind::Ban, reason, issuer, expires_at).await?;
+
+    if let Some(ban) = get_active(player_uuid, PunishmentKind::Ban).await? {
+        if let Some(target) = server.get_player_by_name(nickname).await {
+            target
+                .kick(DisconnectReason::Kicked, ban_message(&ban))
+                .await;
+        }
+    }
+
+    log::info!("{} banned {}: {}", issuer, nickname, reason);
+    Ok(())
+}
+
+// Mutes a player, the mute takes effect right away if they are online
+pub async fn mute_player(
+    server: &Server,
+    player_uuid: &str,
+    nickname: &str,
+    reason: &str,
+    issuer: &str,
+    expires_at: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    add_punishment(
+        player_uuid,
+        PunishmentKind::Mute,
+        reason,
+        issuer,
+        expires_at,
+    )
+    .await?;
+
+    // Offline players get their mute loaded when they join
+    if is_loaded(player_uuid) {
+        load_mute(player_uuid).await?;
+
+        if let (Some(mute), Some(target)) = (
+            get_mute(player_uuid).await,
+            server.get_player_by_name(nickname).await,
+        ) {
+            target.send_system_message(&mute_message(&mute)).await;
+        }
+    }
+
+    log::info!("{} muted {}: {}", issuer, nickname, reason);
+    Ok(())
+}
+
+pub async fn unmute_player(
+    player_uuid: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    MUTES.remove(player_uuid);
+    revoke(player_uuid, PunishmentKind::Mute).await
+}
+
+// Caches the active mute of a player, called on join
+pub async fn load_mute(player_uuid: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match get_active(player_uuid, PunishmentKind::Mute).await? {
+        Some(mute) => {
+            MUTES.insert(player_uuid.to_string(), mute);
+        }
+        None => {
+            MUTES.remove(player_uuid);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn unload_mute(player_uuid: &str) {
+    MUTES.remove(player_uuid);
+}
+
+// Gets the mute of an online player if it is still in effect. Once the
+// cached mute expires the next one still in effect is loaded.
+pub async fn get_mute(player_uuid: &str) -> Option<Punishment> {
+    let mute = MUTES.get(player_uuid).map(|v| v.clone())?;
+    if !mute.is_expired() {
+        return Some(mute);
+    }
+
+    if let Err(e) = load_mute(player_uuid).await {
+        log::error!("Failed to reload the mute of {}: {}", player_uuid, e);
+        MUTES.remove(player_uuid);
+        return None;
+    }
+
+    MUTES.get(player_uuid).map(|v| v.clone())
+}
+
+// Gets the punishment of the given kind currently in effect for a player.
+// Permanent ones come first, then the one that lasts longest.
+pub async fn get_active(
+    player_uuid: &str,
+    kind: PunishmentKind,
+) -> Result<Option<Punishment>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let punishment = sqlx::query_as::<_, Punishment>(db.sql(
+        "SELECT id, uuid, kind, reason, issuer, created_at, expires_at, active FROM punishments
+            WHERE uuid = $1 AND kind = $2 AND active = 1 AND (expires_at IS NULL OR expires_at > $3)
+            ORDER BY CASE WHEN expires_at IS NULL THEN 0 ELSE 1 END, expires_at DESC, id DESC
+            LIMIT 1",
+    ))
+    .bind(player_uuid)
+    .bind(kind.as_str())
+    .bind(current_sec())
+    .fetch_optional(&db.pool)
+    .await?;
+
+    Ok(punishment)
+}
+
+// Revokes all active punishments of the given kind, returns whether any were active
+pub async fn revoke(
+    player_uuid: &str,
+    kind: PunishmentKind,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let res = sqlx::query(
+        db.sql("UPDATE punishments SET active = 0
+            WHERE uuid = $1 AND kind = $2 AND active = 1 AND (expires_at IS NULL OR expires_at > $3)"),
+    )
+    .bind(player_uuid)
+    .bind(kind.as_str())
+    .bind(current_sec())
+    .execute(&db.pool)
+    .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn get_history(
+    player_uuid: &str,
+) -> Result<Vec<Punishment>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let history = sqlx::query_as::<_, Punishment>(db.sql(
+        "SELECT id, uuid, kind, reason, issuer, created_at, expires_at, active FROM punishments
+            WHERE uuid = $1 ORDER BY id DESC",
+    ))
+    .bind(player_uuid)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(history)
+}
+
+// Disconnect message shown to banned players
+pub fn ban_message(ban: &Punishment) -> TextComponent {
+    banned_message(&ban.reason, ban.remaining())
+}
+
+pub fn banned_message(reason: &str, remaining: Option<i64>) -> TextComponent {
+    let msg = match remaining {
+        Some(r) => format!(
+            "You are banned for another {}.\nReason: {}",
+            format_duration(r),
+            reason
+        ),
+        None => format!("You are permanently banned.\nReason: {}", reason),
+    };
+
+    TextComponent::text(msg).color_rgb(error_colour())
+}
+
+// Shown to muted players when they try to chat
+pub fn mute_message(mute: &Punishment) -> TextComponent {
+    let msg = match mute.remaining() {
+        Some(r) => format!(
+            "You are muted for another {}. Reason: {}",
+            format_duration(r),
+            mute.reason
+        ),
+        None => format!("You are permanently muted. Reason: {}", mute.reason),
+    };
+
+    TextComponent::text(msg).color_rgb(error_colour())
+}
+
+pub fn kick_message(reason: &str) -> TextComponent {
+    TextComponent::text(format!("You have been kicked.\nReason: {}", reason))
+        .color_rgb(error_colour())
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Warning {
+    pub id: i64,
+    pub reason: String,
+    pub issuer: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl Warning {
+    pub fn summary(&self) -> String {
+        let expiry = match self.expires_at {
+            Some(e) => format!(" (expires in {})", format_duration(e - current_sec())),
+            None => String::new(),
+        };
+
+        format!(
+            "#{} {} - by {}, {} ago{}",
+            self.id,
+            self.reason,
+            self.issuer,
+            format_duration(current_sec() - self.created_at),
+            expiry
+        )
+    }
+}
+
+// Warns a player, applies the escalation ladder and returns the amount of
+// active warnings the player now has
+pub async fn warn_player(
+    server: &Server,
+    player_uuid: &str,
+    nickname: &str,
+    reason: &str,
+    issuer: &str,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+    let config = get_config().await;
+
+    let expires_at = if config.value.warn_expiry.is_empty() {
+        None
+    } else {
+        let expiry = parse_duration(&config.value.warn_expiry).ok_or("Invalid warn_expiry")?;
+        Some(current_sec() + expiry)
+    };
+
+    sqlx::query(db.sql(
+        "INSERT INTO warnings (player_id, reason, issuer, created_at, expires_at)
+            VALUES ((SELECT id FROM players WHERE uuid = $1), $2, $3, $4, $5)",
+    ))
+    .bind(player_uuid)
+    .bind(reason)
+    .bind(issuer)
+    .bind(current_sec())
+    .bind(expires_at)
+    .execute(&db.pool)
+    .await?;
+
+    log::info!("{} warned {}: {}", issuer, nickname, reason);
+
+    if let Some(target) = server.get_player_by_name(nickname).await {
+        target
+            .send_system_message(
+                &TextComponent::text(format!("You have been warned: {}", reason))
+                    .color_rgb(error_colour()),
+            )
+            .await;
+    }
+
+    let warnings = get_warnings(player_uuid).await?;
+    escalate(server, player_uuid, nickname, &warnings).await?;
+
+    Ok(warnings.len())
+}
+
+// Active warnings of a player, newest first
+pub async fn get_warnings(
+    player_uuid: &str,
+) -> Result<Vec<Warning>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let warnings = sqlx::query_as::<_, Warning>(db.sql(
+        "SELECT w.id, w.reason, w.issuer, w.created_at, w.expires_at FROM warnings w
+            INNER JOIN players p ON p.id = w.player_id
+            WHERE p.uuid = $1 AND (w.expires_at IS NULL OR w.expires_at > $2)
+            ORDER BY w.id DESC",
+    ))
+    .bind(player_uuid)
+    .bind(current_sec())
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(warnings)
+}
+
+// Applies the highest escalation step the active warnings have reached, unless
+// it was already applied while those warnings were active
+async fn escalate(
+    server: &Server,
+    player_uuid: &str,
+    nickname: &str,
+    warnings: &[Warning],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+    let config = get_config().await;
+
+    let count = warnings.len() as i64;
+    let Some(step) = config
+        .value
+        .warn_escalation
+        .iter()
+        .filter(|s| s.warnings <= count)
+        .max_by_key(|s| s.warnings)
+    else {
+        return Ok(());
+    };
+
+    let reason = format!("Reached {} warnings", step.warnings);
+    let since = warnings.iter().map(|w| w.created_at).min().unwrap_or(0);
+    let applied: Option<i64> = sqlx::query_scalar(db.sql(
+        "SELECT id FROM punishments WHERE uuid = $1 AND issuer = $2 AND reason = $3 AND created_at >= $4 LIMIT 1",
+    ))
+    .bind(player_uuid)
+    .bind(ESCALATION_ISSUER)
+    .bind(&reason)
+    .bind(since)
+    .fetch_optional(&db.pool)
+    .await?;
+    if applied.is_some() {
+        return Ok(());
+    }
+
+    let expires_at = match &step.duration {
+        Some(d) => Some(current_sec() + parse_duration(d).ok_or("Invalid escalation duration")?),
+        None => None,
+    };
+
+    match step.action.as_str() {
+        "mute" => {
+            mute_player(
+                server,
+                player_uuid,
+                nickname,
+                &reason,
+                ESCALATION_ISSUER,
+                expires_at,
+            )
+            .await?
+        }
+        "ban" => {
+            ban_player(
+                server,
+                player_uuid,
+                nickname,
+                &reason,
+                ESCALATION_ISSUER,
+                expires_at,
+            )
+            .await?
+        }
+        "kick" => {
+            if let Some(target) = server.get_player_by_name(nickname).await {
+                add_punishment(
+                    player_uuid,
+                    PunishmentKind::Kick,
+                    &reason,
+                    ESCALATION_ISSUER,
+                    None,
+                )
+                .await?;
+                target
+                    .kick(DisconnectReason::Kicked, kick_message(&reason))
+                    .await;
+            }
+        }
+        other => log::warn!("Unknown warn escalation action: {}", other),
+    }
+
+    Ok(())
+}
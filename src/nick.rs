@@ -0,0 +1,287 @@
+use pumpkin_util::text::{color::NamedColor, TextComponent};
+
+use crate::{
+    config::get_config,
+    db::{get_db, is_unique_violation},
+    utils::current_sec,
+};
+
+const MIN_LENGTH: usize = 3;
+const MAX_LENGTH: usize = 16;
+
+const COLOUR_CODES: &str = "0123456789abcdef";
+const FORMAT_CODES: &str = "klmnor";
+
+#[derive(Debug, PartialEq)]
+pub enum NickError {
+    Length,
+    Characters,
+    Colour,
+    Format,
+    Blacklisted,
+    Taken,
+    Failed,
+}
+
+impl NickError {
+    pub fn message(&self) -> String {
+        match self {
+            NickError::Length => format!(
+                "Nicknames must be between {} and {} characters long.",
+                MIN_LENGTH, MAX_LENGTH
+            ),
+            NickError::Characters => {
+                "Nicknames may only contain letters, numbers and underscores.".to_string()
+            }
+            NickError::Colour => "You are not allowed to use colours.".to_string(),
+            NickError::Format => "You are not allowed to use formatting.".to_string(),
+            NickError::Blacklisted => "That nickname is not allowed.".to_string(),
+            NickError::Taken => "That nickname is already taken.".to_string(),
+            NickError::Failed => "Something went wrong, check the server log.".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct NameChange {
+    pub name: String,
+    pub changed_at: i64,
+}
+
+fn named_colour(code: char) -> Option<NamedColor> {
+    let colour = match code {
+        '0' => NamedColor::Black,
+        '1' => NamedColor::DarkBlue,
+        '2' => NamedColor::DarkGreen,
+        '3' => NamedColor::DarkAqua,
+        '4' => NamedColor::DarkRed,
+        '5' => NamedColor::DarkPurple,
+        '6' => NamedColor::Gold,
+        '7' => NamedColor::Gray,
+        '8' => NamedColor::DarkGray,
+        '9' => NamedColor::Blue,
+        'a' => NamedColor::Green,
+        'b' => NamedColor::Aqua,
+        'c' => NamedColor::Red,
+        'd' => NamedColor::LightPurple,
+        'e' => NamedColor::Yellow,
+        'f' => NamedColor::White,
+        _ => return None,
+    };
+
+    Some(colour)
+}
+
+// Splits a nickname into its codes and the text following each code
+fn segments(raw: &str) -> Vec<(Option<char>, String)> {
+    let mut segments = vec![(None, String::new())];
+    let mut chars = raw.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let code = chars.peek().map(|n| n.to_ascii_lowercase());
+        match code {
+            Some(code)
+                if c == '&' && (COLOUR_CODES.contains(code) || FORMAT_CODES.contains(code)) =>
+            {
+                chars.next();
+                segments.push((Some(code), String::new()));
+            }
+            _ => segments.last_mut().unwrap().1.push(c),
+        }
+    }
+
+    segments
+}
+
+// The nickname without any & codes
+pub fn strip_codes(raw: &str) -> String {
+    segments(raw).into_iter().map(|(_, text)| text).collect()
+}
+
+// Turns a nickname with & codes into a styled component
+pub fn render(raw: &str) -> TextComponent {
+    let mut component = TextComponent::text("");
+
+    let mut colour = None;
+    let mut formats: Vec<char> = Vec::new();
+    for (code, text) in segments(raw) {
+        match code {
+            // A colour resets formatting, like in vanilla
+            Some(c) if COLOUR_CODES.contains(c) => {
+                colour = named_colour(c);
+                formats.clear();
+            }
+            Some('r') => {
+                colour = None;
+                formats.clear();
+            }
+            Some(c) => formats.push(c),
+            None => {}
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let mut part = TextComponent::text(text);
+        if let Some(colour) = colour {
+            part = part.color_named(colour);
+        }
+        for f in formats.iter() {
+            part = match f {
+                'k' => part.obfuscated(),
+                'l' => part.bold(),
+                'm' => part.strikethrough(),
+                'n' => part.underlined(),
+                _ => part.italic(),
+            };
+        }
+
+        component = component.add_child(part);
+    }
+
+    component
+}
+
+// Checks whether a player may take a nickname
+pub async fn validate(
+    player_uuid: &str,
+    raw: &str,
+    allow_colour: bool,
+    allow_format: bool,
+) -> Result<(), NickError> {
+    for (code, _) in segments(raw) {
+        match code {
+            Some(c) if COLOUR_CODES.contains(c) && !allow_colour => return Err(NickError::Colour),
+            Some(c) if FORMAT_CODES.contains(c) && !allow_format => return Err(NickError::Format),
+            _ => {}
+        }
+    }
+
+    let plain = strip_codes(raw);
+    if plain.chars().count() < MIN_LENGTH || plain.chars().count() > MAX_LENGTH {
+        return Err(NickError::Length);
+    }
+
+    if !plain.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(NickError::Characters);
+    }
+
+    let lower = plain.to_lowercase();
+    let config = get_config().await;
+    if config
+        .value
+        .nick_blacklist
+        .iter()
+        .any(|b| lower.contains(&b.to_lowercase()))
+    {
+        return Err(NickError::Blacklisted);
+    }
+
+    // Nicknames may not collide with other nicknames or account names
+    let db = get_db().await;
+    let taken = sqlx::query_scalar::<_, i64>(db.sql(
+        "SELECT COUNT(*) FROM players
+            WHERE uuid <> $1 AND (LOWER(nickname) = $2 OR LOWER(display_name_plain) = $3)",
+    ))
+    .bind(player_uuid)
+    .bind(&lower)
+    .bind(&lower)
+    .fetch_one(&db.pool)
+    .await
+    .map_err(|e| {
+        log::error!("Failed to check nickname: {}", e);
+        NickError::Failed
+    })?;
+
+    if taken > 0 {
+        return Err(NickError::Taken);
+    }
+
+    Ok(())
+}
+
+// Stores a nickname, None removes it. The nickname check can race with
+// another player saving the same one, the unique index settles it.
+pub async fn save_nick(player_uuid: &str, raw: Option<&str>) -> Result<(), NickError> {
+    let db = get_db().await;
+
+    sqlx::query(
+        db.sql("UPDATE players SET display_name = $1, display_name_plain = $2 WHERE uuid = $3"),
+    )
+    .bind(raw)
+    .bind(raw.map(|r| strip_codes(r).to_lowercase()))
+    .bind(player_uuid)
+    .execute(&db.pool)
+    .await
+    .map_err(|e| {
+        if is_unique_violation(&e) {
+            return NickError::Taken;
+        }
+        log::error!("Failed to save nickname: {}", e);
+        NickError::Failed
+    })?;
+
+    Ok(())
+}
+
+// Resolves a nickname to the account, returns (uuid, account name)
+pub async fn find_by_nick(
+    nick: &str,
+) -> Result<Option<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let row = sqlx::query_as::<_, (String, String)>(
+        db.sql("SELECT uuid, nickname FROM players WHERE display_name_plain = $1"),
+    )
+    .bind(strip_codes(nick).to_lowercase())
+    .fetch_optional(&db.pool)
+    .await?;
+
+    Ok(row)
+}
+
+// Records an account name if it differs from the last one seen,
+// returns the previous name if it changed
+pub async fn record_name(
+    player_uuid: &str,
+    name: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let last = sqlx::query_scalar::<_, String>(
+        db.sql("SELECT name FROM name_history WHERE uuid = $1 ORDER BY id DESC LIMIT 1"),
+    )
+    .bind(player_uuid)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    if last.as_deref() == Some(name) {
+        return Ok(None);
+    }
+
+    sqlx::query(db.sql("INSERT INTO name_history (uuid, name, changed_at) VALUES ($1, $2, $3)"))
+        .bind(player_uuid)
+        .bind(name)
+        .bind(current_sec())
+        .execute(&db.pool)
+        .await?;
+
+    Ok(last)
+}
+
+// Account names of a player, newest first
+pub async fn get_name_history(
+    player_uuid: &str,
+) -> Result<Vec<NameChange>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let history = sqlx::query_as::<_, NameChange>(
+        db.sql("SELECT name, changed_at FROM name_history WHERE uuid = $1 ORDER BY id DESC"),
+    )
+    .bind(player_uuid)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(history)
+}
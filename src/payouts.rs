@@ -0,0 +1,287 @@
+use std::{sync::Arc, time::Duration};
+
+use pumpkin::server::Server;
+
+use crate::{
+    api::BalanceChangeCause,
+    cache::is_afk,
+    config::get_config,
+    db::get_db,
+    economy::{self, Currency, EconomyError},
+    utils::{current_sec, parse_duration},
+};
+
+// Scheduled payouts in the default currency, each has its own interval and
+// row in payout_schedule
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PayoutKind {
+    // Percentage of every balance
+    Interest,
+    // Fixed amount for online players
+    Income,
+}
+
+impl PayoutKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayoutKind::Interest => "interest",
+            PayoutKind::Income => "income",
+        }
+    }
+}
+
+// Claims a payout run that is due, returns the time it was claimed at which
+// identifies the run. The time is stored before paying, so a restart or
+// another server sharing the database does not start the same interval
+// twice. A run that was not finished is handed out again until it is, the
+// players paid in it already are skipped.
+async fn claim(kind: PayoutKind, interval: i64) -> Result<Option<i64>, sqlx::Error> {
+    let db = get_db().await;
+    let now = current_sec();
+
+    let schedule = sqlx::query_as::<_, (i64, i64)>(
+        db.sql("SELECT last_run, paying FROM payout_schedule WHERE kind = $1"),
+    )
+    .bind(kind.as_str())
+    .fetch_optional(&db.pool)
+    .await?;
+
+    let Some((last_run, paying)) = schedule else {
+        // The first interval starts now instead of paying on the first
+        // startup, another server may have started it already
+        sqlx::query(db.sql(
+            "INSERT INTO payout_schedule (kind, last_run) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        ))
+        .bind(kind.as_str())
+        .bind(now)
+        .execute(&db.pool)
+        .await?;
+        return Ok(None);
+    };
+
+    if paying != 0 {
+        return Ok(Some(last_run));
+    }
+
+    if now - last_run < interval {
+        return Ok(None);
+    }
+
+    let res = sqlx::query(db.sql(
+        "UPDATE payout_schedule SET last_run = $1, paying = 1 WHERE kind = $2 AND last_run = $3",
+    ))
+    .bind(now)
+    .bind(kind.as_str())
+    .bind(last_run)
+    .execute(&db.pool)
+    .await?;
+
+    Ok((res.rows_affected() == 1).then_some(now))
+}
+
+// Marks a run as paid to everyone
+async fn finish(kind: PayoutKind, period: i64) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query(db.sql("UPDATE payout_schedule SET paying = 0 WHERE kind = $1 AND last_run = $2"))
+        .bind(kind.as_str())
+        .bind(period)
+        .execute(&db.pool)
+        .await?;
+
+    Ok(())
+}
+
+// Pays a player their part of a run. The payout is recorded in the
+// transaction changing the balance, returns false if the player was paid in
+// this run already.
+async fn pay(
+    kind: PayoutKind,
+    period: i64,
+    player_uuid: &str,
+    amount: f64,
+) -> Result<bool, EconomyError> {
+    let currency = economy::default_currency().await;
+    let amount = currency.round(amount);
+    economy::check_amount(amount)?;
+    let amount =
+        economy::before_change(player_uuid, &currency, BalanceChangeCause::Deposit, amount).await?;
+    economy::check_amount(amount)?;
+
+    let balance = {
+        let _guard = economy::lock().await;
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let res = sqlx::query(db.sql(
+            "INSERT INTO payouts (kind, period, uuid, amount, paid_at) VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT DO NOTHING",
+        ))
+        .bind(kind.as_str())
+        .bind(period)
+        .bind(player_uuid)
+        .bind(amount)
+        .bind(current_sec())
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let change = economy::apply_in(&mut tx, player_uuid, &currency.name, amount).await?;
+        tx.commit().await?;
+
+        change.committed().await?
+    };
+
+    economy::after_change(
+        player_uuid,
+        &currency,
+        BalanceChangeCause::Deposit,
+        amount,
+        balance,
+    )
+    .await;
+    Ok(true)
+}
+
+// Pays a player and logs a failure, one player can not hold up the others.
+// Returns whether the player was paid.
+async fn pay_logged(kind: PayoutKind, period: i64, player_uuid: &str, amount: f64) -> bool {
+    match pay(kind, period, player_uuid, amount).await {
+        Ok(paid) => paid,
+        Err(e) => {
+            log::error!(
+                "Failed to pay {} {} to {}: {}",
+                kind.as_str(),
+                amount,
+                player_uuid,
+                e
+            );
+            false
+        }
+    }
+}
+
+// Interest on a balance, rounded down to the currency's decimals
+pub(crate) fn interest(currency: &Currency, balance: f64, rate: f64, cap: f64) -> f64 {
+    let factor = 10f64.powi(currency.decimals as i32);
+    let interest = (balance * rate / 100.0 * factor).floor() / factor;
+    if cap > 0.0 {
+        interest.min(cap)
+    } else {
+        interest
+    }
+}
+
+// Pays interest of a run on the balances of all players, returns the amount
+// of players paid
+pub async fn pay_interest(period: i64) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let config = get_config().await;
+    let (rate, cap, minimum) = (
+        config.value.interest_rate,
+        config.value.interest_cap,
+        config.value.interest_minimum,
+    );
+
+    let currency = economy::default_currency().await;
+
+    let db = get_db().await;
+    let uuids = sqlx::query_scalar::<_, String>(db.sql("SELECT uuid FROM players"))
+        .fetch_all(&db.pool)
+        .await?;
+
+    let mut paid = 0;
+    for uuid in uuids {
+        // Online balances are taken from the cache
+        let balance = match economy::balance(&uuid, &currency.name).await {
+            Ok(balance) => balance,
+            Err(e) => {
+                log::error!("Failed to read the balance of {} for interest: {}", uuid, e);
+                continue;
+            }
+        };
+        if balance < minimum {
+            continue;
+        }
+
+        let amount = interest(&currency, balance, rate, cap);
+        if amount > 0.0 && pay_logged(PayoutKind::Interest, period, &uuid, amount).await {
+            paid += 1;
+        }
+    }
+
+    Ok(paid)
+}
+
+// Pays the fixed income of a run to online players, returns the amount of
+// players paid
+pub async fn pay_income(server: &Server, period: i64) -> usize {
+    let config = get_config().await;
+    let amount = config.value.income_amount;
+    let afk_timeout = if config.value.income_skip_afk {
+        parse_duration(&config.value.afk_timeout)
+    } else {
+        None
+    };
+
+    let mut paid = 0;
+    for player in server.get_all_players().await {
+        let uuid = player.gameprofile.id.to_string();
+        if afk_timeout.is_some_and(|timeout| is_afk(&uuid, timeout)) {
+            continue;
+        }
+
+        if pay_logged(PayoutKind::Income, period, &uuid, amount).await {
+            paid += 1;
+        }
+    }
+
+    paid
+}
+
+// Checks every minute whether interest or income is due
+pub fn start_payout_task(server: Arc<Server>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+
+            let config = get_config().await;
+            let interest_interval = parse_duration(&config.value.interest_interval)
+                .filter(|_| config.value.interest_rate > 0.0);
+            let income_interval = parse_duration(&config.value.income_interval)
+                .filter(|_| config.value.income_amount > 0.0);
+
+            // A run that fails as a whole stays unfinished and is resumed
+            // on the next check
+            if let Some(interval) = interest_interval {
+                match claim(PayoutKind::Interest, interval).await {
+                    Ok(Some(period)) => match pay_interest(period).await {
+                        Ok(paid) => {
+                            log::info!("Paid interest to {} players.", paid);
+                            if let Err(e) = finish(PayoutKind::Interest, period).await {
+                                log::error!("Failed to finish interest payout: {}", e);
+                            }
+                        }
+                        Err(e) => log::error!("Failed to pay interest: {}", e),
+                    },
+                    Ok(None) => {}
+                    Err(e) => log::error!("Failed to check interest schedule: {}", e),
+                }
+            }
+
+            if let Some(interval) = income_interval {
+                match claim(PayoutKind::Income, interval).await {
+                    Ok(Some(period)) => {
+                        let paid = pay_income(&server, period).await;
+                        log::info!("Paid income to {} players.", paid);
+                        if let Err(e) = finish(PayoutKind::Income, period).await {
+                            log::error!("Failed to finish income payout: {}", e);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => log::error!("Failed to check income schedule: {}", e),
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,355 @@
+// Chest menu for the shop. The menu is read-only, clicks are turned into
+// trades or page changes and the client is sent the real contents again.
+
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use pumpkin::entity::player::Player;
+use pumpkin_data::item::Item;
+use pumpkin_inventory::{
+    generic_container_screen_handler::{create_generic_9x6, GenericContainerScreenHandler},
+    player::player_inventory::PlayerInventory,
+    screen_handler::{
+        InventoryPlayer, ScreenHandler, ScreenHandlerBehaviour, ScreenHandlerFactory,
+    },
+};
+use pumpkin_protocol::java::server::play::SlotActionType;
+use pumpkin_util::text::TextComponent;
+use pumpkin_world::{
+    inventory::{Clearable, Inventory},
+    item::ItemStack,
+};
+use tokio::sync::Mutex;
+
+use super::{get_shop, inventory, ShopError, TradeKind};
+use crate::{
+    economy::EconomyError,
+    utils::{error_colour, failed_message, neutral_colour, success_colour},
+};
+
+const ROWS: usize = 6;
+// The last row holds the navigation buttons
+const PAGE_SIZE: usize = (ROWS - 1) * 9;
+const SLOT_PREVIOUS: usize = PAGE_SIZE;
+const SLOT_BACK: usize = PAGE_SIZE + 4;
+const SLOT_NEXT: usize = PAGE_SIZE + 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Page {
+    Categories,
+    // Category index and page within it
+    Category(usize, usize),
+}
+
+// Contents of the menu, never changed by clicks
+struct ShopInventory {
+    items: Vec<Arc<Mutex<ItemStack>>>,
+}
+
+impl ShopInventory {
+    fn new(mut items: Vec<ItemStack>) -> Self {
+        items.resize(ROWS * 9, ItemStack::EMPTY.clone());
+        ShopInventory {
+            items: items.into_iter().map(|i| Arc::new(Mutex::new(i))).collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Inventory for ShopInventory {
+    fn size(&self) -> usize {
+        self.items.len()
+    }
+
+    async fn is_empty(&self) -> bool {
+        for item in self.items.iter() {
+            if !item.lock().await.is_empty() {
+                return false;
+            }
+        }
+        true
+    }
+
+    async fn get_stack(&self, slot: usize) -> Arc<Mutex<ItemStack>> {
+        self.items[slot].clone()
+    }
+
+    async fn remove_stack(&self, _slot: usize) -> ItemStack {
+        ItemStack::EMPTY.clone()
+    }
+
+    async fn remove_stack_specific(&self, _slot: usize, _amount: u8) -> ItemStack {
+        ItemStack::EMPTY.clone()
+    }
+
+    async fn set_stack(&self, _slot: usize, _stack: ItemStack) {}
+
+    fn mark_dirty(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[async_trait]
+impl Clearable for ShopInventory {
+    async fn clear(&self) {}
+}
+
+fn icon(item: &'static Item, count: u32) -> ItemStack {
+    ItemStack::new(
+        count.clamp(1, item.components.max_stack_size as u32) as u8,
+        item,
+    )
+}
+
+// Items shown on a page, the navigation row included
+async fn contents(page: Page) -> Vec<ItemStack> {
+    let shop = get_shop().await;
+    let mut items = vec![ItemStack::EMPTY.clone(); ROWS * 9];
+
+    match page {
+        Page::Categories => {
+            for (slot, category) in shop.categories.iter().take(PAGE_SIZE).enumerate() {
+                items[slot] = icon(category.icon, 1);
+            }
+        }
+        Page::Category(index, page) => {
+            let Some(category) = shop.categories.get(index) else {
+                return items;
+            };
+
+            let start = page * PAGE_SIZE;
+            for (slot, item) in category
+                .items
+                .iter()
+                .skip(start)
+                .take(PAGE_SIZE)
+                .enumerate()
+            {
+                items[slot] = icon(item.item, item.quantity);
+            }
+
+            if page > 0 {
+                items[SLOT_PREVIOUS] = icon(&Item::ARROW, 1);
+            }
+            items[SLOT_BACK] = icon(&Item::BARRIER, 1);
+            if category.items.len() > start + PAGE_SIZE {
+                items[SLOT_NEXT] = icon(&Item::ARROW, 1);
+            }
+        }
+    }
+
+    items
+}
+
+// Prices of the items on a category page, the menu has no room for them
+async fn price_list(index: usize, page: usize) -> Option<TextComponent> {
+    let shop = get_shop().await;
+    let category = shop.categories.get(index)?;
+    let currency = shop.currency().await.ok()?;
+
+    let mut lines = vec![format!(
+        "{}: left click to buy, right click to sell, shift for more.",
+        category.name
+    )];
+    for item in category.items.iter().skip(page * PAGE_SIZE).take(PAGE_SIZE) {
+        let buy = item.buy.map_or("-".to_string(), |p| currency.format(p));
+        let sell = item.sell.map_or("-".to_string(), |p| currency.format(p));
+        let mut line = format!(
+            "{}x {}: buy {}, sell {}",
+            item.quantity, item.key, buy, sell
+        );
+        if let Ok(Some(stock)) = super::get_stock(category, item).await {
+            line.push_str(&format!(" ({} in stock)", stock));
+        }
+        lines.push(line);
+    }
+
+    Some(TextComponent::text(lines.join("\n")).color_rgb(neutral_colour()))
+}
+
+pub async fn open(player: &Arc<Player>, page: Page) {
+    let factory = ShopScreenFactory {
+        player: player.clone(),
+        page,
+    };
+    player.open_handled_screen(&factory).await;
+
+    if let Page::Category(index, page) = page {
+        if let Some(msg) = price_list(index, page).await {
+            player.send_system_message(&msg).await;
+        }
+    }
+}
+
+struct ShopScreenFactory {
+    player: Arc<Player>,
+    page: Page,
+}
+
+#[async_trait]
+impl ScreenHandlerFactory for ShopScreenFactory {
+    async fn create_screen_handler(
+        &self,
+        sync_id: u8,
+        player_inventory: &Arc<PlayerInventory>,
+        _player: &dyn InventoryPlayer,
+    ) -> Option<Arc<Mutex<dyn ScreenHandler>>> {
+        let inventory = Arc::new(ShopInventory::new(contents(self.page).await));
+        let inner = create_generic_9x6(sync_id, player_inventory, inventory).await;
+
+        Some(Arc::new(Mutex::new(ShopScreenHandler {
+            inner,
+            player: self.player.clone(),
+            page: self.page,
+        })))
+    }
+
+    fn get_display_name(&self) -> TextComponent {
+        TextComponent::text("Shop")
+    }
+}
+
+struct ShopScreenHandler {
+    inner: GenericContainerScreenHandler,
+    player: Arc<Player>,
+    page: Page,
+}
+
+#[async_trait]
+impl ScreenHandler for ShopScreenHandler {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn get_behaviour(&self) -> &ScreenHandlerBehaviour {
+        self.inner.get_behaviour()
+    }
+
+    fn get_behaviour_mut(&mut self) -> &mut ScreenHandlerBehaviour {
+        self.inner.get_behaviour_mut()
+    }
+
+    async fn quick_move(&mut self, _player: &dyn InventoryPlayer, _slot_index: i32) -> ItemStack {
+        ItemStack::EMPTY.clone()
+    }
+
+    async fn on_slot_click(
+        &mut self,
+        slot_index: i32,
+        button: i32,
+        action_type: SlotActionType,
+        _player: &dyn InventoryPlayer,
+    ) {
+        // Clicks in the player's own inventory are ignored as well, so
+        // nothing can be put into the menu
+        if (0..(ROWS * 9) as i32).contains(&slot_index) {
+            let shift = matches!(action_type, SlotActionType::QuickMove);
+            if matches!(action_type, SlotActionType::Pickup) || shift {
+                // Trades change the player's inventory, which is part of
+                // this screen, so they run once the click is handled
+                tokio::spawn(click(
+                    self.player.clone(),
+                    self.page,
+                    slot_index as usize,
+                    button == 1,
+                    shift,
+                ));
+            }
+        }
+
+        self.sync_state().await;
+    }
+}
+
+async fn click(player: Arc<Player>, page: Page, slot: usize, right: bool, shift: bool) {
+    let shop = get_shop().await;
+
+    match page {
+        Page::Categories => {
+            if slot < shop.categories.len() {
+                open(&player, Page::Category(slot, 0)).await;
+            }
+        }
+        Page::Category(index, page) => match slot {
+            SLOT_BACK => open(&player, Page::Categories).await,
+            SLOT_PREVIOUS if page > 0 => open(&player, Page::Category(index, page - 1)).await,
+            SLOT_NEXT => {
+                let items = shop.categories.get(index).map_or(0, |c| c.items.len());
+                if items > (page + 1) * PAGE_SIZE {
+                    open(&player, Page::Category(index, page + 1)).await;
+                }
+            }
+            slot if slot < PAGE_SIZE => {
+                let item = page * PAGE_SIZE + slot;
+                let kind = if right {
+                    TradeKind::Sell
+                } else {
+                    TradeKind::Buy
+                };
+                trade(&player, index, item, kind, shift).await;
+            }
+            _ => {}
+        },
+    }
+}
+
+// Shift buys a full stack or sells everything the player has of the item
+async fn times(player: &Player, index: usize, item: usize, kind: TradeKind, shift: bool) -> u32 {
+    if !shift {
+        return 1;
+    }
+
+    let shop = get_shop().await;
+    let Ok((_, item)) = shop.get(index, item) else {
+        return 1;
+    };
+
+    let amount = match kind {
+        TradeKind::Buy => item.item.components.max_stack_size as u32,
+        TradeKind::Sell => inventory::count(player, item.item).await,
+    };
+    (amount / item.quantity).max(1)
+}
+
+async fn trade(player: &Arc<Player>, index: usize, item: usize, kind: TradeKind, shift: bool) {
+    let shop = get_shop().await;
+    let Ok((_, shop_item)) = shop.get(index, item) else {
+        return;
+    };
+
+    let times = times(player, index, item, kind, shift).await;
+    let res = match kind {
+        TradeKind::Buy => super::buy(player, index, item, times).await,
+        TradeKind::Sell => super::sell(player, index, item, times).await,
+    };
+
+    let msg = match res {
+        Ok(trade) => {
+            let Ok(currency) = shop.currency().await else {
+                return;
+            };
+            let msg = format!(
+                "{} {}x {} for {}, your balance is now {}.",
+                if kind == TradeKind::Buy {
+                    "Bought"
+                } else {
+                    "Sold"
+                },
+                trade.quantity,
+                shop_item.key,
+                currency.format(trade.price),
+                currency.format(trade.balance)
+            );
+            TextComponent::text(msg).color_rgb(success_colour())
+        }
+        Err(ShopError::Economy(EconomyError::Database(e))) => {
+            log::error!("Shop trade failed: {}", e);
+            failed_message()
+        }
+        Err(e) => TextComponent::text(e.to_string()).color_rgb(error_colour()),
+    };
+
+    player.send_system_message(&msg).await;
+}
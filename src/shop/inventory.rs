@@ -0,0 +1,233 @@
+// Player inventory helpers for the shop. Only the main inventory and hotbar
+// are used, armour and the offhand are left alone. Stacks with components,
+// e.g. enchanted or renamed items, are worth more than their type and are
+// only moved as whole stacks.
+
+use pumpkin::entity::player::Player;
+use pumpkin_data::item::Item;
+use pumpkin_world::item::ItemStack;
+
+fn max_stack(item: &'static Item) -> u32 {
+    item.components.max_stack_size as u32
+}
+
+// Whether a stack has nothing but the defaults of its item type
+fn is_plain(stack: &ItemStack) -> bool {
+    stack.patch.is_empty()
+}
+
+// Sends the changed slots to the client
+async fn sync(player: &Player) {
+    let handler = player.current_screen_handler.lock().await;
+    handler.lock().await.send_content_updates().await;
+}
+
+// Amount of the item that still fits into the inventory
+pub async fn room_for(player: &Player, item: &'static Item) -> u32 {
+    let mut room = 0;
+    for slot in player.inventory().main_inventory.iter() {
+        let stack = slot.lock().await;
+        if stack.is_empty() {
+            room += max_stack(item);
+        } else if stack.item.id == item.id && is_plain(&stack) {
+            room += max_stack(item).saturating_sub(stack.item_count as u32);
+        }
+    }
+
+    room
+}
+
+// Amount of the item in the inventory
+pub async fn count(player: &Player, item: &'static Item) -> u32 {
+    let mut count = 0;
+    for slot in player.inventory().main_inventory.iter() {
+        let stack = slot.lock().await;
+        if !stack.is_empty() && stack.item.id == item.id && is_plain(&stack) {
+            count += stack.item_count as u32;
+        }
+    }
+
+    count
+}
+
+// Item type and amount in the selected hotbar slot, None if it is empty or
+// holds a stack with components
+pub async fn held(player: &Player) -> Option<(&'static Item, u32)> {
+    let slot = player.inventory().held_item();
+    let stack = slot.lock().await;
+    if stack.is_empty() || !is_plain(&stack) {
+        return None;
+    }
+
+    Some((stack.item, stack.item_count as u32))
+}
+
+// Amount of every item type in the inventory, in slot order
+pub async fn totals(player: &Player) -> Vec<(&'static Item, u32)> {
+    let mut totals: Vec<(&'static Item, u32)> = Vec::new();
+    for slot in player.inventory().main_inventory.iter() {
+        let stack = slot.lock().await;
+        if stack.is_empty() || !is_plain(&stack) {
+            continue;
+        }
+
+        match totals.iter_mut().find(|(item, _)| item.id == stack.item.id) {
+            Some((_, count)) => *count += stack.item_count as u32,
+            None => totals.push((stack.item, stack.item_count as u32)),
+        }
+    }
+
+    totals
+}
+
+// Adds the items to existing stacks first, then to empty slots. Nothing is
+// added if they do not all fit.
+pub async fn give(player: &Player, item: &'static Item, amount: u32) -> bool {
+    if room_for(player, item).await < amount {
+        return false;
+    }
+
+    let mut left = amount;
+    for fill_empty in [false, true] {
+        for slot in player.inventory().main_inventory.iter() {
+            if left == 0 {
+                break;
+            }
+
+            let mut stack = slot.lock().await;
+            if stack.is_empty() {
+                if !fill_empty {
+                    continue;
+                }
+                let added = left.min(max_stack(item));
+                *stack = ItemStack::new(added as u8, item);
+                left -= added;
+            } else if !fill_empty && stack.item.id == item.id && is_plain(&stack) {
+                let added = left.min(max_stack(item).saturating_sub(stack.item_count as u32));
+                stack.item_count += added as u8;
+                left -= added;
+            }
+        }
+    }
+
+    sync(player).await;
+    true
+}
+
+// Removes the items, starting with the last slots so the hotbar is emptied
+// last. Nothing is removed if there are not enough.
+pub async fn take(player: &Player, item: &'static Item, amount: u32) -> bool {
+    if count(player, item).await < amount {
+        return false;
+    }
+
+    let mut left = amount;
+    for slot in player.inventory().main_inventory.iter().rev() {
+        if left == 0 {
+            break;
+        }
+
+        let mut stack = slot.lock().await;
+        if stack.is_empty() || stack.item.id != item.id || !is_plain(&stack) {
+            continue;
+        }
+
+        let taken = left.min(stack.item_count as u32);
+        stack.decrement(taken as u8);
+        left -= taken;
+    }
+
+    sync(player).await;
+    true
+}
+
+// Removes items from the selected hotbar slot only, nothing is removed if
+// it does not hold enough of the item
+pub async fn take_held(player: &Player, item: &'static Item, amount: u32) -> bool {
+    {
+        let slot = player.inventory().held_item();
+        let mut stack = slot.lock().await;
+        if stack.is_empty()
+            || stack.item.id != item.id
+            || !is_plain(&stack)
+            || (stack.item_count as u32) < amount
+        {
+            return false;
+        }
+        stack.decrement(amount as u8);
+    }
+
+    sync(player).await;
+    true
+}
+
+// Copy of the stack in the selected hotbar slot, None if it is empty
+pub async fn held_stack(player: &Player) -> Option<ItemStack> {
+    let slot = player.inventory().held_item();
+    let stack = slot.lock().await;
+    if stack.is_empty() {
+        return None;
+    }
+
+    Some(stack.clone())
+}
+
+// Swaps the stack in the selected hotbar slot for a changed one, returns the
+// stack as it was. None if the slot is empty or change gives nothing.
+pub async fn replace_held_stack(
+    player: &Player,
+    change: impl FnOnce(&ItemStack) -> Option<ItemStack>,
+) -> Option<ItemStack> {
+    let previous = {
+        let slot = player.inventory().held_item();
+        let mut stack = slot.lock().await;
+        if stack.is_empty() {
+            return None;
+        }
+        let changed = change(&stack)?;
+        std::mem::replace(&mut *stack, changed)
+    };
+
+    sync(player).await;
+    Some(previous)
+}
+
+// Swaps every stack change gives a new one for, an empty stack removes it.
+// Returns the amount of stacks replaced.
+pub async fn replace_stacks(
+    player: &Player,
+    mut change: impl FnMut(&ItemStack) -> Option<ItemStack>,
+) -> usize {
+    let mut replaced = 0;
+    for slot in player.inventory().main_inventory.iter() {
+        let mut stack = slot.lock().await;
+        if stack.is_empty() {
+            continue;
+        }
+        if let Some(changed) = change(&stack) {
+            *stack = changed;
+            replaced += 1;
+        }
+    }
+
+    if replaced > 0 {
+        sync(player).await;
+    }
+    replaced
+}
+
+// Puts a stack into an empty slot as it is, so its components are kept.
+// False if there is no empty slot.
+pub async fn give_stack(player: &Player, stack: ItemStack) -> bool {
+    for slot in player.inventory().main_inventory.iter() {
+        let mut current = slot.lock().await;
+        if current.is_empty() {
+            *current = stack;
+            drop(current);
+            sync(player).await;
+            return true;
+        }
+    }
+
+    false
+}
@@ -0,0 +1,535 @@
+pub mod gui;
+pub mod inventory;
+pub mod worth;
+
+use std::{fmt, path::Path, sync::Arc};
+
+use jsonc_parser::parse_to_serde_value;
+use pumpkin::entity::player::Player;
+use pumpkin_data::item::Item;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::{
+    api::BalanceChangeCause,
+    config::get_data_folder,
+    db::get_db,
+    economy::{self, Currency, EconomyError},
+    utils::current_sec,
+};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShopValue {
+    // Currency prices are in, leave empty for the default currency
+    #[serde(default)]
+    pub currency: String,
+    #[serde(default)]
+    pub categories: Vec<CategoryValue>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CategoryValue {
+    pub name: String,
+    // Item shown for the category in the shop
+    pub icon: String,
+    #[serde(default)]
+    pub items: Vec<ShopItemValue>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShopItemValue {
+    pub item: String,
+    // Amount of items traded at once
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+    // Items without a buy price can not be bought, without a sell price
+    // they can not be sold
+    pub buy: Option<f64>,
+    pub sell: Option<f64>,
+    // Items the shop has to sell, selling adds to it. Unlimited if left out
+    pub stock: Option<i64>,
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShopError {
+    Economy(EconomyError),
+    UnknownCategory,
+    NotForSale,
+    NotBought,
+    OutOfStock,
+    // Not enough room in the player's inventory
+    NoSpace,
+    NotEnoughItems,
+    NothingToSell,
+}
+
+impl fmt::Display for ShopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShopError::Economy(e) => write!(f, "{}", e),
+            ShopError::UnknownCategory => write!(f, "That shop category does not exist."),
+            ShopError::NotForSale => write!(f, "That item can not be bought."),
+            ShopError::NotBought => write!(f, "That item can not be sold."),
+            ShopError::OutOfStock => write!(f, "That item is out of stock."),
+            ShopError::NoSpace => write!(f, "You do not have enough room in your inventory."),
+            ShopError::NotEnoughItems => write!(f, "You do not have enough of that item."),
+            ShopError::NothingToSell => write!(f, "You have nothing that can be sold."),
+        }
+    }
+}
+
+impl std::error::Error for ShopError {}
+
+impl From<EconomyError> for ShopError {
+    fn from(e: EconomyError) -> Self {
+        ShopError::Economy(e)
+    }
+}
+
+impl From<sqlx::Error> for ShopError {
+    fn from(e: sqlx::Error) -> Self {
+        ShopError::Economy(e.into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TradeKind {
+    Buy,
+    Sell,
+}
+
+impl TradeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeKind::Buy => "buy",
+            TradeKind::Sell => "sell",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ShopItem {
+    // Registry key without the minecraft: prefix
+    pub key: String,
+    pub item: &'static Item,
+    pub quantity: u32,
+    pub buy: Option<f64>,
+    pub sell: Option<f64>,
+    pub stock: Option<i64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Category {
+    pub name: String,
+    pub icon: &'static Item,
+    pub items: Vec<ShopItem>,
+}
+
+#[derive(Debug)]
+pub struct Shop {
+    currency: String,
+    pub categories: Vec<Category>,
+}
+
+// A completed trade, prices are the total paid or received
+#[derive(Clone, Debug)]
+pub struct Trade {
+    pub quantity: u32,
+    pub price: f64,
+    pub balance: f64,
+}
+
+const DEFAULT_SHOP: &str = r#"
+{
+    // Currency prices are in, leave empty for the default currency
+    "currency": "",
+    // Categories are shown in the /shop menu with their icon. Items are
+    // registry keys like diamond. Quantity is the amount traded at once,
+    // items without a buy or sell price can only be sold or bought.
+    // Stock limits how many items can be bought, selling adds to it
+    "categories": [
+        {
+            "name": "Ores",
+            "icon": "diamond",
+            "items": [
+                { "item": "coal", "quantity": 16, "buy": 20.0, "sell": 8.0 },
+                { "item": "iron_ingot", "quantity": 8, "buy": 40.0, "sell": 16.0 },
+                { "item": "diamond", "quantity": 1, "buy": 100.0, "sell": 40.0, "stock": 64 }
+            ]
+        },
+        {
+            "name": "Food",
+            "icon": "bread",
+            "items": [
+                { "item": "bread", "quantity": 16, "buy": 16.0 },
+                { "item": "cooked_beef", "quantity": 16, "buy": 32.0 },
+                { "item": "wheat", "quantity": 32, "sell": 8.0 }
+            ]
+        }
+    ]
+}
+"#;
+
+static SHOP_INSTANCE: OnceCell<Arc<Shop>> = OnceCell::const_new();
+
+fn lookup(key: &str) -> Option<&'static Item> {
+    Item::from_registry_key(key.trim_start_matches("minecraft:"))
+}
+
+// Resolves the item keys, unknown items are logged and left out
+fn build(value: ShopValue) -> Shop {
+    let mut categories = Vec::new();
+    for category in value.categories {
+        let Some(icon) = lookup(&category.icon) else {
+            log::warn!(
+                "Unknown icon {} for shop category {}.",
+                category.icon,
+                category.name
+            );
+            continue;
+        };
+
+        let mut items = Vec::new();
+        for item in category.items {
+            let Some(resolved) = lookup(&item.item) else {
+                log::warn!(
+                    "Unknown item {} in shop category {}.",
+                    item.item,
+                    category.name
+                );
+                continue;
+            };
+
+            items.push(ShopItem {
+                key: item.item.trim_start_matches("minecraft:").to_string(),
+                item: resolved,
+                quantity: item.quantity.max(1),
+                buy: item.buy.filter(|p| *p > 0.0),
+                sell: item.sell.filter(|p| *p > 0.0),
+                stock: item.stock.map(|s| s.max(0)),
+            });
+        }
+
+        categories.push(Category {
+            name: category.name,
+            icon,
+            items,
+        });
+    }
+
+    Shop {
+        currency: value.currency,
+        categories,
+    }
+}
+
+// Stock rows are created for limited items the first time they are seen,
+// afterwards the stock lives in the database
+async fn init_stock(shop: &Shop) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    for category in shop.categories.iter() {
+        for item in category.items.iter() {
+            let Some(stock) = item.stock else {
+                continue;
+            };
+
+            // Another server may have created the row at the same time
+            sqlx::query(db.sql(
+                "INSERT INTO shop_stock (category, item, stock) VALUES ($1, $2, $3)
+                    ON CONFLICT DO NOTHING",
+            ))
+            .bind(&category.name)
+            .bind(&item.key)
+            .bind(stock)
+            .execute(&db.pool)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Loads shop.jsonc from the plugin folder, writing the example shop if
+// there is none. Needs the database for stock.
+pub async fn setup_shop() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = get_data_folder().join("shop.jsonc");
+    if !Path::new(&path).exists() {
+        tokio::fs::write(&path, DEFAULT_SHOP).await?;
+    }
+
+    let data = tokio::fs::read_to_string(&path).await?;
+    let value = match parse_to_serde_value(&data, &Default::default())? {
+        Some(json_value) => serde_json::from_value(json_value)?,
+        None => ShopValue {
+            currency: String::new(),
+            categories: Vec::new(),
+        },
+    };
+
+    let shop = build(value);
+    init_stock(&shop).await?;
+
+    if SHOP_INSTANCE.set(Arc::new(shop)).is_err() {
+        return Err("The shop was already set up.".into());
+    }
+
+    Ok(())
+}
+
+pub async fn get_shop() -> Arc<Shop> {
+    SHOP_INSTANCE.get().unwrap().clone()
+}
+
+impl Shop {
+    pub async fn currency(&self) -> Result<Currency, EconomyError> {
+        if self.currency.is_empty() {
+            return Ok(economy::default_currency().await);
+        }
+        economy::get_currency(&self.currency).await
+    }
+
+    pub fn get(&self, category: usize, item: usize) -> Result<(&Category, &ShopItem), ShopError> {
+        let category = self
+            .categories
+            .get(category)
+            .ok_or(ShopError::UnknownCategory)?;
+        let item = category.items.get(item).ok_or(ShopError::UnknownCategory)?;
+        Ok((category, item))
+    }
+}
+
+// Items left of a limited item, None for unlimited ones
+pub async fn get_stock(category: &Category, item: &ShopItem) -> Result<Option<i64>, ShopError> {
+    if item.stock.is_none() {
+        return Ok(None);
+    }
+
+    let db = get_db().await;
+    let stock = sqlx::query_scalar::<_, i64>(
+        db.sql("SELECT stock FROM shop_stock WHERE category = $1 AND item = $2"),
+    )
+    .bind(&category.name)
+    .bind(&item.key)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    Ok(Some(stock.unwrap_or(0)))
+}
+
+// Changes the stock of a limited item, false if there is not enough left
+async fn change_stock(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    category: &Category,
+    item: &ShopItem,
+    delta: i64,
+) -> Result<bool, sqlx::Error> {
+    if item.stock.is_none() {
+        return Ok(true);
+    }
+
+    let db = get_db().await;
+    let res = sqlx::query(db.sql(
+        "UPDATE shop_stock SET stock = stock + $1
+            WHERE category = $2 AND item = $3 AND stock + $4 >= 0",
+    ))
+    .bind(delta)
+    .bind(&category.name)
+    .bind(&item.key)
+    .bind(delta)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+// Records a trade, in the transaction making it
+#[allow(clippy::too_many_arguments)]
+async fn record(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    player_uuid: &str,
+    category: &Category,
+    item: &ShopItem,
+    kind: TradeKind,
+    quantity: u32,
+    price: f64,
+    currency: &str,
+) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query(db.sql(
+        "INSERT INTO shop_trades (uuid, category, item, kind, quantity, price, currency, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    ))
+    .bind(player_uuid)
+    .bind(&category.name)
+    .bind(&item.key)
+    .bind(kind.as_str())
+    .bind(quantity as i64)
+    .bind(price)
+    .bind(currency)
+    .bind(current_sec())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Buys the item `times` times. The stock, balance and trade are written in
+// one transaction that only commits once the items were handed out.
+pub async fn buy(
+    player: &Arc<Player>,
+    category: usize,
+    item: usize,
+    times: u32,
+) -> Result<Trade, ShopError> {
+    let shop = get_shop().await;
+    let (category, item) = shop.get(category, item)?;
+    let unit = item.buy.ok_or(ShopError::NotForSale)?;
+    let currency = shop.currency().await?;
+    let player_uuid = player.gameprofile.id.to_string();
+
+    let quantity = item.quantity * times.max(1);
+    let price = currency.round(unit * times.max(1) as f64);
+    economy::check_amount(price)?;
+    let price =
+        economy::before_change(&player_uuid, &currency, BalanceChangeCause::Withdraw, price)
+            .await?;
+    economy::check_amount(price)?;
+
+    let balance = {
+        let _guard = economy::lock().await;
+
+        if inventory::room_for(player, item.item).await < quantity {
+            return Err(ShopError::NoSpace);
+        }
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        if !change_stock(&mut tx, category, item, -(quantity as i64)).await? {
+            return Err(ShopError::OutOfStock);
+        }
+        let change = economy::apply_in(&mut tx, &player_uuid, &currency.name, -price).await?;
+        record(
+            &mut tx,
+            &player_uuid,
+            category,
+            item,
+            TradeKind::Buy,
+            quantity,
+            price,
+            &currency.name,
+        )
+        .await?;
+
+        // The inventory may have filled up since it was checked, dropping
+        // the transaction undoes the rest
+        if !inventory::give(player, item.item, quantity).await {
+            return Err(ShopError::NoSpace);
+        }
+        if let Err(e) = tx.commit().await {
+            if !inventory::take(player, item.item, quantity).await {
+                log::error!(
+                    "Failed to take back {} {} from {} after a failed shop purchase.",
+                    quantity,
+                    item.key,
+                    player_uuid
+                );
+            }
+            return Err(e.into());
+        }
+
+        change.committed().await?
+    };
+
+    economy::after_change(
+        &player_uuid,
+        &currency,
+        BalanceChangeCause::Withdraw,
+        price,
+        balance,
+    )
+    .await;
+
+    Ok(Trade {
+        quantity,
+        price,
+        balance,
+    })
+}
+
+// Sells the item `times` times. The balance, stock and trade are written in
+// one transaction that only commits once the items were taken.
+pub async fn sell(
+    player: &Arc<Player>,
+    category: usize,
+    item: usize,
+    times: u32,
+) -> Result<Trade, ShopError> {
+    let shop = get_shop().await;
+    let (category, item) = shop.get(category, item)?;
+    let unit = item.sell.ok_or(ShopError::NotBought)?;
+    let currency = shop.currency().await?;
+    let player_uuid = player.gameprofile.id.to_string();
+
+    let quantity = item.quantity * times.max(1);
+    let price = currency.round(unit * times.max(1) as f64);
+    economy::check_amount(price)?;
+    let price =
+        economy::before_change(&player_uuid, &currency, BalanceChangeCause::Deposit, price).await?;
+    economy::check_amount(price)?;
+
+    let balance = {
+        let _guard = economy::lock().await;
+
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let change = economy::apply_in(&mut tx, &player_uuid, &currency.name, price).await?;
+        change_stock(&mut tx, category, item, quantity as i64).await?;
+        record(
+            &mut tx,
+            &player_uuid,
+            category,
+            item,
+            TradeKind::Sell,
+            quantity,
+            price,
+            &currency.name,
+        )
+        .await?;
+
+        if !inventory::take(player, item.item, quantity).await {
+            return Err(ShopError::NotEnoughItems);
+        }
+        if let Err(e) = tx.commit().await {
+            if !inventory::give(player, item.item, quantity).await {
+                log::error!(
+                    "Failed to give {} {} back to {} after a failed shop sale.",
+                    quantity,
+                    item.key,
+                    player_uuid
+                );
+            }
+            return Err(e.into());
+        }
+
+        change.committed().await?
+    };
+
+    economy::after_change(
+        &player_uuid,
+        &currency,
+        BalanceChangeCause::Deposit,
+        price,
+        balance,
+    )
+    .await;
+
+    Ok(Trade {
+        quantity,
+        price,
+        balance,
+    })
+}
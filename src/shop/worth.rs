@@ -0,0 +1,280 @@
+// Item values for /sell and /worth. Values come from the config and are in
+// the default currency, dynamic pricing lowers them as more is sold.
+
+use std::sync::Arc;
+
+use pumpkin::entity::player::Player;
+use pumpkin_data::item::Item;
+
+use super::{inventory, lookup, ShopError};
+use crate::{
+    api::BalanceChangeCause,
+    config::get_config,
+    db::get_db,
+    economy::{self, Currency},
+    utils::{current_sec, parse_duration},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum SellTarget {
+    // The stack in the selected hotbar slot
+    Hand,
+    // Every item with a value
+    All,
+    Item(&'static Item),
+}
+
+// One item type of a sale, prices are totals
+#[derive(Clone, Debug)]
+pub struct SoldItem {
+    pub item: &'static Item,
+    pub quantity: u32,
+    pub price: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sale {
+    pub items: Vec<SoldItem>,
+    pub price: f64,
+    pub balance: f64,
+}
+
+// Item name as shown to players, e.g. iron ingot
+pub fn display_name(item: &'static Item) -> String {
+    item.registry_key.replace('_', " ")
+}
+
+// Items by registry key, with or without the minecraft: prefix
+pub fn find_item(name: &str) -> Option<&'static Item> {
+    lookup(&name.to_lowercase())
+}
+
+// Item type in the selected hotbar slot
+pub async fn held_item(player: &Player) -> Option<&'static Item> {
+    inventory::held(player).await.map(|(item, _)| item)
+}
+
+// Configured value of one item, None if it can not be sold
+pub async fn base_value(item: &'static Item) -> Option<f64> {
+    let config = get_config().await;
+    let values = &config.value.item_values;
+    values
+        .get(item.registry_key)
+        .or_else(|| values.get(&format!("minecraft:{}", item.registry_key)))
+        .copied()
+        .filter(|v| *v > 0.0)
+}
+
+// Items of a type sold by anyone within the dynamic pricing window
+async fn sold_recently(item: &'static Item, window: i64) -> Result<i64, sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query_scalar::<_, i64>(db.sql(
+        "SELECT CAST(COALESCE(SUM(quantity), 0) AS BIGINT) FROM item_sales
+                WHERE item = $1 AND sold_at >= $2",
+    ))
+    .bind(item.registry_key)
+    .bind(current_sec() - window)
+    .fetch_one(&db.pool)
+    .await
+}
+
+// Value of selling the items now, None if they can not be sold. With
+// dynamic pricing every item is priced after the ones before it.
+pub async fn price(
+    currency: &Currency,
+    item: &'static Item,
+    quantity: u32,
+) -> Result<Option<f64>, ShopError> {
+    let Some(value) = base_value(item).await else {
+        return Ok(None);
+    };
+
+    let config = get_config().await;
+    let window = parse_duration(&config.value.sell_dynamic_window).unwrap_or(0);
+    if !config.value.sell_dynamic_pricing || window <= 0 {
+        return Ok(Some(currency.round(value * quantity as f64)));
+    }
+
+    let step = config.value.sell_dynamic_step.max(1);
+    let drop = config.value.sell_dynamic_drop.max(0.0) / 100.0;
+    let floor = config.value.sell_dynamic_floor.clamp(0.0, 100.0) / 100.0;
+    let sold = sold_recently(item, window).await?;
+
+    let total = (0..quantity as i64)
+        .map(|i| {
+            let steps = ((sold + i) / step) as f64;
+            value * (1.0 - drop * steps).max(floor)
+        })
+        .sum::<f64>();
+
+    Ok(Some(currency.round(total)))
+}
+
+// Records a sale, in the transaction paying for it
+async fn record(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    player_uuid: &str,
+    currency: &str,
+    sold: &SoldItem,
+) -> Result<(), sqlx::Error> {
+    let db = get_db().await;
+    sqlx::query(db.sql(
+        "INSERT INTO item_sales (uuid, item, quantity, price, currency, sold_at)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+    ))
+    .bind(player_uuid)
+    .bind(sold.item.registry_key)
+    .bind(sold.quantity as i64)
+    .bind(sold.price)
+    .bind(currency)
+    .bind(current_sec())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Puts items back after a sale could not be completed
+async fn give_back(player: &Player, items: &[SoldItem]) {
+    for sold in items {
+        if !inventory::give(player, sold.item, sold.quantity).await {
+            log::error!(
+                "Failed to give {} {} back to {} after a failed sale.",
+                sold.quantity,
+                sold.item.registry_key,
+                player.gameprofile.id
+            );
+        }
+    }
+}
+
+// Items and amounts a sell command is about, amount limits hand and item
+// sales and defaults to everything the player has
+async fn wanted(
+    player: &Player,
+    target: SellTarget,
+    amount: Option<u32>,
+) -> Result<Vec<(&'static Item, u32)>, ShopError> {
+    let (item, count) = match target {
+        SellTarget::All => {
+            let mut items = Vec::new();
+            for (item, count) in inventory::totals(player).await {
+                if base_value(item).await.is_some() {
+                    items.push((item, count));
+                }
+            }
+            if items.is_empty() {
+                return Err(ShopError::NothingToSell);
+            }
+            return Ok(items);
+        }
+        SellTarget::Hand => inventory::held(player)
+            .await
+            .ok_or(ShopError::NothingToSell)?,
+        SellTarget::Item(item) => (item, inventory::count(player, item).await),
+    };
+
+    let amount = amount.unwrap_or(count);
+    if amount == 0 || amount > count {
+        return Err(ShopError::NotEnoughItems);
+    }
+    if base_value(item).await.is_none() {
+        return Err(ShopError::NotBought);
+    }
+
+    Ok(vec![(item, amount)])
+}
+
+// Sells items for the default currency. The items are priced, taken and
+// the balance credited under the economy lock, so each sale is priced after
+// the ones before it. Nothing is paid unless all items could be taken.
+pub async fn sell(
+    player: &Arc<Player>,
+    target: SellTarget,
+    amount: Option<u32>,
+) -> Result<Sale, ShopError> {
+    let currency = economy::default_currency().await;
+    let player_uuid = player.gameprofile.id.to_string();
+
+    let wanted = wanted(player, target, amount).await?;
+
+    // Listeners see a quote, the final price may be lower if other sales
+    // come first
+    let mut quote = 0.0;
+    for &(item, quantity) in wanted.iter() {
+        quote += price(&currency, item, quantity)
+            .await?
+            .ok_or(ShopError::NotBought)?;
+    }
+    let quote = currency.round(quote);
+    economy::check_amount(quote)?;
+    let settled =
+        economy::before_change(&player_uuid, &currency, BalanceChangeCause::Deposit, quote).await?;
+    economy::check_amount(settled)?;
+    // Listeners may have changed the amount, the prices follow
+    let factor = settled / quote;
+
+    let (items, price, balance) = {
+        let _guard = economy::lock().await;
+
+        let mut items = Vec::new();
+        for (item, quantity) in wanted {
+            let value = price(&currency, item, quantity)
+                .await?
+                .ok_or(ShopError::NotBought)?;
+            items.push(SoldItem {
+                item,
+                quantity,
+                price: currency.round(value * factor),
+            });
+        }
+        let price = currency.round(items.iter().map(|i| i.price).sum());
+        economy::check_amount(price)?;
+
+        // The credit and the sales are staged first and only committed
+        // once the items were taken, the sales are in before the lock is
+        // released so the next sale is priced after them
+        let db = get_db().await;
+        let mut tx = db.pool.begin().await?;
+        let change = economy::apply_in(&mut tx, &player_uuid, &currency.name, price).await?;
+        for sold in items.iter() {
+            record(&mut tx, &player_uuid, &currency.name, sold).await?;
+        }
+
+        let mut taken: Vec<SoldItem> = Vec::new();
+        for sold in items.iter() {
+            let ok = match target {
+                SellTarget::Hand => inventory::take_held(player, sold.item, sold.quantity).await,
+                _ => inventory::take(player, sold.item, sold.quantity).await,
+            };
+            if !ok {
+                give_back(player, &taken).await;
+                return Err(ShopError::NotEnoughItems);
+            }
+            taken.push(sold.clone());
+        }
+
+        if let Err(e) = tx.commit().await {
+            give_back(player, &taken).await;
+            return Err(e.into());
+        }
+        let balance = change.committed().await?;
+
+        (items, price, balance)
+    };
+
+    economy::after_change(
+        &player_uuid,
+        &currency,
+        BalanceChangeCause::Deposit,
+        price,
+        balance,
+    )
+    .await;
+
+    Ok(Sale {
+        items,
+        price,
+        balance,
+    })
+}
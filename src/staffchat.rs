@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use pumpkin::{entity::player::Player, server::Server};
+use pumpkin_util::text::TextComponent;
+
+use crate::{
+    bridge,
+    config::get_config,
+    db::get_db,
+    utils::{current_sec, mark_colour, neutral_colour},
+};
+
+// The built-in channel behind /staffchat
+pub const DEFAULT_CHANNEL: &str = "staff";
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+struct DBStaffMessage {
+    sender: String,
+    message: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub name: String,
+    pub names: Vec<String>,
+    pub prefix: String,
+}
+
+impl Channel {
+    fn default_channel() -> Self {
+        Channel {
+            name: DEFAULT_CHANNEL.to_string(),
+            names: vec!["staffchat".to_string(), "sc".to_string()],
+            prefix: "[SC]".to_string(),
+        }
+    }
+
+    pub fn use_permission(&self) -> String {
+        if self.name == DEFAULT_CHANNEL {
+            "servercore:staffchat.use".to_string()
+        } else {
+            format!("servercore:staffchat.{}.use", self.name)
+        }
+    }
+
+    pub fn receive_permission(&self) -> String {
+        if self.name == DEFAULT_CHANNEL {
+            "servercore:staffchat.receive".to_string()
+        } else {
+            format!("servercore:staffchat.{}.receive", self.name)
+        }
+    }
+
+    fn format_message(&self, sender: &str, message: &str) -> TextComponent {
+        TextComponent::text(format!("{} {}: {}", self.prefix, sender, message))
+            .color_rgb(mark_colour())
+    }
+}
+
+lazy_static! {
+    // User UUID -> Channel the player's chat is redirected to
+    static ref TOGGLED: DashMap<String, String> = DashMap::new();
+}
+
+// The default channel followed by all channels defined in the config
+pub async fn get_channels() -> Vec<Channel> {
+    let mut channels = vec![Channel::default_channel()];
+
+    for c in get_config().await.value.staffchat_channels.iter() {
+        let mut names = vec![c.name.clone()];
+        names.extend(c.aliases.iter().cloned());
+
+        channels.push(Channel {
+            name: c.name.clone(),
+            names,
+            prefix: c.prefix.clone(),
+        });
+    }
+
+    channels
+}
+
+pub async fn get_channel(name: &str) -> Option<Channel> {
+    get_channels().await.into_iter().find(|c| c.name == name)
+}
+
+pub fn get_toggled(player_uuid: &str) -> Option<String> {
+    TOGGLED.get(player_uuid).map(|v| v.clone())
+}
+
+// Toggles chat redirection to a channel, returns whether it is now enabled
+pub fn toggle(player_uuid: &str, channel: &str) -> bool {
+    match TOGGLED.remove(player_uuid) {
+        // Switching from one channel straight to another
+        Some((_, old)) if old != channel => {
+            TOGGLED.insert(player_uuid.to_string(), channel.to_string());
+            true
+        }
+        Some(_) => false,
+        None => {
+            TOGGLED.insert(player_uuid.to_string(), channel.to_string());
+            true
+        }
+    }
+}
+
+pub fn clear(player_uuid: &str) {
+    TOGGLED.remove(player_uuid);
+}
+
+// Sends a message to everyone that can receive the channel, the console,
+// the chat bridge and stores it in the history
+pub async fn send(server: &Server, channel: &Channel, sender: &str, message: &str) {
+    relay(server, channel, sender, message).await;
+    bridge::post_staff(&channel.prefix, sender, message).await;
+}
+
+// Same as send, but without posting to the chat bridge. Used for messages
+// that came in through the bridge.
+pub async fn relay(server: &Server, channel: &Channel, sender: &str, message: &str) {
+    let tc = channel.format_message(sender, message);
+    let perm = channel.receive_permission();
+
+    let players = server.get_all_players().await;
+    for player in players.iter() {
+        if player.has_permission(&perm).await {
+            player.send_system_message(&tc).await;
+        }
+    }
+
+    log::info!("{} {}: {}", channel.prefix, sender, message);
+
+    if let Err(e) = store_message(&channel.name, sender, message).await {
+        log::error!("Failed to store staffchat message: {}", e);
+    }
+}
+
+async fn store_message(
+    channel: &str,
+    sender: &str,
+    message: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+    let history_size = get_config().await.value.staffchat_history_size;
+
+    sqlx::query(
+        db.sql("INSERT INTO staffchat_messages (channel, sender, message, created_at) VALUES ($1, $2, $3, $4)"),
+    )
+    .bind(channel)
+    .bind(sender)
+    .bind(message)
+    .bind(current_sec())
+    .execute(&db.pool)
+    .await?;
+
+    // Only keep the last N messages of every channel around. MySQL can not
+    // use LIMIT in a subquery of the table being deleted from, so look up
+    // the newest message that falls outside the history first.
+    let cutoff = sqlx::query_scalar::<_, i64>(db.sql(
+        "SELECT id FROM staffchat_messages WHERE channel = $1 ORDER BY id DESC LIMIT 1 OFFSET $2",
+    ))
+    .bind(channel)
+    .bind(history_size)
+    .fetch_optional(&db.pool)
+    .await?;
+
+    if let Some(cutoff) = cutoff {
+        sqlx::query(db.sql("DELETE FROM staffchat_messages WHERE channel = $1 AND id <= $2"))
+            .bind(channel)
+            .bind(cutoff)
+            .execute(&db.pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Sends the stored history of every channel the player receives
+pub async fn replay_history(
+    player: &Arc<Player>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+    let history_size = get_config().await.value.staffchat_history_size;
+
+    for channel in get_channels().await.iter() {
+        if !player.has_permission(&channel.receive_permission()).await {
+            continue;
+        }
+
+        let mut messages = sqlx::query_as::<_, DBStaffMessage>(
+            db.sql("SELECT sender, message FROM staffchat_messages WHERE channel = $1 ORDER BY id DESC LIMIT $2"),
+        )
+        .bind(&channel.name)
+        .bind(history_size)
+        .fetch_all(&db.pool)
+        .await?;
+
+        if messages.is_empty() {
+            continue;
+        }
+
+        // Oldest first
+        messages.reverse();
+
+        player
+            .send_system_message(
+                &TextComponent::text(format!("Recent {} messages:", channel.prefix))
+                    .color_rgb(neutral_colour()),
+            )
+            .await;
+
+        for msg in messages.iter() {
+            player
+                .send_system_message(&channel.format_message(&msg.sender, &msg.message))
+                .await;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{
+    any::{AnyArguments, AnyRow},
+    query::Query,
+    Any, Column, Row,
+};
+
+use crate::{
+    cache::sync_all,
+    config::get_data_folder,
+    db::{get_db, SCHEMA_VERSION},
+    economy,
+    utils::current_sec,
+};
+
+const EXPORT_DIR: &str = "exports";
+const FORMAT: &str = "servercore";
+// Bumped when the layout of export files changes
+pub const EXPORT_VERSION: i64 = 1;
+
+// Exported tables, players first so references to them can be remapped.
+// Each table lists the columns identifying a row when merging.
+const TABLES: [(&str, &[&str]); 20] = [
+    ("players", &["uuid"]),
+    ("balances", &["player_id", "currency"]),
+    ("homes", &["user_id", "name"]),
+    ("warps", &["name"]),
+    ("staffchat_messages", &["channel", "sender", "created_at"]),
+    ("punishments", &["uuid", "kind", "created_at"]),
+    ("warnings", &["player_id", "created_at"]),
+    ("player_sessions", &["uuid", "ip", "joined_at"]),
+    ("ip_bans", &["ip", "created_at"]),
+    ("name_history", &["uuid", "name"]),
+    ("bank_accounts", &["name"]),
+    ("bank_members", &["account", "uuid"]),
+    (
+        "bank_transactions",
+        &["account", "uuid", "kind", "created_at"],
+    ),
+    ("payout_schedule", &["kind"]),
+    ("payouts", &["kind", "uuid", "paid_at"]),
+    ("shop_stock", &["category", "item"]),
+    ("shop_trades", &["uuid", "category", "item", "created_at"]),
+    ("item_sales", &["uuid", "item", "sold_at"]),
+    ("auction_listings", &["seller", "item", "created_at"]),
+    (
+        "auction_mailbox",
+        &["uuid", "reason", "item", "amount", "created_at"],
+    ),
+];
+
+// Columns holding the id of a row in players
+const PLAYER_REFERENCES: [&str; 2] = ["user_id", "player_id"];
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Export {
+    pub format: String,
+    pub version: i64,
+    pub schema_version: i64,
+    pub exported_at: i64,
+    pub tables: BTreeMap<String, Vec<Map<String, Value>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportMode {
+    // Reports what would be imported without writing anything
+    DryRun,
+    // Adds rows that are not in the database yet, existing rows are kept
+    Merge,
+    // Empties the tables before importing
+    Replace,
+}
+
+impl ImportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::DryRun => "dry-run",
+            ImportMode::Merge => "merge",
+            ImportMode::Replace => "replace",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TableReport {
+    pub table: String,
+    pub inserted: usize,
+    pub skipped: usize,
+    // Rows referencing a player that is not in the import
+    pub orphaned: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub tables: Vec<TableReport>,
+    // Tables and columns in the file that this database does not have
+    pub ignored: Vec<String>,
+}
+
+// Location of an export file, only plain file names inside the exports
+// folder are allowed
+fn export_path(file: &str) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    if file.is_empty() || file.starts_with('.') || file.contains(['/', '\\']) {
+        return Err(format!("{} is not a valid file name.", file).into());
+    }
+
+    let file = if file.ends_with(".json") {
+        file.to_string()
+    } else {
+        format!("{}.json", file)
+    };
+
+    Ok(get_data_folder().join(EXPORT_DIR).join(file))
+}
+
+// Reads a column without knowing its type up front
+fn decode(row: &AnyRow, index: usize) -> Value {
+    if let Ok(v) = row.try_get::<Option<i64>, _>(index) {
+        return v.map_or(Value::Null, Value::from);
+    }
+    if let Ok(v) = row.try_get::<Option<f64>, _>(index) {
+        return v.map_or(Value::Null, Value::from);
+    }
+
+    row.try_get::<Option<String>, _>(index)
+        .ok()
+        .flatten()
+        .map_or(Value::Null, Value::from)
+}
+
+fn bind_value<'q>(
+    query: Query<'q, Any, AnyArguments<'q>>,
+    value: &Value,
+) -> Query<'q, Any, AnyArguments<'q>> {
+    match value {
+        Value::Bool(b) => query.bind(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => query.bind(i),
+            None => query.bind(n.as_f64().unwrap_or(0.0)),
+        },
+        Value::String(s) => query.bind(s.clone()),
+        other => query.bind(other.to_string()),
+    }
+}
+
+// Writes all plugin tables to a file in the exports folder, returns the
+// path and the amount of rows written
+pub async fn export(
+    file: &str,
+) -> Result<(PathBuf, usize), Box<dyn std::error::Error + Send + Sync>> {
+    let path = export_path(file)?;
+
+    // Online players would otherwise be exported as of their last sync
+    sync_all().await;
+
+    let db = get_db().await;
+    let mut tables = BTreeMap::new();
+    let mut count = 0;
+    for (table, _) in TABLES {
+        let rows = sqlx::query(&format!("SELECT * FROM {} ORDER BY id", table))
+            .fetch_all(&db.pool)
+            .await?;
+
+        let rows: Vec<Map<String, Value>> = rows
+            .iter()
+            .map(|row| {
+                row.columns()
+                    .iter()
+                    .map(|c| (c.name().to_string(), decode(row, c.ordinal())))
+                    .collect()
+            })
+            .collect();
+
+        count += rows.len();
+        tables.insert(table.to_string(), rows);
+    }
+
+    let export = Export {
+        format: FORMAT.to_string(),
+        version: EXPORT_VERSION,
+        schema_version: SCHEMA_VERSION,
+        exported_at: current_sec(),
+        tables,
+    };
+
+    tokio::fs::create_dir_all(get_data_folder().join(EXPORT_DIR)).await?;
+    tokio::fs::write(&path, serde_json::to_vec_pretty(&export)?).await?;
+
+    log::info!("Exported {} rows to {}.", count, path.display());
+    Ok((path, count))
+}
+
+// Id of a row matching the key columns of an imported row
+async fn find_existing(
+    conn: &mut sqlx::AnyConnection,
+    table: &str,
+    keys: &[&str],
+    values: &[(String, Value)],
+) -> Result<Option<i64>, Box<dyn std::error::Error + Send + Sync>> {
+    let db = get_db().await;
+
+    let mut conditions = Vec::new();
+    let mut bound = Vec::new();
+    for key in keys {
+        match values.iter().find(|(c, _)| c == key) {
+            Some((_, v)) => {
+                bound.push(v);
+                conditions.push(format!("{} = ${}", key, bound.len()));
+            }
+            None => conditions.push(format!("{} IS NULL", key)),
+        }
+    }
+
+    let sql = db.rewrite(&format!(
+        "SELECT id FROM {} WHERE {} LIMIT 1",
+        table,
+        conditions.join(" AND ")
+    ));
+    let mut query = sqlx::query(&sql);
+    for v in bound {
+        query = bind_value(query, v);
+    }
+
+    let id = query
+        .map(|row: AnyRow| row.get::<i64, _>(0))
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(id)
+}
+
+// Loads an export file. Rows get new ids, references to players are
+// remapped to the ids the players have in this database.
+pub async fn import(
+    file: &str,
+    mode: ImportMode,
+) -> Result<ImportReport, Box<dyn std::error::Error + Send + Sync>> {
+    let path = export_path(file)?;
+    if !path.exists() {
+        return Err(format!("{} does not exist.", path.display()).into());
+    }
+
+    let export: Export = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+    if export.format != FORMAT {
+        return Err(format!("{} is not a servercore export.", path.display()).into());
+    }
+    if export.version > EXPORT_VERSION || export.schema_version > SCHEMA_VERSION {
+        return Err("The export was made by a newer version of the plugin.".into());
+    }
+
+    let mut report = ImportReport::default();
+    for table in export.tables.keys() {
+        if !TABLES.iter().any(|(t, _)| t == table) {
+            report.ignored.push(table.clone());
+        }
+    }
+
+    let db = get_db().await;
+    let mut columns = HashMap::new();
+    for (table, _) in TABLES {
+        columns.insert(table, db.columns(table).await?);
+    }
+
+    // Dry runs go through the same steps and are rolled back at the end
+    let mut tx = db.pool.begin().await?;
+
+    if mode == ImportMode::Replace {
+        for (table, _) in TABLES.iter().rev() {
+            sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    // Player id in the file -> player id in this database
+    let mut player_ids: HashMap<i64, i64> = HashMap::new();
+
+    for (table, keys) in TABLES {
+        let Some(rows) = export.tables.get(table) else {
+            continue;
+        };
+
+        let columns = &columns[table];
+        let mut table_report = TableReport {
+            table: table.to_string(),
+            ..Default::default()
+        };
+
+        for row in rows {
+            let mut values: Vec<(String, Value)> = Vec::new();
+            let mut orphaned = false;
+            for (column, value) in row {
+                // Nulls are left to the column default
+                if column == "id" || value.is_null() {
+                    continue;
+                }
+
+                if !columns.contains(column) {
+                    let ignored = format!("{}.{}", table, column);
+                    if !report.ignored.contains(&ignored) {
+                        report.ignored.push(ignored);
+                    }
+                    continue;
+                }
+
+                if PLAYER_REFERENCES.contains(&column.as_str()) {
+                    match value.as_i64().and_then(|id| player_ids.get(&id)) {
+                        Some(id) => values.push((column.clone(), Value::from(*id))),
+                        None => orphaned = true,
+                    }
+                    continue;
+                }
+
+                values.push((column.clone(), value.clone()));
+            }
+
+            if orphaned || values.is_empty() {
+                table_report.orphaned += 1;
+                continue;
+            }
+
+            let old_id = row.get("id").and_then(|v| v.as_i64());
+
+            // Rows already in the database are kept as they are
+            if let Some(id) = find_existing(&mut tx, table, keys, &values).await? {
+                if let (true, Some(old_id)) = (table == "players", old_id) {
+                    player_ids.insert(old_id, id);
+                }
+                table_report.skipped += 1;
+                continue;
+            }
+
+            let names: Vec<&str> = values.iter().map(|(c, _)| c.as_str()).collect();
+            let placeholders: Vec<String> = (1..=values.len()).map(|i| format!("${}", i)).collect();
+            let sql = db.rewrite(&format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                names.join(", "),
+                placeholders.join(", ")
+            ));
+            let mut query = sqlx::query(&sql);
+            for (_, v) in values.iter() {
+                query = bind_value(query, v);
+            }
+            query.execute(&mut *tx).await?;
+
+            if let (true, Some(old_id)) = (table == "players", old_id) {
+                if let Some(id) = find_existing(&mut tx, table, keys, &values).await? {
+                    player_ids.insert(old_id, id);
+                }
+            }
+            table_report.inserted += 1;
+        }
+
+        report.tables.push(table_report);
+    }
+
+    // Exports from before currencies keep balances in the players table
+    if !export.tables.contains_key("balances") {
+        let currency = economy::default_currency().await.name;
+        let mut table_report = TableReport {
+            table: "balances".to_string(),
+            ..Default::default()
+        };
+
+        for row in export.tables.get("players").into_iter().flatten() {
+            let (Some(old_id), Some(balance)) = (
+                row.get("id").and_then(|v| v.as_i64()),
+                row.get("balance").and_then(|v| v.as_f64()),
+            ) else {
+                continue;
+            };
+            let Some(id) = player_ids.get(&old_id) else {
+                table_report.orphaned += 1;
+                continue;
+            };
+
+            let values = vec![
+                ("player_id".to_string(), Value::from(*id)),
+                ("currency".to_string(), Value::from(currency.clone())),
+            ];
+            if find_existing(&mut tx, "balances", &["player_id", "currency"], &values)
+                .await?
+                .is_some()
+            {
+                table_report.skipped += 1;
+                continue;
+            }
+
+            sqlx::query(
+                db.sql("INSERT INTO balances (player_id, currency, balance) VALUES ($1, $2, $3)"),
+            )
+            .bind(*id)
+            .bind(&currency)
+            .bind(balance)
+            .execute(&mut *tx)
+            .await?;
+            table_report.inserted += 1;
+        }
+
+        report.tables.push(table_report);
+    }
+
+    if mode == ImportMode::DryRun {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+        log::info!("Imported {} ({}).", path.display(), mode.as_str());
+    }
+
+    Ok(report)
+}
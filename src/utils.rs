@@ -1,3 +1,4 @@
+use pumpkin::command::CommandSender;
 use pumpkin_util::text::{color::RGBColor, TextComponent};
 
 pub fn error_colour() -> RGBColor {
@@ -26,3 +27,105 @@ pub fn current_sec() -> i64 {
         .unwrap()
         .as_secs() as i64
 }
+
+pub fn sender_name(sender: &CommandSender) -> String {
+    match sender.as_player() {
+        Some(player) => player.gameprofile.name.clone(),
+        None => "Server".to_string(),
+    }
+}
+
+// Parses durations like 30m, 1h30m or 7d12h into seconds
+pub fn parse_duration(s: &str) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut num = String::new();
+
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return None,
+        };
+
+        let n = num.parse::<i64>().ok()?;
+        total = total.checked_add(n.checked_mul(unit)?)?;
+        num.clear();
+    }
+
+    // Trailing number without a unit or nothing at all
+    if !num.is_empty() || total <= 0 {
+        return None;
+    }
+
+    Some(total)
+}
+
+// Formats seconds in the same compact form parse_duration accepts
+pub fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    let parts = [
+        (secs / 86400, "d"),
+        ((secs % 86400) / 3600, "h"),
+        ((secs % 3600) / 60, "m"),
+        (secs % 60, "s"),
+    ];
+
+    let s = parts
+        .iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, u)| format!("{}{}", n, u))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if s.is_empty() {
+        "0s".to_string()
+    } else {
+        s
+    }
+}
+
+pub fn failed_message() -> TextComponent {
+    TextComponent::text("Something went wrong, check the server log.").color_rgb(error_colour())
+}
+
+pub fn player_not_found_message(name: &str) -> TextComponent {
+    TextComponent::text(format!("Player {} not found.", name)).color_rgb(error_colour())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30s"), Some(30));
+        assert_eq!(parse_duration("30m"), Some(1800));
+        assert_eq!(parse_duration("1h30m"), Some(5400));
+        assert_eq!(parse_duration("7d12h"), Some(648000));
+        assert_eq!(parse_duration("2w"), Some(1209600));
+        // Units may repeat and add up
+        assert_eq!(parse_duration("1m1m"), Some(120));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("30"), None);
+        assert_eq!(parse_duration("1h30"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("-5m"), None);
+        assert_eq!(parse_duration("5 m"), None);
+        assert_eq!(parse_duration("1y"), None);
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+        assert_eq!(parse_duration("9999999999999999w"), None);
+    }
+}